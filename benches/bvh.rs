@@ -0,0 +1,74 @@
+//! Compares the flat SAH `Bvh` against the original `BVHNode` tree, building both over the
+//! world of a built-in scene and tracing the same batch of rays through them.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lib::builtin;
+use lib::bvh::Bvh;
+use lib::bvh_node::BVHNode;
+use lib::hittable::Hittable;
+use lib::hittable_list::HittableList;
+use lib::ray::Ray;
+use lib::sampler::Sampler;
+use lib::vec::Vec3;
+use rand::Rng;
+
+const RAYS: usize = 10_000;
+
+/// Half camera rays, half random rays from points inside the scene's bounds.
+fn rays(scene: &lib::scene::Scene, sampler: &mut Sampler) -> Vec<Ray> {
+    let bounds = scene.world.bounding_box(0.0, 1.0).unwrap();
+    (0..RAYS)
+        .map(|i| {
+            if i % 2 == 0 {
+                scene
+                    .camera
+                    .get_ray(sampler.gen::<f64>(), sampler.gen::<f64>(), sampler)
+            } else {
+                let t = Vec3::random(sampler);
+                let origin = bounds.min + (bounds.max - bounds.min) * t;
+                Ray::new_at_time(origin, Vec3::random_unit_vector(sampler), sampler.gen())
+            }
+        })
+        .collect()
+}
+
+fn trace(world: &dyn Hittable, rays: &[Ray]) -> usize {
+    rays.iter()
+        .filter(|ray| world.hit(ray, 0.001, f64::INFINITY).is_some())
+        .count()
+}
+
+fn bench_scene(c: &mut Criterion, name: &str) {
+    let mut sampler = Sampler::new(1);
+    let scene = builtin::load(name, &mut sampler).unwrap();
+    let world: &HittableList = &scene.world;
+    let rays = rays(&scene, &mut sampler);
+
+    let mut build = c.benchmark_group(format!("build/{}", name));
+    build.bench_function(BenchmarkId::new("BVHNode", world.objects.len()), |b| {
+        b.iter(|| BVHNode::new_hittablelist(world, 0.0, 1.0, &mut Sampler::new(2)))
+    });
+    build.bench_function(BenchmarkId::new("Bvh", world.objects.len()), |b| {
+        b.iter(|| Bvh::from_list(world, 0.0, 1.0))
+    });
+    build.finish();
+
+    let old = BVHNode::new_hittablelist(world, 0.0, 1.0, &mut Sampler::new(2));
+    let new = Bvh::from_list(world, 0.0, 1.0);
+    let mut traverse = c.benchmark_group(format!("traverse/{}", name));
+    traverse.bench_function(BenchmarkId::new("BVHNode", RAYS), |b| {
+        b.iter(|| trace(&old, &rays))
+    });
+    traverse.bench_function(BenchmarkId::new("Bvh", RAYS), |b| {
+        b.iter(|| trace(&new, &rays))
+    });
+    traverse.finish();
+}
+
+fn bvh(c: &mut Criterion) {
+    bench_scene(c, "random_scene");
+    bench_scene(c, "final_scene");
+}
+
+criterion_group!(benches, bvh);
+criterion_main!(benches);
@@ -0,0 +1,297 @@
+use lib::error::Error;
+use lib::image_output::ImageFormat;
+use lib::job::{Region, TileOrder, Tiling};
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage:
+    raytracer render <SCENE> [OPTIONS]
+    raytracer list-scenes
+    raytracer info <SCENE>
+    raytracer help
+
+SCENE is the name of a built-in scene or the path to a scene file.
+
+Render options:
+    -w, --width <PIXELS>         image width
+    -h, --height <PIXELS>        image height; keeps the scene's aspect ratio if omitted
+    -s, --spp <SAMPLES>          samples per pixel, the most any pixel takes with --adaptive
+        --adaptive <ERROR>       stop sampling a pixel once the standard error of its mean
+                                 falls below this fraction of it, e.g. 0.02
+        --min-spp <SAMPLES>      samples every pixel takes with --adaptive, defaults to 16
+        --sample-map <FILE>      also write an image of the samples taken in each pixel
+        --pass-spp <SAMPLES>     samples added to every pixel per pass, defaults to 16
+        --checkpoint <FILE>      save the samples so far to this file, and the image so
+                                 far to the output, between passes
+        --checkpoint-interval <SECONDS>
+                                 time between checkpoints, defaults to 300
+        --resume <FILE>          continue the render saved in a checkpoint file, adding
+                                 samples up to --spp; checkpoints go back to the same file
+                                 unless --checkpoint is given
+    -d, --depth <BOUNCES>        maximum ray depth
+    -t, --threads <COUNT>        number of render threads, defaults to one per core
+        --seed <SEED>            seed for scene construction and rendering; the same seed
+                                 gives the same image, random if omitted
+    -o, --output <FILE>          output file, defaults to out.ppm
+    -f, --format <FORMAT>        output format: ppm, ppm-ascii, png, pfm or exr;
+                                 defaults to the output file's extension
+        --crop <X,Y,W,H>         render only this region, measured from the top left
+        --tile-size <PIXELS>     width and height of the tiles rendered by each job,
+                                 defaults to 32
+        --tile-order <ORDER>     order the tiles are rendered in: scanline, spiral (from
+                                 the centre), hilbert or costliest (most samples left
+                                 first), defaults to scanline
+        --spectral               trace one wavelength per sample, for dispersion";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Render(Box<RenderOptions>),
+    ListScenes,
+    Info { scene: String },
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RenderOptions {
+    pub scene: String,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples_per_pixel: Option<i32>,
+    pub max_depth: Option<i32>,
+    pub threads: Option<usize>,
+    pub seed: Option<u64>,
+    pub output: String,
+    pub format: ImageFormat,
+    pub crop: Option<Region>,
+    pub tiling: Tiling,
+    pub spectral: bool,
+    pub adaptive: Option<f64>,
+    pub min_samples: Option<i32>,
+    pub sample_map: Option<String>,
+    pub pass_samples: Option<i32>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Duration,
+    pub resume: Option<String>,
+}
+
+impl RenderOptions {
+    fn new(scene: String) -> Self {
+        RenderOptions {
+            scene,
+            width: None,
+            height: None,
+            samples_per_pixel: None,
+            max_depth: None,
+            threads: None,
+            seed: None,
+            output: "out.ppm".to_owned(),
+            format: ImageFormat::Ppm,
+            crop: None,
+            tiling: Tiling::default(),
+            spectral: false,
+            adaptive: None,
+            min_samples: None,
+            sample_map: None,
+            pass_samples: None,
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(300),
+            resume: None,
+        }
+    }
+}
+
+/// Parses the command line, without the program name.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, Error> {
+    let mut args = args.into_iter();
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Err(Error::argument("No command given")),
+    };
+    match command.as_str() {
+        "render" => parse_render(args).map(|options| Command::Render(Box::new(options))),
+        "list-scenes" => {
+            no_more_arguments(args)?;
+            Ok(Command::ListScenes)
+        }
+        "info" => {
+            let scene = args
+                .next()
+                .ok_or_else(|| Error::argument("'info' needs a scene"))?;
+            no_more_arguments(args)?;
+            Ok(Command::Info { scene })
+        }
+        "help" | "--help" => Ok(Command::Help),
+        _ => Err(Error::argument(&format!("Unknown command '{}'", command))),
+    }
+}
+
+fn no_more_arguments<I: Iterator<Item = String>>(mut args: I) -> Result<(), Error> {
+    match args.next() {
+        Some(arg) => Err(Error::argument(&format!("Unexpected argument '{}'", arg))),
+        None => Ok(()),
+    }
+}
+
+fn parse_render<I: Iterator<Item = String>>(mut args: I) -> Result<RenderOptions, Error> {
+    let mut scene = None;
+    let mut options = RenderOptions::new(String::new());
+    let mut format = None;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if scene.is_some() {
+                return Err(Error::argument(&format!("Unexpected argument '{}'", arg)));
+            }
+            scene = Some(arg);
+            continue;
+        }
+        if arg == "--spectral" {
+            options.spectral = true;
+            continue;
+        }
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| Error::argument(&format!("'{}' needs a value", arg)))
+        };
+        match arg.as_str() {
+            "-w" | "--width" => options.width = Some(positive(&arg, &value()?)?),
+            "-h" | "--height" => options.height = Some(positive(&arg, &value()?)?),
+            "-s" | "--spp" => options.samples_per_pixel = Some(positive(&arg, &value()?)? as i32),
+            "-d" | "--depth" => options.max_depth = Some(positive(&arg, &value()?)? as i32),
+            "-t" | "--threads" => options.threads = Some(positive(&arg, &value()?)?),
+            "--seed" => options.seed = Some(number(&arg, &value()?)?),
+            "-o" | "--output" => options.output = value()?,
+            "-f" | "--format" => format = Some(ImageFormat::from_name(&value()?)?),
+            "--crop" => options.crop = Some(region(&value()?)?),
+            "--tile-size" => {
+                let size = positive(&arg, &value()?)?;
+                options.tiling = Tiling::square(size).order(options.tiling.order)
+            }
+            "--tile-order" => options.tiling.order = TileOrder::from_name(&value()?)?,
+            "--adaptive" => options.adaptive = Some(fraction(&arg, &value()?)?),
+            "--min-spp" => options.min_samples = Some(positive(&arg, &value()?)? as i32),
+            "--sample-map" => options.sample_map = Some(value()?),
+            "--pass-spp" => options.pass_samples = Some(positive(&arg, &value()?)? as i32),
+            "--checkpoint" => options.checkpoint = Some(value()?),
+            "--checkpoint-interval" => {
+                options.checkpoint_interval = Duration::from_secs(number(&arg, &value()?)?)
+            }
+            "--resume" => options.resume = Some(value()?),
+            _ => return Err(Error::argument(&format!("Unknown option '{}'", arg))),
+        }
+    }
+
+    options.scene = scene.ok_or_else(|| Error::argument("'render' needs a scene"))?;
+    options.format = match format {
+        Some(format) => format,
+        None => ImageFormat::from_path(&options.output)?,
+    };
+    Ok(options)
+}
+
+fn number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, Error> {
+    value.parse::<T>().map_err(|_| {
+        Error::argument(&format!(
+            "Invalid value '{}' for '{}', expected a whole number",
+            value, option
+        ))
+    })
+}
+
+fn positive(option: &str, value: &str) -> Result<usize, Error> {
+    let n = number::<usize>(option, value)?;
+    if n == 0 || n > i32::MAX as usize {
+        return Err(Error::argument(&format!(
+            "'{}' must be between 1 and {}",
+            option,
+            i32::MAX
+        )));
+    }
+    Ok(n)
+}
+
+fn fraction(option: &str, value: &str) -> Result<f64, Error> {
+    match value.parse::<f64>() {
+        Ok(x) if x > 0.0 && x.is_finite() => Ok(x),
+        _ => Err(Error::argument(&format!(
+            "Invalid value '{}' for '{}', expected a positive number",
+            value, option
+        ))),
+    }
+}
+
+fn region(value: &str) -> Result<Region, Error> {
+    let parts = value
+        .split(',')
+        .map(|p| number::<usize>("--crop", p.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match parts[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok(Region::new(x, y, width, height)),
+        _ => Err(Error::argument(&format!(
+            "Invalid crop '{}', expected X,Y,WIDTH,HEIGHT with a non-empty size",
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::error::Kind;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|s| s.to_owned()).collect()
+    }
+
+    #[test]
+    fn parse_render_options() {
+        let command = parse_args(args(
+            "render scenes/cornell_box.scene -w 300 --spp 16 -d 8 --threads 2 --seed 7 -o a.ppm --crop 10,20,30,40 --tile-size 16 --tile-order hilbert --spectral --adaptive 0.05 --min-spp 8 --sample-map spp.png --pass-spp 4 --resume a.ckpt --checkpoint-interval 30",
+        ))
+        .unwrap();
+        let mut expected = RenderOptions::new("scenes/cornell_box.scene".to_owned());
+        expected.width = Some(300);
+        expected.samples_per_pixel = Some(16);
+        expected.max_depth = Some(8);
+        expected.threads = Some(2);
+        expected.seed = Some(7);
+        expected.output = "a.ppm".to_owned();
+        expected.crop = Some(Region::new(10, 20, 30, 40));
+        expected.tiling = Tiling::square(16).order(TileOrder::Hilbert);
+        expected.spectral = true;
+        expected.adaptive = Some(0.05);
+        expected.min_samples = Some(8);
+        expected.sample_map = Some("spp.png".to_owned());
+        expected.pass_samples = Some(4);
+        expected.resume = Some("a.ckpt".to_owned());
+        expected.checkpoint_interval = Duration::from_secs(30);
+        assert_eq!(command, Command::Render(Box::new(expected)));
+    }
+
+    #[test]
+    fn invalid_input_is_an_argument_error() {
+        for line in [
+            "",
+            "draw",
+            "render",
+            "render a.scene --spp",
+            "render a.scene --spp many",
+            "render a.scene --width 0",
+            "render a.scene --crop 1,2,3",
+            "render a.scene --adaptive 0",
+            "render a.scene --tile-order random",
+            "render a.scene -o out.xyz",
+            "render a.scene --bogus",
+            "info",
+            "list-scenes extra",
+        ] {
+            match parse_args(args(line)) {
+                Err(Error {
+                    kind: Kind::ArgumentError,
+                    ..
+                }) => {}
+                _ => panic!("'{}' should be rejected", line),
+            }
+        }
+    }
+}
@@ -32,24 +32,46 @@ impl AABB {
     }
 
     // improved version
-    pub fn hit2(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
-        let vec3_values: Vec<fn(&Vec3) -> f64> =
-            vec![|v: &Vec3| v.x, |v: &Vec3| v.y, |v: &Vec3| v.z];
-        for v in vec3_values {
-            let inv_d = 1.0 / v(&ray.direction);
-            let mut t0 = (v(&self.min) - v(&ray.origin)) * inv_d;
-            let mut t1 = (v(&self.max) - v(&ray.origin)) * inv_d;
+    pub fn hit2(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let (t_min, t_max) = self.slabs(ray, t_min, t_max);
+        t_max > t_min
+    }
+
+    /// The part of `t_min` to `t_max` where `ray` is inside the box, if any.
+    pub fn clip(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (t_min, t_max) = self.slabs(ray, t_min, t_max);
+        (t_max >= t_min).then_some((t_min, t_max))
+    }
+
+    /// `t_min` to `t_max` narrowed to where `ray` is between the faces of the box on every
+    /// axis. The end comes before the start if the ray misses.
+    fn slabs(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> (f64, f64) {
+        let axes = [
+            (self.min.x, self.max.x, ray.origin.x, ray.direction.x),
+            (self.min.y, self.max.y, ray.origin.y, ray.direction.y),
+            (self.min.z, self.max.z, ray.origin.z, ray.direction.z),
+        ];
+        for (min, max, origin, direction) in axes {
+            let inv_d = 1.0 / direction;
+            let mut t0 = (min - origin) * inv_d;
+            let mut t1 = (max - origin) * inv_d;
             if inv_d < 0.0 {
                 swap(&mut t0, &mut t1);
             }
             t_min = t0.max(t_min);
             t_max = t1.min(t_max);
-            if t_max <= t_min {
-                return false;
+            if t_max < t_min {
+                break;
             }
         }
-        true
+        (t_min, t_max)
     }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
     pub fn surrounding_box(box0: &AABB, box1: &AABB) -> AABB {
         let small = Vec3::new(
             box0.min.x.min(box1.min.x),
@@ -73,3 +95,27 @@ impl fmt::Debug for AABB {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slabs_clip_rays_to_the_box() {
+        let cube = AABB::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let along_z = Ray::new_at_time(Vec3::new(0.5, 0.0, -5.0), Vec3::new(0.0, 0.0, 2.0), 0.0);
+        assert!(cube.hit2(&along_z, 0.0, f64::INFINITY));
+        assert_eq!(cube.clip(&along_z, 0.0, f64::INFINITY), Some((2.0, 3.0)));
+        assert_eq!(cube.clip(&along_z, 2.5, 10.0), Some((2.5, 3.0)));
+        assert!(!cube.hit2(&along_z, 0.0, 1.5));
+
+        let beside = Ray::new_at_time(Vec3::new(1.5, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(!cube.hit2(&beside, 0.0, f64::INFINITY));
+        assert_eq!(cube.clip(&beside, 0.0, f64::INFINITY), None);
+
+        // a box with no depth is touched at a single distance
+        let flat = AABB::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(flat.clip(&along_z, 0.0, f64::INFINITY), Some((2.5, 2.5)));
+        assert!(!flat.hit2(&along_z, 0.0, f64::INFINITY));
+    }
+}
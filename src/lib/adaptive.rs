@@ -0,0 +1,149 @@
+//! Adaptive sampling: a pixel stops taking samples once its estimate is precise enough.
+//!
+//! Every pixel keeps a running mean and variance of the luminance of its samples (Welford's
+//! method). After a minimum number of samples, it stops as soon as the standard error of the
+//! mean falls below a fraction of the mean. Flat regions such as an empty background finish
+//! early and leave the budget to noisy ones.
+
+use crate::image_output::Framebuffer;
+use crate::spectrum;
+use crate::vec::Vec3;
+
+/// Luminance added to the mean before dividing by it, so pixels that are nearly black
+/// still converge instead of chasing a relative error of zero.
+const DARK: f64 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    /// Relative standard error of a pixel's mean luminance at which it stops
+    pub threshold: f64,
+    /// Samples every pixel takes before its error is trusted
+    pub min_samples: i32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(threshold: f64) -> Self {
+        AdaptiveSampling {
+            threshold,
+            min_samples: 16,
+        }
+    }
+
+    /// Whether a pixel needs no more samples.
+    pub fn is_done(&self, estimate: &PixelEstimate) -> bool {
+        estimate.count() >= self.min_samples.max(2) as u32
+            && estimate.relative_error() <= self.threshold
+    }
+}
+
+/// Running statistics of the samples of one pixel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PixelEstimate {
+    sum: Vec3,
+    count: u32,
+    /// Mean luminance
+    mean: f64,
+    /// Sum of squared differences of the luminance from the mean
+    m2: f64,
+}
+
+impl PixelEstimate {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, color: Vec3) {
+        self.sum += color;
+        self.count += 1;
+        let luminance = spectrum::luminance(&color);
+        let delta = luminance - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (luminance - self.mean);
+    }
+
+    /// The sum of the samples, their count, and the mean and squared deviations of their
+    /// luminance, for storing the estimate.
+    pub(crate) fn parts(&self) -> (Vec3, u32, f64, f64) {
+        (self.sum, self.count, self.mean, self.m2)
+    }
+
+    pub(crate) fn from_parts(sum: Vec3, count: u32, mean: f64, m2: f64) -> Self {
+        PixelEstimate {
+            sum,
+            count,
+            mean,
+            m2,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// The pixel color, the mean of the samples.
+    pub fn mean(&self) -> Vec3 {
+        if self.count == 0 {
+            Vec3::new(0.0, 0.0, 0.0)
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    /// Standard error of the mean luminance relative to the mean, infinite with fewer than
+    /// two samples.
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.count as f64;
+        let variance = self.m2 / (n - 1.0);
+        (variance / n).sqrt() / (self.mean.abs() + DARK)
+    }
+}
+
+/// Grey image of the samples taken in each pixel, `counts` row by row from the top, as a
+/// fraction of `max_samples`.
+pub fn sample_map(counts: &[u32], width: usize, height: usize, max_samples: i32) -> Framebuffer {
+    let mut image = Framebuffer::new(width, height);
+    for (pixel, count) in image.pixels_mut().iter_mut().zip(counts) {
+        let value = *count as f64 / max_samples.max(1) as f64;
+        *pixel = Vec3::new(value, value, value);
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_tracks_mean_and_variance() {
+        let mut estimate = PixelEstimate::new();
+        assert_eq!(estimate.relative_error(), f64::INFINITY);
+        for value in [1.0, 3.0, 2.0, 6.0] {
+            estimate.add(Vec3::new(value, value, value));
+        }
+        assert_eq!(estimate.count(), 4);
+        assert_eq!(estimate.mean(), Vec3::new(3.0, 3.0, 3.0));
+        // sample variance 14 / 3, standard error sqrt(14 / 12)
+        let expected = (14.0f64 / 12.0).sqrt() / (3.0 + DARK);
+        assert!((estimate.relative_error() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn constant_pixels_stop_at_the_minimum() {
+        let adaptive = AdaptiveSampling {
+            threshold: 0.01,
+            min_samples: 8,
+        };
+        let mut black = PixelEstimate::new();
+        let mut noisy = PixelEstimate::new();
+        for i in 0..8 {
+            assert!(!adaptive.is_done(&black));
+            black.add(Vec3::new(0.0, 0.0, 0.0));
+            noisy.add(Vec3::new(1.0, 1.0, 1.0) * (i % 2) as f64);
+        }
+        assert!(adaptive.is_done(&black));
+        assert!(!adaptive.is_done(&noisy));
+    }
+}
@@ -0,0 +1,331 @@
+//! Transforms that change over time, for motion blur of whole objects.
+//!
+//! An animation is a list of keyframes, each placing the object at one time by scaling,
+//! turning and then moving it. In between, the offset and scale change at a steady rate
+//! and the rotation turns at a steady speed.
+
+use crate::aabb::AABB;
+use crate::transform::{Matrix, Transform};
+use crate::vec::Vec3;
+
+/// Largest turn between the transforms sampled for a swept bounding box, in degrees
+const SWEEP_STEP: f64 = 10.0;
+
+/// A rotation as a unit quaternion, w + v.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub v: Vec3,
+}
+
+impl Quaternion {
+    pub fn identity() -> Self {
+        Quaternion {
+            w: 1.0,
+            v: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// The same rotation as `Transform::rotation(axis, angle)`.
+    pub fn rotation(axis: Vec3, angle: f64) -> Self {
+        let (sin, cos) = (0.5 * angle.to_radians()).sin_cos();
+        Quaternion {
+            w: cos,
+            v: Vec3::unit_vector(axis) * sin,
+        }
+    }
+
+    /// This rotation followed by `next`.
+    pub fn then(&self, next: &Quaternion) -> Self {
+        let (a, b) = (next, self);
+        Quaternion {
+            w: a.w * b.w - Vec3::dot(&a.v, &b.v),
+            v: a.w * b.v + b.w * a.v + Vec3::cross(a.v, b.v),
+        }
+    }
+
+    fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + Vec3::dot(&self.v, &other.v)
+    }
+
+    /// How far the rotation turns on the way from `self` to `other`, in degrees.
+    pub fn turn(&self, other: &Quaternion) -> f64 {
+        2.0 * self.dot(other).clamp(-1.0, 1.0).acos().to_degrees()
+    }
+
+    /// Spherical interpolation, turning at a steady speed. Unlike the usual slerp it doesn't
+    /// take the shorter way round, so a turn of 270° written as angles stays one, and only
+    /// a full turn is out of reach.
+    pub fn slerp(&self, other: &Quaternion, t: f64) -> Self {
+        let cos = self.dot(other).clamp(-1.0, 1.0);
+        let angle = cos.acos();
+        let (a, b) = if angle.sin().abs() < 1e-9 {
+            // nearly the same rotation
+            (1.0 - t, t)
+        } else {
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+        let q = Quaternion {
+            w: a * self.w + b * other.w,
+            v: a * self.v + b * other.v,
+        };
+        let length = (q.w * q.w + q.v.length_squared()).sqrt();
+        Quaternion {
+            w: q.w / length,
+            v: q.v / length,
+        }
+    }
+
+    /// The rotation matrix, rows of the linear part.
+    fn matrix(&self) -> [[f64; 3]; 3] {
+        let (w, x, y, z) = (self.w, self.v.x, self.v.y, self.v.z);
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+}
+
+/// Where an object is at `time`: scaled by `scale`, turned by `rotation` and moved by
+/// `offset`, in that order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f64,
+    pub offset: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    /// Leaves the object as it is.
+    pub fn new(time: f64) -> Self {
+        Keyframe {
+            time,
+            offset: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::identity(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn offset(mut self, offset: Vec3) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Turns by `angle` degrees around `axis`, after any rotation given before.
+    pub fn rotate(mut self, axis: Vec3, angle: f64) -> Self {
+        self.rotation = self.rotation.then(&Quaternion::rotation(axis, angle));
+        self
+    }
+
+    pub fn scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// The keyframe a fraction `t` of the way to `next`.
+    fn towards(&self, next: &Keyframe, t: f64) -> Keyframe {
+        Keyframe {
+            time: self.time + t * (next.time - self.time),
+            offset: self.offset + t * (next.offset - self.offset),
+            rotation: self.rotation.slerp(&next.rotation, t),
+            scale: self.scale + t * (next.scale - self.scale),
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        let r = self.rotation.matrix();
+        let s = [self.scale.x, self.scale.y, self.scale.z];
+        let o = [self.offset.x, self.offset.y, self.offset.z];
+        let mut matrix = Matrix::identity();
+        let mut inverse = Matrix::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                matrix.m[i][j] = r[i][j] * s[j];
+                // scaling back after turning back: S⁻¹ Rᵀ
+                inverse.m[i][j] = r[j][i] / s[i];
+            }
+            matrix.m[i][3] = o[i];
+        }
+        for i in 0..3 {
+            inverse.m[i][3] = -(0..3).map(|j| inverse.m[i][j] * o[j]).sum::<f64>();
+        }
+        Transform { matrix, inverse }
+    }
+}
+
+/// Keyframes in time order. Before the first and after the last the object stays put.
+pub struct Animation {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Animation {
+    /// `None` without keyframes, when two share a time, or when a scale factor is zero or
+    /// changes sign, which would flatten the object on the way.
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Option<Self> {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let positive = |k: &Keyframe| [k.scale.x > 0.0, k.scale.y > 0.0, k.scale.z > 0.0];
+        let first = keyframes.first()?;
+        let signs = positive(first);
+        let valid = keyframes.iter().all(|k| {
+            k.scale.x != 0.0 && k.scale.y != 0.0 && k.scale.z != 0.0 && positive(k) == signs
+        }) && keyframes.windows(2).all(|w| w[0].time < w[1].time);
+        if valid {
+            Some(Animation { keyframes })
+        } else {
+            None
+        }
+    }
+
+    /// Where the object is at `time`.
+    pub fn keyframe(&self, time: f64) -> Keyframe {
+        let after = self.keyframes.partition_point(|k| k.time <= time);
+        if after == 0 {
+            return self.keyframes[0];
+        }
+        if after == self.keyframes.len() {
+            return self.keyframes[after - 1];
+        }
+        let (a, b) = (&self.keyframes[after - 1], &self.keyframes[after]);
+        a.towards(b, (time - a.time) / (b.time - a.time))
+    }
+
+    /// From object to world space at `time`.
+    pub fn at(&self, time: f64) -> Transform {
+        self.keyframe(time).transform()
+    }
+
+    /// A world box around everywhere `b`, a box in object space, goes between `time_start`
+    /// and `time_end`.
+    ///
+    /// The transform is sampled at the keyframes in between and often enough to turn less
+    /// than `SWEEP_STEP` from one sample to the next. Corners travel along arcs between the
+    /// samples, so the box grows by how far an arc can bulge past its chord.
+    pub fn bounding_box(&self, b: &AABB, time_start: f64, time_end: f64) -> AABB {
+        let mut times = vec![time_start];
+        times.extend(
+            self.keyframes
+                .iter()
+                .map(|k| k.time)
+                .filter(|t| *t > time_start && *t < time_end),
+        );
+        times.push(time_end);
+
+        let mut bounds = self.at(time_start).bounding_box(b);
+        let mut largest_step: f64 = 0.0;
+        for span in times.windows(2) {
+            let (from, to) = (self.keyframe(span[0]), self.keyframe(span[1]));
+            let turn = from.rotation.turn(&to.rotation);
+            let steps = (turn / SWEEP_STEP).ceil().max(1.0);
+            largest_step = largest_step.max(turn / steps);
+            for i in 1..=steps as usize {
+                let t = span[0] + (span[1] - span[0]) * i as f64 / steps;
+                bounds = AABB::surrounding_box(&bounds, &self.at(t).bounding_box(b));
+            }
+        }
+
+        // furthest any corner gets from the object's origin
+        let scale = self
+            .keyframes
+            .iter()
+            .fold(Vec3::new(0.0, 0.0, 0.0), |s, k| {
+                Vec3::new(
+                    s.x.max(k.scale.x.abs()),
+                    s.y.max(k.scale.y.abs()),
+                    s.z.max(k.scale.z.abs()),
+                )
+            });
+        let reach = Vec3::new(
+            scale.x * b.min.x.abs().max(b.max.x.abs()),
+            scale.y * b.min.y.abs().max(b.max.y.abs()),
+            scale.z * b.min.z.abs().max(b.max.z.abs()),
+        )
+        .length();
+        let bulge = reach * (1.0 - (0.5 * largest_step).to_radians().cos());
+        let bulge = Vec3::new(bulge, bulge, bulge);
+        AABB::new(bounds.min - bulge, bounds.max + bulge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn quaternions_agree_with_matrices() {
+        let axis = Vec3::new(1.0, 2.0, -0.5);
+        let q = Quaternion::rotation(axis, 70.0)
+            .then(&Quaternion::rotation(Vec3::new(0.0, 1.0, 0.0), 30.0));
+        let key = Keyframe {
+            rotation: q,
+            ..Keyframe::new(0.0)
+        };
+        let expected = Transform::rotation(axis, 70.0).then(&Transform::rotation_y(30.0));
+        let p = Vec3::new(0.3, -1.0, 2.0);
+        assert_near(key.transform().point(&p), expected.point(&p));
+        assert_near(
+            key.transform().inverse.transform_point(&expected.point(&p)),
+            p,
+        );
+    }
+
+    #[test]
+    fn interpolates_between_keyframes() {
+        let animation = Animation::new(vec![
+            Keyframe::new(1.0)
+                .offset(Vec3::new(4.0, 0.0, 0.0))
+                .rotate(Vec3::new(0.0, 1.0, 0.0), 270.0)
+                .scale(Vec3::new(3.0, 3.0, 3.0)),
+            Keyframe::new(0.0),
+        ])
+        .unwrap();
+        let p = Vec3::new(1.0, 0.0, 0.0);
+        assert_near(animation.at(-1.0).point(&p), p);
+        // halfway: scaled by 2, turned by 135° and moved by 2
+        let half = 135f64.to_radians();
+        assert_near(
+            animation.at(0.5).point(&p),
+            Vec3::new(2.0 + 2.0 * half.cos(), 0.0, -2.0 * half.sin()),
+        );
+        assert_near(animation.at(2.0).point(&p), Vec3::new(4.0, 0.0, 3.0));
+
+        assert!(Animation::new(vec![]).is_none());
+        let flip = Keyframe::new(1.0).scale(Vec3::new(-1.0, 1.0, 1.0));
+        assert!(Animation::new(vec![Keyframe::new(0.0), flip]).is_none());
+    }
+
+    #[test]
+    fn swept_box_covers_the_turn() {
+        let animation = Animation::new(vec![
+            Keyframe::new(0.0),
+            Keyframe::new(1.0).rotate(Vec3::new(0.0, 0.0, 1.0), 180.0),
+        ])
+        .unwrap();
+        let rod = AABB::new(Vec3::new(1.0, -0.1, -0.1), Vec3::new(2.0, 0.1, 0.1));
+        let swept = animation.bounding_box(&rod, 0.0, 1.0);
+        // the far end passes through (0, 2) halfway
+        assert!(swept.max.y >= 2.0 && swept.max.y < 2.1, "{:?}", swept);
+        assert!(swept.min.x <= -2.0 && swept.max.x >= 2.0);
+        // only the first half of the shutter
+        let half = animation.bounding_box(&rod, 0.0, 0.5);
+        assert!(half.min.x < 0.0 && half.min.x > -0.2, "{:?}", half);
+    }
+}
@@ -0,0 +1,302 @@
+//! The scenes built into the renderer, selectable by name instead of a scene file.
+
+use crate::camera::Camera;
+use crate::constant_medium::ConstantMedium;
+use crate::environment::SolidBackground;
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::mybox::MyBox;
+use crate::rectangle::XZRectangle;
+use crate::render::RenderSettings;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::sphere::Sphere;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture};
+use crate::transform::Transform;
+use crate::vec::Vec3;
+use rand::Rng;
+use std::sync::Arc;
+
+pub const NAMES: [&str; 3] = ["scene1", "random_scene", "final_scene"];
+
+/// Built in, so the scenes don't depend on where the renderer runs from
+const WORLD_MAP: &[u8] = include_bytes!("../../scenes/world.png");
+
+/// The built-in scene called `name`, if there is one.
+pub fn load(name: &str, sampler: &mut Sampler) -> Option<Scene> {
+    match name {
+        "scene1" => Some(scene1(sampler)),
+        "random_scene" => Some(random_scene(sampler)),
+        "final_scene" => Some(final_scene(sampler)),
+        _ => None,
+    }
+}
+
+fn camera(look_from: Vec3, look_at: Vec3, vfov: f64, aspect_ratio: f64) -> Camera {
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let distance_to_focus = 10.0;
+    let aperture = 0.0;
+
+    Camera::new(
+        look_from,
+        look_at,
+        vup,
+        vfov,
+        aspect_ratio,
+        aperture,
+        distance_to_focus,
+        0.0,
+        1.0,
+    )
+}
+
+pub fn scene1(_sampler: &mut Sampler) -> Scene {
+    let mut world = HittableList::new();
+
+    let material_ground = Arc::new(Lambertian::new_color(Vec3::new(0.8, 0.8, 0.0)));
+    let material_center = Arc::new(Lambertian::new_color(Vec3::new(0.1, 0.2, 0.5)));
+    let material_left = Arc::new(Dielectric::new(1.5));
+    let material_right = Arc::new(Metal::new(Vec3::new(0.8, 0.6, 0.2), 0.0));
+
+    let ground_sphere = Arc::new(Sphere::new(
+        Vec3::new(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    ));
+    world.add(ground_sphere);
+
+    let center_sphere = Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, material_center));
+    world.add(center_sphere);
+    let left_sphere = Arc::new(Sphere::new(Vec3::new(-1.0, 0.0, -1.0), 0.5, material_left));
+    world.add(left_sphere);
+    let right_sphere = Arc::new(Sphere::new(Vec3::new(1.0, 0.0, -1.0), 0.5, material_right));
+    world.add(right_sphere);
+
+    let settings = RenderSettings::default();
+    Scene {
+        world,
+        lights: HittableList::new(),
+        punctual_lights: Vec::new(),
+        camera: camera(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            90.0,
+            settings.aspect_ratio,
+        ),
+        background: Arc::new(SolidBackground::new(Vec3::new(0.7, 0.8, 1.0))),
+        settings,
+    }
+}
+
+pub fn random_scene(sampler: &mut Sampler) -> Scene {
+    let mut world = HittableList::new();
+    let checker = Arc::new(CheckerTexture::new_color(
+        Vec3::new(0.2, 0.3, 0.1),
+        Vec3::new(0.9, 0.9, 0.9),
+    ));
+    //    let material_ground = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let ground_sphere = Arc::new(Sphere::new(
+        Vec3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new_texture(checker)),
+    ));
+    world.add(ground_sphere);
+    for a in -11..11 {
+        for b in -11..11 {
+            let material_choise = sampler.gen::<f64>();
+            let center = Vec3::new(
+                a as f64 + 0.9 * sampler.gen::<f64>(),
+                0.2,
+                b as f64 * 0.9 * sampler.gen::<f64>(),
+            );
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if material_choise < 0.8 {
+                    //diffuse
+                    let albedo = Vec3::random_range(sampler, 0.0, 1.0)
+                        * Vec3::random_range(sampler, 0.0, 1.0);
+                    let material_sphere = Arc::new(Lambertian::new_color(albedo));
+                    let center_end = center + Vec3::new(0.0, sampler.gen_range(0.0..0.5), 0.0);
+                    world.add(Arc::new(Sphere::new_moving(
+                        0.2,
+                        center,
+                        center_end,
+                        0.0,
+                        1.0,
+                        material_sphere,
+                    )));
+                } else if material_choise < 0.95 {
+                    //metal
+                    let albedo = Vec3::random_range(sampler, 0.5, 1.0);
+                    let fuzz = sampler.gen_range(0.5..1.0);
+                    let material_sphere = Arc::new(Metal::new(albedo, fuzz));
+                    world.add(Arc::new(Sphere::new(center, 0.2, material_sphere)));
+                } else {
+                    //glass
+                    let material_sphere = Arc::new(Dielectric::new(1.5));
+                    world.add(Arc::new(Sphere::new(center, 0.2, material_sphere)));
+                }
+            }
+        }
+    }
+
+    let material1 = Arc::new(Dielectric::new(1.5));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        material1,
+    )));
+
+    let material2 = Arc::new(Lambertian::new_color(Vec3::new(0.4, 0.2, 0.1)));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(-4.0, 1.0, 0.0),
+        1.0,
+        material2,
+    )));
+
+    let material3 = Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(4.0, 1.0, 0.0),
+        1.0,
+        material3,
+    )));
+
+    let settings = RenderSettings::default();
+    Scene {
+        world,
+        lights: HittableList::new(),
+        punctual_lights: Vec::new(),
+        camera: camera(
+            Vec3::new(13.0, 2.0, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            20.0,
+            settings.aspect_ratio,
+        ),
+        background: Arc::new(SolidBackground::new(Vec3::new(0.7, 0.8, 1.0))),
+        settings,
+    }
+}
+
+pub fn final_scene(sampler: &mut Sampler) -> Scene {
+    let mut objects = HittableList::new();
+    let ground = Arc::new(Lambertian::new_color(Vec3::new(0.48, 0.83, 0.53)));
+
+    let boxes_per_side = 20;
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let w = 100.0;
+            let x0 = -1000.0 + (i as f64 * w);
+            let z0 = -1000.0 + (j as f64 * w);
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = sampler.gen_range(1.0..101.0);
+            let z1 = z0 + w;
+            objects.add(Arc::new(MyBox::new(
+                Vec3::new(x0, y0, z0),
+                Vec3::new(x1, y1, z1),
+                ground.clone(),
+            )));
+        }
+    }
+
+    let light = Arc::new(DiffuseLight::new_color(Vec3::new(7.0, 7.0, 7.0)));
+    let light = Arc::new(XZRectangle::new(123.0, 423.0, 147.0, 412.0, 554.0, light));
+    objects.add(light.clone());
+    let mut lights = HittableList::new();
+    lights.add(light);
+
+    let center1 = Vec3::new(400.0, 400.0, 200.0);
+    let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
+
+    let moving_sphere_material = Arc::new(Lambertian::new_color(Vec3::new(0.7, 0.3, 0.1)));
+    objects.add(Arc::new(Sphere::new_moving(
+        50.0,
+        center1,
+        center2,
+        0.0,
+        1.0,
+        moving_sphere_material,
+    )));
+
+    objects.add(Arc::new(Sphere::new(
+        Vec3::new(260.0, 150.0, 45.0),
+        50.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 150.0, 145.0),
+        50.0,
+        Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.9), 1.0)),
+    )));
+
+    let boundary = Arc::new(Sphere::new(
+        Vec3::new(360.0, 150.0, 145.0),
+        70.0,
+        Arc::new(Dielectric::new(1.5)),
+    ));
+    objects.add(boundary.clone());
+    objects.add(Arc::new(ConstantMedium::new_color(
+        boundary,
+        0.2,
+        Vec3::new(0.2, 0.4, 0.9),
+    )));
+    let boundary = Arc::new(Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        5000.0,
+        Arc::new(Dielectric::new(1.5)),
+    ));
+    objects.add(Arc::new(ConstantMedium::new_color(
+        boundary,
+        0.0001,
+        Vec3::new(1.0, 1.0, 1.0),
+    )));
+
+    let earth_material = Arc::new(Lambertian::new_texture(Arc::new(
+        ImageTexture::load_from_memory(WORLD_MAP).expect("the world map is a valid image"),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Vec3::new(400.0, 200.0, 400.0),
+        100.0,
+        earth_material,
+    )));
+    let pertext = Arc::new(NoiseTexture::new_scaled(0.1, sampler));
+    objects.add(Arc::new(Sphere::new(
+        Vec3::new(220.0, 280.0, 300.0),
+        80.0,
+        Arc::new(Lambertian::new_texture(pertext)),
+    )));
+
+    let white = Arc::new(Lambertian::new_color(Vec3::new(0.73, 0.73, 0.73)));
+    let placement =
+        Transform::rotation_y(15.0).then(&Transform::translation(Vec3::new(-100.0, 270.0, 395.0)));
+    let ns = 1000;
+    for _ in 0..ns {
+        objects.add(Arc::new(Sphere::new(
+            placement.point(&Vec3::random_range(sampler, 0.0, 165.0)),
+            10.0,
+            white.clone(),
+        )));
+    }
+
+    let settings = RenderSettings {
+        image_width: 1920,
+        fixed_height: None,
+        aspect_ratio: 16.0 / 9.0,
+        samples_per_pixel: 10000,
+        max_depth: 50,
+        spectral: false,
+        adaptive: None,
+    };
+    Scene {
+        world: objects,
+        lights,
+        punctual_lights: Vec::new(),
+        camera: camera(
+            Vec3::new(478.0, 278.0, -600.0),
+            Vec3::new(278.0, 278.0, 0.0),
+            40.0,
+            settings.aspect_ratio,
+        ),
+        background: Arc::new(SolidBackground::new(Vec3::new(0.0, 0.0, 0.0))),
+        settings,
+    }
+}
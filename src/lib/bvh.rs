@@ -0,0 +1,428 @@
+//! Bounding volume hierarchy built with the binned surface area heuristic.
+//!
+//! The tree is stored as a flat array of nodes in depth-first order, so the first child of
+//! an interior node directly follows it. Traversal uses an explicit stack and visits the
+//! child nearer to the ray origin first.
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::HittableList;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use std::sync::Arc;
+
+/// Number of buckets the centroid range is divided into when looking for a split.
+const BINS: usize = 16;
+/// Largest leaf the builder creates when splitting isn't worth it.
+const MAX_LEAF_SIZE: usize = 8;
+/// Cost of visiting a node relative to intersecting one primitive.
+const TRAVERSAL_COST: f64 = 0.125;
+/// Below this many primitives subtrees are built on the current thread.
+const PARALLEL_THRESHOLD: usize = 4096;
+
+#[derive(Clone, Copy)]
+pub(crate) enum Node {
+    Leaf {
+        bounds: AABB,
+        first: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: AABB,
+        /// Index of the second child; the first one follows the node.
+        second: usize,
+        /// Axis the children were split along, which decides their order for a ray.
+        axis: usize,
+    },
+}
+
+impl Node {
+    pub(crate) fn bounds(&self) -> &AABB {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Builds a tree over primitives with the given bounding boxes. Returns the nodes and the
+/// order the primitives must be stored in, so every leaf covers a contiguous range.
+pub(crate) fn build(boxes: &[AABB]) -> (Vec<Node>, Vec<usize>) {
+    if boxes.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let mut items = boxes
+        .iter()
+        .enumerate()
+        .map(|(index, b)| Item {
+            index,
+            bounds: *b,
+            centroid: (b.min + b.max) * 0.5,
+        })
+        .collect::<Vec<_>>();
+    let tree = build_recursive(&mut items, 0);
+    let mut nodes = Vec::with_capacity(2 * boxes.len());
+    flatten(tree, &mut nodes);
+    (nodes, items.iter().map(|item| item.index).collect())
+}
+
+/// Walks the tree front to back. `hit_primitive` is called with the index of a primitive,
+/// in build order, and the current closest distance, and returns the distance of a closer hit.
+pub(crate) fn traverse<F>(nodes: &[Node], ray: &Ray, t_min: f64, t_max: f64, mut hit_primitive: F)
+where
+    F: FnMut(usize, f64) -> Option<f64>,
+{
+    if nodes.is_empty() {
+        return;
+    }
+    let negative = [
+        ray.direction.x < 0.0,
+        ray.direction.y < 0.0,
+        ray.direction.z < 0.0,
+    ];
+    let mut closest = t_max;
+    let mut stack = Vec::with_capacity(64);
+    stack.push(0);
+    while let Some(index) = stack.pop() {
+        let node = &nodes[index];
+        if !node.bounds().hit2(ray, t_min, closest) {
+            continue;
+        }
+        match *node {
+            Node::Leaf { first, count, .. } => {
+                for i in first..first + count {
+                    if let Some(t) = hit_primitive(i, closest) {
+                        closest = t;
+                    }
+                }
+            }
+            Node::Interior { second, axis, .. } => {
+                // push the far child first so the near one is visited next
+                let (near, far) = if negative[axis] {
+                    (second, index + 1)
+                } else {
+                    (index + 1, second)
+                };
+                stack.push(far);
+                stack.push(near);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Item {
+    index: usize,
+    bounds: AABB,
+    centroid: Vec3,
+}
+
+enum BuildNode {
+    Leaf {
+        bounds: AABB,
+        first: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: AABB,
+        axis: usize,
+        children: Box<(BuildNode, BuildNode)>,
+    },
+}
+
+fn axis_value(v: &Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+fn surrounding(items: &[Item], value: impl Fn(&Item) -> AABB) -> AABB {
+    items.iter().skip(1).fold(value(&items[0]), |b, item| {
+        AABB::surrounding_box(&b, &value(item))
+    })
+}
+
+/// `offset` is the position of `items[0]` in the final primitive order.
+fn build_recursive(items: &mut [Item], offset: usize) -> BuildNode {
+    let bounds = surrounding(items, |item| item.bounds);
+    let leaf = BuildNode::Leaf {
+        bounds,
+        first: offset,
+        count: items.len(),
+    };
+    if items.len() <= 2 {
+        return leaf;
+    }
+
+    let centroid_bounds = surrounding(items, |item| AABB::new(item.centroid, item.centroid));
+    let extent = centroid_bounds.max - centroid_bounds.min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    let axis_min = axis_value(&centroid_bounds.min, axis);
+    let axis_extent = axis_value(&extent, axis);
+
+    let mid = if axis_extent <= 0.0 {
+        // all centroids coincide, so no plane separates them
+        if items.len() <= MAX_LEAF_SIZE {
+            return leaf;
+        }
+        items.len() / 2
+    } else {
+        let bin_of = |item: &Item| {
+            let relative = (axis_value(&item.centroid, axis) - axis_min) / axis_extent;
+            ((relative * BINS as f64) as usize).min(BINS - 1)
+        };
+        let mut counts = [0usize; BINS];
+        let mut bin_bounds: [Option<AABB>; BINS] = [None; BINS];
+        for item in items.iter() {
+            let bin = bin_of(item);
+            counts[bin] += 1;
+            bin_bounds[bin] = Some(match bin_bounds[bin] {
+                Some(b) => AABB::surrounding_box(&b, &item.bounds),
+                None => item.bounds,
+            });
+        }
+
+        // cost of splitting after each bin, from sweeps in both directions
+        let mut costs = [0.0; BINS - 1];
+        let mut count = 0;
+        let mut area_bounds: Option<AABB> = None;
+        for split in 0..BINS - 1 {
+            count += counts[split];
+            area_bounds = merge(area_bounds, bin_bounds[split]);
+            costs[split] = count as f64 * area_bounds.map_or(0.0, |b| b.surface_area());
+        }
+        let mut count = 0;
+        let mut area_bounds: Option<AABB> = None;
+        for split in (0..BINS - 1).rev() {
+            count += counts[split + 1];
+            area_bounds = merge(area_bounds, bin_bounds[split + 1]);
+            costs[split] += count as f64 * area_bounds.map_or(0.0, |b| b.surface_area());
+        }
+        let (best_split, best_cost) = costs
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(split, cost)| (split, *cost))
+            .unwrap();
+
+        let split_cost = TRAVERSAL_COST + best_cost / bounds.surface_area().max(f64::MIN_POSITIVE);
+        if items.len() <= MAX_LEAF_SIZE && split_cost >= items.len() as f64 {
+            return leaf;
+        }
+        let mut mid = partition(items, |item| bin_of(item) <= best_split);
+        if mid == 0 || mid == items.len() {
+            mid = items.len() / 2;
+            items.select_nth_unstable_by(mid, |a, b| {
+                axis_value(&a.centroid, axis).total_cmp(&axis_value(&b.centroid, axis))
+            });
+        }
+        mid
+    };
+
+    let (left, right) = items.split_at_mut(mid);
+    let children = if left.len() + right.len() > PARALLEL_THRESHOLD {
+        rayon::join(
+            || build_recursive(left, offset),
+            || build_recursive(right, offset + mid),
+        )
+    } else {
+        (
+            build_recursive(left, offset),
+            build_recursive(right, offset + mid),
+        )
+    };
+    BuildNode::Interior {
+        bounds,
+        axis,
+        children: Box::new(children),
+    }
+}
+
+fn merge(a: Option<AABB>, b: Option<AABB>) -> Option<AABB> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(AABB::surrounding_box(&a, &b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Moves the items matching `predicate` to the front, returning how many there are.
+fn partition(items: &mut [Item], predicate: impl Fn(&Item) -> bool) -> usize {
+    let mut first_false = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, first_false);
+            first_false += 1;
+        }
+    }
+    first_false
+}
+
+fn flatten(node: BuildNode, nodes: &mut Vec<Node>) {
+    match node {
+        BuildNode::Leaf {
+            bounds,
+            first,
+            count,
+        } => nodes.push(Node::Leaf {
+            bounds,
+            first,
+            count,
+        }),
+        BuildNode::Interior {
+            bounds,
+            axis,
+            children,
+        } => {
+            let index = nodes.len();
+            nodes.push(Node::Interior {
+                bounds,
+                second: 0,
+                axis,
+            });
+            let (first, second) = *children;
+            flatten(first, nodes);
+            let second_index = nodes.len();
+            flatten(second, nodes);
+            nodes[index] = Node::Interior {
+                bounds,
+                second: second_index,
+                axis,
+            };
+        }
+    }
+}
+
+/// A hierarchy over arbitrary hittables, the replacement for `BVHNode`.
+pub struct Bvh {
+    objects: Vec<Arc<dyn Hittable>>,
+    nodes: Vec<Node>,
+    /// Objects without a bounding box, like infinite planes, tested on every ray
+    unbounded: Vec<Arc<dyn Hittable>>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy for bounding boxes over the time interval of the rays.
+    pub fn new(objects: Vec<Arc<dyn Hittable>>, time_start: f64, time_end: f64) -> Self {
+        let mut bounded = Vec::with_capacity(objects.len());
+        let mut boxes = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for object in objects {
+            match object.bounding_box(time_start, time_end) {
+                Some(b) => {
+                    boxes.push(b);
+                    bounded.push(object);
+                }
+                None => unbounded.push(object),
+            }
+        }
+        let (nodes, order) = build(&boxes);
+        Bvh {
+            objects: order.iter().map(|&i| bounded[i].clone()).collect(),
+            nodes,
+            unbounded,
+        }
+    }
+
+    pub fn from_list(list: &HittableList, time_start: f64, time_end: f64) -> Self {
+        Bvh::new(list.objects.clone(), time_start, time_end)
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        for object in &self.unbounded {
+            let t_max = closest.as_ref().map_or(t_max, |hit| hit.t);
+            if let Some(hit) = object.hit(ray, t_min, t_max) {
+                closest = Some(hit);
+            }
+        }
+        let t_max = closest.as_ref().map_or(t_max, |hit| hit.t);
+        traverse(&self.nodes, ray, t_min, t_max, |i, closest_so_far| {
+            let hit = self.objects[i].hit(ray, t_min, closest_so_far)?;
+            let t = hit.t;
+            closest = Some(hit);
+            Some(t)
+        });
+        closest
+    }
+
+    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| *node.bounds())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sampler::Sampler;
+    use crate::sphere::Sphere;
+    use rand::Rng;
+
+    fn spheres(sampler: &mut Sampler, count: usize) -> HittableList {
+        let material = Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5)));
+        let mut list = HittableList::new();
+        for _ in 0..count {
+            list.add(Arc::new(Sphere::new(
+                Vec3::random_range(sampler, -10.0, 10.0),
+                sampler.gen_range(0.05..0.5),
+                material.clone(),
+            )));
+        }
+        list
+    }
+
+    #[test]
+    fn finds_the_same_hits_as_a_list() {
+        let mut sampler = Sampler::new(11);
+        // enough objects for the parallel build to kick in
+        for count in [1, 2, 3, 100, 5000] {
+            let list = spheres(&mut sampler, count);
+            let bvh = Bvh::from_list(&list, 0.0, 1.0);
+            for _ in 0..500 {
+                let ray = Ray::new_at_time(
+                    Vec3::random_range(&mut sampler, -12.0, 12.0),
+                    Vec3::random_unit_vector(&mut sampler),
+                    0.0,
+                );
+                let expected = list.hit(&ray, 0.001, f64::INFINITY).map(|h| h.t);
+                let actual = bvh.hit(&ray, 0.001, f64::INFINITY).map(|h| h.t);
+                assert_eq!(expected, actual);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_cover_every_primitive_once() {
+        let mut sampler = Sampler::new(12);
+        let list = spheres(&mut sampler, 1000);
+        let boxes = list
+            .objects
+            .iter()
+            .map(|o| o.bounding_box(0.0, 1.0).unwrap())
+            .collect::<Vec<_>>();
+        let (nodes, order) = build(&boxes);
+        let mut covered = vec![0; boxes.len()];
+        for node in &nodes {
+            if let Node::Leaf { first, count, .. } = node {
+                assert!(*count <= MAX_LEAF_SIZE);
+                for i in *first..first + count {
+                    covered[order[i]] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&c| c == 1));
+    }
+}
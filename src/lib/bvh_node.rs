@@ -2,6 +2,7 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::Vec3;
 use rand::prelude::SliceRandom;
 use std::cmp::Ordering;
@@ -15,21 +16,34 @@ pub struct BVHNode {
 }
 
 impl BVHNode {
-    pub fn new_hittablelist(list: &HittableList, time_start: f64, time_end: f64) -> BVHNode {
-        BVHNode::new_vector(&list.objects, 0, list.objects.len(), time_start, time_end)
+    pub fn new_hittablelist(
+        list: &HittableList,
+        time_start: f64,
+        time_end: f64,
+        sampler: &mut Sampler,
+    ) -> BVHNode {
+        BVHNode::new_vector(
+            &list.objects,
+            0,
+            list.objects.len(),
+            time_start,
+            time_end,
+            sampler,
+        )
     }
 
     pub fn new_vector(
-        source_objects: &Vec<Arc<dyn Hittable>>,
+        source_objects: &[Arc<dyn Hittable>],
         start: usize,
         end: usize,
         time_start: f64,
         time_end: f64,
+        sampler: &mut Sampler,
     ) -> BVHNode {
-        let mut objects = source_objects.clone();
+        let mut objects = source_objects.to_vec();
 
         let axes: Vec<fn(&Vec3) -> f64> = vec![|v: &Vec3| v.x, |v: &Vec3| v.y, |v: &Vec3| v.z];
-        let axis_to_compare = axes.choose(&mut rand::thread_rng()).unwrap();
+        let axis_to_compare = axes.choose(sampler).unwrap();
         let object_span = end - start;
 
         let (left, right) = match object_span {
@@ -44,14 +58,14 @@ impl BVHNode {
                 }
             }
             _ => {
-                objects.sort_by(|a, b| BVHNode::compare(&**a, &**b, *axis_to_compare));
+                objects[start..end].sort_by(|a, b| BVHNode::compare(&**a, &**b, *axis_to_compare));
                 let mid = start + object_span / 2;
                 (
                     Arc::new(BVHNode::new_vector(
-                        &objects, start, mid, time_start, time_end,
+                        &objects, start, mid, time_start, time_end, sampler,
                     )) as Arc<dyn Hittable>,
                     Arc::new(BVHNode::new_vector(
-                        &objects, mid, end, time_start, time_end,
+                        &objects, mid, end, time_start, time_end, sampler,
                     )) as Arc<dyn Hittable>,
                 )
             }
@@ -93,7 +107,7 @@ impl Hittable for BVHNode {
             if let Some(right_hit) = self.right.hit(ray, t_min, left_hit.t) {
                 return Some(right_hit);
             }
-            return Some(left_hit);
+            Some(left_hit)
         } else {
             self.right.hit(ray, t_min, t_max)
         }
@@ -1,4 +1,5 @@
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::Vec3;
 use rand::Rng;
 pub struct Camera {
@@ -15,6 +16,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -55,16 +57,28 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
-        let offset = self.u * rd.x + self.v * rd.y;
+    /// Changes the aspect ratio by widening or narrowing the view, keeping the vertical
+    /// field of view.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let current = self.horizontal.length() / self.vertical.length();
+        self.horizontal *= aspect_ratio / current;
+        self.lower_left_corner = center - self.horizontal / 2.0 - self.vertical / 2.0;
+    }
 
-        let mut rng = rand::thread_rng();
+    /// The times the shutter opens and closes; rays are spread between them.
+    pub fn shutter(&self) -> (f64, f64) {
+        (self.time_start, self.time_end)
+    }
+
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut Sampler) -> Ray {
+        let rd = self.lens_radius * Vec3::random_in_unit_disk(sampler);
+        let offset = self.u * rd.x + self.v * rd.y;
 
         Ray::new_at_time(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-            rng.gen_range(self.time_start..self.time_end),
+            sampler.gen_range(self.time_start..self.time_end),
         )
     }
 }
@@ -0,0 +1,125 @@
+use crate::aabb::AABB;
+use crate::cylinder::around_axis;
+use crate::frame::{Frame, Motion};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::polynomial::quadratic_roots;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use std::sync::Arc;
+
+/// Every point within `radius` of the segment from `start` to `end`: a cylinder with a half
+/// sphere on each end.
+///
+/// u goes around the axis and v along it, from the tip of the start to the tip of the end.
+pub struct Capsule {
+    /// Origin halfway along the segment
+    frame: Frame,
+    radius: f64,
+    half_length: f64,
+    material: Arc<dyn Material>,
+}
+
+impl Capsule {
+    pub fn new(start: Vec3, end: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        Capsule::new_moving(start, end, radius, Motion::none(), material)
+    }
+
+    pub fn new_moving(
+        start: Vec3,
+        end: Vec3,
+        radius: f64,
+        motion: Motion,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        let axis = end - start;
+        // any axis does for a sphere
+        let axis = if axis.near_zero() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            axis
+        };
+        Capsule {
+            frame: Frame::new((start + end) * 0.5, axis, motion),
+            radius,
+            half_length: 0.5 * (end - start).length(),
+            material,
+        }
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = self.frame.to_local(ray);
+        let (o, d) = (local.origin, local.direction);
+        let (r, h) = (self.radius, self.half_length);
+
+        // the side, and each half sphere beyond its end of the segment
+        let mut hits: Vec<f64> = quadratic_roots(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - r * r,
+        )
+        .into_iter()
+        .filter(|t| local.at(*t).z.abs() <= h)
+        .collect();
+        for z in [-h, h] {
+            let oc = o - Vec3::new(0.0, 0.0, z);
+            let roots = quadratic_roots(
+                d.length_squared(),
+                2.0 * Vec3::dot(&oc, &d),
+                oc.length_squared() - r * r,
+            );
+            hits.extend(
+                roots
+                    .into_iter()
+                    .filter(|t| (local.at(*t).z - z) * z >= 0.0),
+            );
+        }
+        let t = hits
+            .into_iter()
+            .filter(|t| *t >= t_min && *t <= t_max)
+            .min_by(f64::total_cmp)?;
+
+        let p = local.at(t);
+        let normal = p - Vec3::new(0.0, 0.0, p.z.clamp(-h, h));
+        let uv = (around_axis(&p), (p.z + h + r) / (2.0 * (h + r)));
+        Some(self.frame.hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        let (r, h) = (self.radius, self.half_length);
+        let local = AABB::new(Vec3::new(-r, -r, -h - r), Vec3::new(r, r, h + r));
+        Some(self.frame.bounding_box(&local, time_start, time_end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn capsule_has_round_ends() {
+        let capsule = Capsule::new(
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            0.5,
+            Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let hit = |origin: Vec3, direction: Vec3| {
+            capsule.hit(&Ray::new_at_time(origin, direction, 0.0), 0.001, 100.0)
+        };
+        let end = hit(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert!((end.t - 3.5).abs() < 1e-9);
+        assert!((end.normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((end.v - 1.0).abs() < 1e-9);
+
+        let side = hit(Vec3::new(0.5, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!((side.t - 2.5).abs() < 1e-9 && side.normal.y > 0.999);
+        // over the rounded end, lower than the side
+        let round = hit(Vec3::new(1.3, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!((round.t - 2.6).abs() < 1e-9);
+        assert!(hit(Vec3::new(1.6, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{Isotropic, Material};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+use crate::vec::Vec3;
+use rand::Rng;
+use std::sync::Arc;
+
+enum Density {
+    Constant {
+        neg_inv_density: f64,
+    },
+    Varying {
+        density: Arc<dyn Texture>,
+        max_density: f64,
+    },
+}
+
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    phase_function: Arc<dyn Material>,
+    density: Density,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: Arc<dyn Texture>) -> Self {
+        ConstantMedium {
+            boundary,
+            phase_function: Arc::new(Isotropic::new(albedo)),
+            density: Density::Constant {
+                neg_inv_density: -1.0 / density,
+            },
+        }
+    }
+
+    pub fn new_color(boundary: Arc<dyn Hittable>, density: f64, color: Vec3) -> Self {
+        ConstantMedium {
+            boundary,
+            phase_function: Arc::new(Isotropic::new_color(color)),
+            density: Density::Constant {
+                neg_inv_density: -1.0 / density,
+            },
+        }
+    }
+
+    /// A heterogeneous medium whose density at a point is `max_density` scaled by the
+    /// average of the `density` texture's channels, clamped to [0.0, 1.0].
+    /// Sampled with delta tracking, so `max_density` must bound the density everywhere.
+    pub fn new_varying(
+        boundary: Arc<dyn Hittable>,
+        max_density: f64,
+        density: Arc<dyn Texture>,
+        albedo: Arc<dyn Texture>,
+    ) -> Self {
+        // tracking steps forward by distances drawn from the bound, so it must be positive
+        assert!(
+            max_density > 0.0 && max_density.is_finite(),
+            "max_density must be positive, found {}",
+            max_density
+        );
+        ConstantMedium {
+            boundary,
+            phase_function: Arc::new(Isotropic::new(albedo)),
+            density: Density::Varying {
+                density,
+                max_density,
+            },
+        }
+    }
+
+    fn density_fraction(density: &dyn Texture, point: &Vec3) -> f64 {
+        let value = density.value(0.0, 0.0, point);
+        ((value.x + value.y + value.z) / 3.0).clamp(0.0, 1.0)
+    }
+
+    /// Distance along the ray, from t_start, to the next scattering event.
+    /// Returns a value >= t_end if the ray passes through the medium.
+    fn sample_distance(&self, ray: &Ray, t_start: f64, t_end: f64) -> f64 {
+        let ray_length = ray.direction.length();
+        let mut sampler = Sampler::for_ray(ray);
+        match &self.density {
+            Density::Constant { neg_inv_density } => {
+                t_start + neg_inv_density * sampler.gen::<f64>().ln() / ray_length
+            }
+            Density::Varying {
+                density,
+                max_density,
+            } => {
+                let neg_inv_max = -1.0 / max_density;
+                let mut t = t_start;
+                loop {
+                    t += neg_inv_max * sampler.gen::<f64>().ln() / ray_length;
+                    if t >= t_end
+                        || Self::density_fraction(&**density, &ray.at(t)) > sampler.gen::<f64>()
+                    {
+                        return t;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let hit1 = self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?;
+        let hit2 = self.boundary.hit(ray, hit1.t + 0.0001, f64::INFINITY)?;
+
+        let t_start = hit1.t.max(t_min).max(0.0);
+        let t_end = hit2.t.min(t_max);
+        if t_start >= t_end {
+            return None;
+        }
+
+        let t = self.sample_distance(ray, t_start, t_end);
+        if t >= t_end {
+            return None;
+        }
+
+        let mut hit_record = HitRecord::new(self.phase_function.clone());
+        hit_record.t = t;
+        hit_record.point = ray.at(t);
+        // normal and front_face are arbitrary inside a volume
+        hit_record.normal = Vec3::new(1.0, 0.0, 0.0);
+        hit_record.front_face = true;
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        self.boundary.bounding_box(time_start, time_end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    fn boundary() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5))),
+        ))
+    }
+
+    #[test]
+    fn dense_medium_scatters_inside_boundary() {
+        let medium = ConstantMedium::new_color(boundary(), 1.0e6, Vec3::new(1.0, 1.0, 1.0));
+        let ray = Ray::new_at_time(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let hit = medium.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!(hit.t > 3.99 && hit.t < 4.01);
+    }
+
+    #[test]
+    fn ray_missing_boundary_misses_medium() {
+        let medium = ConstantMedium::new_color(boundary(), 1.0e6, Vec3::new(1.0, 1.0, 1.0));
+        let ray = Ray::new_at_time(Vec3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(medium.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn zero_density_texture_never_scatters() {
+        let medium = ConstantMedium::new_varying(
+            boundary(),
+            1.0e3,
+            Arc::new(SolidColor::new_color(Vec3::new(0.0, 0.0, 0.0))),
+            Arc::new(SolidColor::new_color(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let ray = Ray::new_at_time(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(medium.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "max_density must be positive")]
+    fn varying_density_needs_a_positive_bound() {
+        ConstantMedium::new_varying(
+            boundary(),
+            -1.0,
+            Arc::new(SolidColor::new_color(Vec3::new(0.0, 0.0, 0.0))),
+            Arc::new(SolidColor::new_color(Vec3::new(1.0, 1.0, 1.0))),
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Constructive solid geometry: shapes combined as sets of points.
+//!
+//! The children must be closed, so every hit is either where a ray enters one or where it
+//! leaves it, which `front_face` tells apart. Walking along all the hits of both children
+//! tracks whether the ray is inside each, and the combination has a surface wherever
+//! being inside it changes.
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use std::sync::Arc;
+
+/// Everything inside either child.
+pub struct CsgUnion {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+}
+
+impl CsgUnion {
+    pub fn new(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Self {
+        CsgUnion { left, right }
+    }
+}
+
+impl Hittable for CsgUnion {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_all(ray, t_min, t_max).into_iter().next()
+    }
+
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        combine(&*self.left, &*self.right, ray, t_min, t_max, |l, r| l || r)
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        let left = self.left.bounding_box(time_start, time_end)?;
+        let right = self.right.bounding_box(time_start, time_end)?;
+        Some(AABB::surrounding_box(&left, &right))
+    }
+}
+
+/// Everything inside both children.
+pub struct CsgIntersection {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+}
+
+impl CsgIntersection {
+    pub fn new(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Self {
+        CsgIntersection { left, right }
+    }
+}
+
+impl Hittable for CsgIntersection {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_all(ray, t_min, t_max).into_iter().next()
+    }
+
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        combine(&*self.left, &*self.right, ray, t_min, t_max, |l, r| l && r)
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        match (
+            self.left.bounding_box(time_start, time_end),
+            self.right.bounding_box(time_start, time_end),
+        ) {
+            (Some(left), Some(right)) => Some(overlap(&left, &right)),
+            (left, right) => left.or(right),
+        }
+    }
+}
+
+/// Everything inside the first child but not the second. Where the second child cuts into
+/// the first, its surface faces the other way.
+pub struct CsgDifference {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+}
+
+impl CsgDifference {
+    pub fn new(left: Arc<dyn Hittable>, right: Arc<dyn Hittable>) -> Self {
+        CsgDifference { left, right }
+    }
+}
+
+impl Hittable for CsgDifference {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_all(ray, t_min, t_max).into_iter().next()
+    }
+
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        combine(&*self.left, &*self.right, ray, t_min, t_max, |l, r| l && !r)
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        self.left.bounding_box(time_start, time_end)
+    }
+}
+
+/// The hits of the combination of `left` and `right` between `t_min` and `t_max`, where
+/// `inside` says whether a point is in it given whether it's in each child.
+fn combine<F: Fn(bool, bool) -> bool>(
+    left: &dyn Hittable,
+    right: &dyn Hittable,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+    inside: F,
+) -> Vec<HitRecord> {
+    // Further hits decide whether the ray starts inside a child, so both are followed to
+    // the end; only hits of the combination are cut off at `t_max`.
+    let left_hits = left.hit_all(ray, t_min, f64::INFINITY);
+    let right_hits = right.hit_all(ray, t_min, f64::INFINITY);
+    // leaving a child first means the ray started inside it
+    let starts_inside = |hits: &[HitRecord]| hits.first().is_some_and(|h| !h.front_face);
+    let mut in_left = starts_inside(&left_hits);
+    let mut in_right = starts_inside(&right_hits);
+    let mut was_inside = inside(in_left, in_right);
+
+    let mut events: Vec<(HitRecord, bool)> = left_hits
+        .into_iter()
+        .map(|h| (h, true))
+        .chain(right_hits.into_iter().map(|h| (h, false)))
+        .collect();
+    events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+    let mut hits = Vec::new();
+    for (mut hit, from_left) in events {
+        if hit.t > t_max {
+            break;
+        }
+        if from_left {
+            in_left = hit.front_face;
+        } else {
+            in_right = hit.front_face;
+        }
+        let is_inside = inside(in_left, in_right);
+        if is_inside != was_inside {
+            // the normal already faces the ray; only which side is the outside can change
+            hit.front_face = is_inside;
+            hits.push(hit);
+            was_inside = is_inside;
+        }
+    }
+    hits
+}
+
+/// The box both boxes cover, empty boxes shrinking to a point.
+fn overlap(a: &AABB, b: &AABB) -> AABB {
+    let min = Vec3::new(
+        a.min.x.max(b.min.x),
+        a.min.y.max(b.min.y),
+        a.min.z.max(b.min.z),
+    );
+    let max = Vec3::new(
+        a.max.x.min(b.max.x).max(min.x),
+        a.max.y.min(b.max.y).max(min.y),
+        a.max.z.min(b.max.z).max(min.z),
+    );
+    AABB::new(min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::mybox::MyBox;
+    use crate::sphere::Sphere;
+
+    fn material(shade: f64) -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_color(Vec3::new(shade, shade, shade)))
+    }
+
+    fn sphere(x: f64, radius: f64) -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Vec3::new(x, 0.0, 0.0), radius, material(0.5)))
+    }
+
+    fn along_x(x: f64) -> Ray {
+        Ray::new_at_time(Vec3::new(x, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0)
+    }
+
+    fn ts(hits: &[HitRecord]) -> Vec<(f64, bool)> {
+        hits.iter()
+            .map(|h| ((h.t * 1e6).round() / 1e6, h.front_face))
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_spheres() {
+        // spheres from -1 to 1 and from 0 to 2 along the x axis
+        let (a, b) = (sphere(0.0, 1.0), sphere(1.0, 1.0));
+        let ray = along_x(-5.0);
+        let union = CsgUnion::new(a.clone(), b.clone());
+        assert_eq!(
+            ts(&union.hit_all(&ray, 0.001, 100.0)),
+            [(4.0, true), (7.0, false)]
+        );
+        let intersection = CsgIntersection::new(a.clone(), b.clone());
+        assert_eq!(
+            ts(&intersection.hit_all(&ray, 0.001, 100.0)),
+            [(5.0, true), (6.0, false)]
+        );
+        let difference = CsgDifference::new(a.clone(), b.clone());
+        assert_eq!(
+            ts(&difference.hit_all(&ray, 0.001, 100.0)),
+            [(4.0, true), (5.0, false)]
+        );
+        // from inside the lens the first hit is on the way out
+        let hit = intersection.hit(&along_x(0.5), 0.001, 100.0).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-9 && !hit.front_face);
+        assert!(union.hit(&ray, 0.001, 3.0).is_none());
+        let bounds = intersection.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!((bounds.min.x, bounds.max.x), (0.0, 1.0));
+    }
+
+    #[test]
+    fn cut_surfaces_face_the_other_way() {
+        // a sphere with a corner cut out by a box, seen along the axis through the cut
+        let white = material(0.9);
+        let cube = Arc::new(MyBox::new(
+            Vec3::new(0.0, -0.5, -0.5),
+            Vec3::new(3.0, 0.5, 0.5),
+            white.clone(),
+        ));
+        let carved = CsgDifference::new(sphere(0.0, 1.0), cube);
+        let from_right = Ray::new_at_time(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let hit = carved.hit(&from_right, 0.001, 100.0).unwrap();
+        // the box's wall at x = 0, whose outward normal points away from the ray
+        assert!((hit.t - 5.0).abs() < 1e-9);
+        assert!(hit.front_face);
+        assert!((hit.normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!(Arc::ptr_eq(&hit.material, &white));
+    }
+}
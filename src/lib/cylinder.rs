@@ -0,0 +1,279 @@
+use crate::aabb::AABB;
+use crate::frame::{Frame, Motion};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::polynomial::quadratic_roots;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// A cylinder from the center of its `base` to the center of its `top`, closed by flat caps
+/// unless made `open`.
+///
+/// The side has u going around the axis and v from the base to the top. The caps map the
+/// square around them to 0 to 1, like a `Disk`.
+pub struct Cylinder {
+    /// Origin halfway along the axis
+    frame: Frame,
+    radius: f64,
+    half_height: f64,
+    capped: bool,
+    material: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, top: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        Cylinder::new_moving(base, top, radius, Motion::none(), material)
+    }
+
+    pub fn new_moving(
+        base: Vec3,
+        top: Vec3,
+        radius: f64,
+        motion: Motion,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Cylinder {
+            frame: Frame::new((base + top) * 0.5, top - base, motion),
+            radius,
+            half_height: 0.5 * (top - base).length(),
+            capped: true,
+            material,
+        }
+    }
+
+    /// Leaves out the caps, so only the side is hit.
+    pub fn open(mut self) -> Self {
+        self.capped = false;
+        self
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = self.frame.to_local(ray);
+        let (o, d) = (local.origin, local.direction);
+        let (r, h) = (self.radius, self.half_height);
+        // nearest hit so far: distance, normal and texture coordinates
+        let mut closest: Option<(f64, Vec3, (f64, f64))> = None;
+
+        let side = quadratic_roots(
+            d.x * d.x + d.y * d.y,
+            2.0 * (o.x * d.x + o.y * d.y),
+            o.x * o.x + o.y * o.y - r * r,
+        );
+        for t in side {
+            let p = local.at(t);
+            if t >= t_min && t <= t_max && p.z.abs() <= h {
+                let uv = (around_axis(&p), (p.z + h) / (2.0 * h));
+                closest = Some((t, Vec3::new(p.x, p.y, 0.0), uv));
+                break;
+            }
+        }
+        if self.capped {
+            for z in [-h, h] {
+                if let Some((t, uv)) = cap(&local, z, r, t_min, t_max) {
+                    if closest.is_none_or(|(closest, _, _)| t < closest) {
+                        closest = Some((t, Vec3::new(0.0, 0.0, z), uv));
+                    }
+                }
+            }
+        }
+
+        let (t, normal, uv) = closest?;
+        Some(self.frame.hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        let (r, h) = (self.radius, self.half_height);
+        let local = AABB::new(Vec3::new(-r, -r, -h), Vec3::new(r, r, h));
+        Some(self.frame.bounding_box(&local, time_start, time_end))
+    }
+}
+
+/// A cone from the center of its round `base` to its tip at `apex`, closed at the base
+/// unless made `open`.
+///
+/// The side has u going around the axis and v from the base to the apex. The base maps the
+/// square around it to 0 to 1, like a `Disk`.
+pub struct Cone {
+    /// Origin at the center of the base
+    frame: Frame,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    material: Arc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, apex: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        Cone::new_moving(base, apex, radius, Motion::none(), material)
+    }
+
+    pub fn new_moving(
+        base: Vec3,
+        apex: Vec3,
+        radius: f64,
+        motion: Motion,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Cone {
+            frame: Frame::new(base, apex - base, motion),
+            radius,
+            height: (apex - base).length(),
+            capped: true,
+            material,
+        }
+    }
+
+    /// Leaves out the base, so only the side is hit.
+    pub fn open(mut self) -> Self {
+        self.capped = false;
+        self
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = self.frame.to_local(ray);
+        let (o, d) = (local.origin, local.direction);
+        let h = self.height;
+        // the radius shrinks by k for every unit towards the apex
+        let k = self.radius / h;
+        let k2 = k * k;
+        let mut closest: Option<(f64, Vec3, (f64, f64))> = None;
+
+        let side = quadratic_roots(
+            d.x * d.x + d.y * d.y - k2 * d.z * d.z,
+            2.0 * (o.x * d.x + o.y * d.y + k2 * (h - o.z) * d.z),
+            o.x * o.x + o.y * o.y - k2 * (h - o.z) * (h - o.z),
+        );
+        for t in side {
+            let p = local.at(t);
+            if t >= t_min && t <= t_max && (0.0..=h).contains(&p.z) {
+                let normal = Vec3::new(p.x, p.y, k2 * (h - p.z));
+                let normal = if normal.near_zero() {
+                    // the tip
+                    Vec3::new(0.0, 0.0, 1.0)
+                } else {
+                    normal
+                };
+                closest = Some((t, normal, (around_axis(&p), p.z / h)));
+                break;
+            }
+        }
+        if self.capped {
+            if let Some((t, uv)) = cap(&local, 0.0, self.radius, t_min, t_max) {
+                if closest.is_none_or(|(closest, _, _)| t < closest) {
+                    closest = Some((t, Vec3::new(0.0, 0.0, -1.0), uv));
+                }
+            }
+        }
+
+        let (t, normal, uv) = closest?;
+        Some(self.frame.hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        let r = self.radius;
+        let local = AABB::new(Vec3::new(-r, -r, 0.0), Vec3::new(r, r, self.height));
+        Some(self.frame.bounding_box(&local, time_start, time_end))
+    }
+}
+
+/// Angle of `p` around the z axis as a fraction of a turn.
+pub(crate) fn around_axis(p: &Vec3) -> f64 {
+    (p.y.atan2(p.x) + PI) / (2.0 * PI)
+}
+
+/// Where a local ray crosses the disk of `radius` around the z axis at height `z`, and the
+/// texture coordinates there.
+fn cap(local: &Ray, z: f64, radius: f64, t_min: f64, t_max: f64) -> Option<(f64, (f64, f64))> {
+    if local.direction.z == 0.0 {
+        return None;
+    }
+    let t = (z - local.origin.z) / local.direction.z;
+    let p = local.at(t);
+    if t < t_min || t > t_max || p.x * p.x + p.y * p.y > radius * radius {
+        return None;
+    }
+    Some((t, (0.5 + 0.5 * p.x / radius, 0.5 + 0.5 * p.y / radius)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray::new_at_time(origin, direction, 0.0)
+    }
+
+    #[test]
+    fn cylinder_side_and_caps() {
+        let cylinder = Cylinder::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            0.5,
+            material(),
+        );
+        let side = cylinder
+            .hit(
+                &ray(Vec3::new(5.0, 2.5, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+                0.001,
+                100.0,
+            )
+            .unwrap();
+        assert!((side.t - 4.5).abs() < 1e-9);
+        assert!((side.normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((side.v - 0.75).abs() < 1e-9);
+
+        let down = ray(Vec3::new(0.2, 5.0, 0.1), Vec3::new(0.0, -1.0, 0.0));
+        let top = cylinder.hit(&down, 0.001, 100.0).unwrap();
+        assert!((top.t - 2.0).abs() < 1e-9);
+        assert!((top.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        // without caps the ray passes through
+        assert!(cylinder.open().hit(&down, 0.001, 100.0).is_none());
+    }
+
+    #[test]
+    fn cone_narrows_towards_the_apex() {
+        let cone = Cone::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            1.0,
+            material(),
+        );
+        // halfway up, the radius is 0.5 and the normal leans up at 1 in 2
+        let hit = cone
+            .hit(
+                &ray(Vec3::new(3.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+                0.001,
+                100.0,
+            )
+            .unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-9);
+        let expected = Vec3::unit_vector(Vec3::new(2.0, 1.0, 0.0));
+        assert!((hit.normal - expected).length() < 1e-9);
+        let base = cone
+            .hit(
+                &ray(Vec3::new(0.9, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+                0.001,
+                100.0,
+            )
+            .unwrap();
+        assert!((base.t - 1.0).abs() < 1e-9 && base.front_face && base.normal.y < 0.0);
+        assert!(cone
+            .hit(
+                &ray(Vec3::new(0.6, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+                0.001,
+                100.0
+            )
+            .is_some_and(|h| (h.t - 2.2).abs() < 1e-9));
+    }
+}
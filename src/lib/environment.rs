@@ -0,0 +1,308 @@
+//! Light arriving from infinitely far away, seen by rays that leave the scene.
+
+use crate::error::Error;
+use crate::pdf::Distribution1D;
+use crate::sampler::Sampler;
+use crate::spectrum;
+use crate::vec::Vec3;
+use rand::Rng;
+use stb_image::image::LoadResult;
+use std::f64::consts::PI;
+use std::fmt;
+use std::path::Path;
+
+/// What lies beyond the scene in every direction.
+pub trait Environment: Send + Sync + fmt::Display {
+    /// Radiance arriving from the unit vector `direction`, the one seen looking along it.
+    fn radiance(&self, direction: &Vec3) -> Vec3;
+
+    /// Whether the environment can be sampled as a light with `pdf` and `sample`.
+    fn is_sampled(&self) -> bool {
+        false
+    }
+
+    /// Density, with respect to solid angle, of `sample` choosing `direction`.
+    fn pdf(&self, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// A unit direction towards the environment, picked where it's bright.
+    fn sample(&self, _sampler: &mut Sampler) -> Vec3 {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+/// The same color in every direction.
+pub struct SolidBackground {
+    pub color: Vec3,
+}
+
+impl SolidBackground {
+    pub fn new(color: Vec3) -> Self {
+        SolidBackground { color }
+    }
+}
+
+impl Environment for SolidBackground {
+    fn radiance(&self, _direction: &Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+impl fmt::Display for SolidBackground {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.color)
+    }
+}
+
+/// Image based lighting from an equirectangular map, usually a high dynamic range `.hdr`.
+///
+/// The top row of the image looks straight up (+y) and the bottom row straight down. The
+/// middle of the image looks along -z, with +x to its right. Directions are drawn in
+/// proportion to the luminance of the pixels, so small bright features like the sun are
+/// found by light sampling.
+pub struct EnvironmentLight {
+    width: usize,
+    height: usize,
+    /// Linear radiance, row by row from the top
+    pixels: Vec<Vec3>,
+    /// Rotation of the map about the y axis, in degrees
+    rotation: f64,
+    intensity: f64,
+    /// Rows in proportion to their total weight
+    rows: Distribution1D,
+    /// Pixels of each row in proportion to their weight
+    columns: Vec<Distribution1D>,
+}
+
+impl EnvironmentLight {
+    /// A map of `width` by `height` pixels of linear radiance, row by row from the top.
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        let columns: Vec<Distribution1D> = (0..height)
+            .map(|j| {
+                // rows near the poles cover less solid angle
+                let sin_theta = (PI * (j as f64 + 0.5) / height as f64).sin();
+                let weights: Vec<f64> = pixels[j * width..(j + 1) * width]
+                    .iter()
+                    .map(|p| spectrum::luminance(p).max(0.0) * sin_theta)
+                    .collect();
+                Distribution1D::new(&weights)
+            })
+            .collect();
+        let rows = Distribution1D::new(&columns.iter().map(|c| c.total()).collect::<Vec<_>>());
+        EnvironmentLight {
+            width,
+            height,
+            pixels,
+            rotation: 0.0,
+            intensity: 1.0,
+            rows,
+            columns,
+        }
+    }
+
+    /// Loads an image file. High dynamic range formats are read as they are, others are
+    /// taken to be sRGB encoded.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        EnvironmentLight::from_image(stb_image::image::load(&path)).map_err(|e| {
+            Error::argument(&format!(
+                "Unable to load environment map {}: {}",
+                path.as_ref().display(),
+                e
+            ))
+        })
+    }
+
+    fn from_image(image: LoadResult) -> Result<Self, String> {
+        let (width, height, depth, data): (usize, usize, usize, Vec<f64>) = match image {
+            LoadResult::ImageF32(image) => (
+                image.width,
+                image.height,
+                image.depth,
+                image.data.iter().map(|x| *x as f64).collect(),
+            ),
+            LoadResult::ImageU8(image) => (
+                image.width,
+                image.height,
+                image.depth,
+                image
+                    .data
+                    .iter()
+                    .map(|x| (*x as f64 / 255.0).powf(2.2))
+                    .collect(),
+            ),
+            LoadResult::Error(e) => return Err(e),
+        };
+        if width == 0 || height == 0 || depth == 0 {
+            return Err("the image is empty".to_owned());
+        }
+        let pixels = data
+            .chunks(depth)
+            .map(|p| match depth {
+                1 | 2 => Vec3::new(p[0], p[0], p[0]),
+                _ => Vec3::new(p[0], p[1], p[2]),
+            })
+            .collect();
+        Ok(EnvironmentLight::new(width, height, pixels))
+    }
+
+    /// Turns the map about the y axis by `degrees`, counterclockwise seen from above.
+    pub fn rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    /// Scales the radiance of the map.
+    pub fn intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Image coordinates in [0, 1] of `direction`, with v from the top.
+    fn map_coordinates(&self, direction: &Vec3) -> (f64, f64) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        // undo the rotation
+        let x = cos * direction.x - sin * direction.z;
+        let z = sin * direction.x + cos * direction.z;
+        let u = 0.5 + x.atan2(-z) / (2.0 * PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    /// The unit direction at image coordinates `u` and `v`.
+    fn direction_at(&self, u: f64, v: f64) -> Vec3 {
+        let (sin_theta, cos_theta) = (PI * v).sin_cos();
+        let (sin_phi, cos_phi) = (2.0 * PI * (u - 0.5)).sin_cos();
+        let (x, z) = (sin_theta * sin_phi, -sin_theta * cos_phi);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        Vec3::new(cos * x + sin * z, cos_theta, -sin * x + cos * z)
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> (usize, usize) {
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        (i, j)
+    }
+}
+
+impl Environment for EnvironmentLight {
+    fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let (u, v) = self.map_coordinates(direction);
+        let (i, j) = self.pixel(u, v);
+        self.intensity * self.pixels[j * self.width + i]
+    }
+
+    fn is_sampled(&self) -> bool {
+        self.rows.total() > 0.0
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.map_coordinates(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (i, j) = self.pixel(u, v);
+        let probability = self.rows.probability(j) * self.columns[j].probability(i);
+        // uniform within the pixel, and the map covers 2π by π radians
+        probability * (self.width * self.height) as f64 / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn sample(&self, sampler: &mut Sampler) -> Vec3 {
+        let j = self.rows.sample(sampler);
+        let i = self.columns[j].sample(sampler);
+        let u = (i as f64 + sampler.gen::<f64>()) / self.width as f64;
+        let v = (j as f64 + sampler.gen::<f64>()) / self.height as f64;
+        self.direction_at(u, v)
+    }
+}
+
+impl fmt::Display for EnvironmentLight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "environment map {}x{}, rotated {}°, intensity {}",
+            self.width, self.height, self.rotation, self.intensity
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dim map with one bright pixel.
+    fn map() -> EnvironmentLight {
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Vec3::new(0.1, 0.2, 0.3); width * height];
+        pixels[2 * width + 5] = Vec3::new(50.0, 40.0, 30.0);
+        EnvironmentLight::new(width, height, pixels)
+            .rotation(30.0)
+            .intensity(2.0)
+    }
+
+    #[test]
+    fn directions_round_trip_through_the_map() {
+        let map = map();
+        let mut sampler = Sampler::new(1);
+        for _ in 0..100 {
+            let direction = Vec3::random_unit_vector(&mut sampler);
+            let (u, v) = map.map_coordinates(&direction);
+            assert!((map.direction_at(u, v) - direction).length() < 1e-9);
+        }
+        // without rotation the middle of the map looks along -z, and a quarter to the right +x
+        let unrotated = map.rotation(0.0);
+        assert!((unrotated.direction_at(0.5, 0.5) - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+        assert!((unrotated.direction_at(0.75, 0.5) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((unrotated.direction_at(0.3, 0.0) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn sampling_follows_the_density() {
+        let map = map();
+        assert!(map.is_sampled());
+        let mut sampler = Sampler::new(2);
+
+        // the density integrates to one over the sphere
+        let n = 400_000;
+        let integral: f64 = (0..n)
+            .map(|_| map.pdf(&Vec3::random_unit_vector(&mut sampler)))
+            .sum::<f64>()
+            * 4.0
+            * PI
+            / n as f64;
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+
+        // importance sampled and uniform estimates of the irradiance from above agree
+        let irradiance = |w: &Vec3| map.radiance(w).y * w.y.max(0.0);
+        let sampled: f64 = (0..n)
+            .map(|_| {
+                let w = map.sample(&mut sampler);
+                irradiance(&w) / map.pdf(&w)
+            })
+            .sum::<f64>()
+            / n as f64;
+        let uniform: f64 = (0..n)
+            .map(|_| irradiance(&Vec3::random_unit_vector(&mut sampler)) * 4.0 * PI)
+            .sum::<f64>()
+            / n as f64;
+        assert!(
+            (sampled - uniform).abs() < 0.03 * uniform,
+            "{} vs {}",
+            sampled,
+            uniform
+        );
+    }
+
+    #[test]
+    fn reads_radiance_hdr() {
+        // a 2x1 Radiance RGBE image: 1.0 1.0 1.0 and 0.5 0.25 2.0
+        let mut file = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        file.extend_from_slice(&[128, 128, 128, 129, 32, 16, 128, 130]);
+        let map = EnvironmentLight::from_image(stb_image::image::load_from_memory(&file)).unwrap();
+        assert_eq!((map.width, map.height), (2, 1));
+        assert_eq!(map.pixels[0], Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(map.pixels[1], Vec3::new(0.5, 0.25, 2.0));
+    }
+}
@@ -8,6 +8,8 @@ pub struct Error {
 #[derive(Debug)]
 pub enum Kind {
     IOError(std::io::Error),
+    ParseError { line: usize, column: usize },
+    ArgumentError,
     UnknownError,
 }
 
@@ -22,6 +24,12 @@ impl Error {
         self.explanation = Some(explanation.to_owned());
         self
     }
+    pub fn argument(explanation: &str) -> Self {
+        Error::new(Kind::ArgumentError).explanation(explanation)
+    }
+    pub fn parse(line: usize, column: usize, explanation: &str) -> Self {
+        Error::new(Kind::ParseError { line, column }).explanation(explanation)
+    }
 }
 
 impl std::error::Error for Error {
@@ -36,10 +44,14 @@ impl std::error::Error for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if let Some(ref e) = self.explanation {
-            write!(f, "{}", e)?;
+            write!(f, "{}: ", e)?;
         }
         match &self.kind {
             Kind::IOError(e) => write!(f, "IO Error, caused by: {}", e),
+            Kind::ParseError { line, column } => {
+                write!(f, "Parse Error at line {}, column {}", line, column)
+            }
+            Kind::ArgumentError => write!(f, "Invalid Argument"),
             Kind::UnknownError => write!(f, "Unknown Error"),
         }
     }
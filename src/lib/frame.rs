@@ -0,0 +1,113 @@
+//! Placement of the analytic shapes: each is defined in its own coordinates around the z
+//! axis, and a frame puts it into the world and moves it over time.
+
+use crate::aabb::AABB;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::triangle;
+use crate::vec::Vec3;
+use std::sync::Arc;
+
+/// Straight movement at a constant speed, with the convention of `Sphere::new_moving`: no
+/// offset at `time_start`, `offset` at `time_end`, and the same speed before and after.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Motion {
+    pub offset: Vec3,
+    pub time_start: f64,
+    pub time_end: f64,
+}
+
+impl Motion {
+    pub fn new(offset: Vec3, time_start: f64, time_end: f64) -> Self {
+        Motion {
+            offset,
+            time_start,
+            time_end,
+        }
+    }
+
+    /// Standing still.
+    pub fn none() -> Self {
+        Motion::new(Vec3::new(0.0, 0.0, 0.0), 0.0, 1.0)
+    }
+
+    /// How far the shape has moved at `time`.
+    pub fn at(&self, time: f64) -> Vec3 {
+        if self.offset.near_zero() {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        ((time - self.time_start) / (self.time_end - self.time_start)) * self.offset
+    }
+}
+
+/// Where a shape is: its origin, the direction its z axis points in, and how it moves.
+pub struct Frame {
+    origin: Vec3,
+    basis: Onb,
+    motion: Motion,
+}
+
+impl Frame {
+    pub fn new(origin: Vec3, axis: Vec3, motion: Motion) -> Self {
+        Frame {
+            origin,
+            basis: Onb::build_from_w(&axis),
+            motion,
+        }
+    }
+
+    /// `ray` in the shape's coordinates. The frame doesn't scale, so distances along the
+    /// ray stay the same.
+    pub fn to_local(&self, ray: &Ray) -> Ray {
+        let origin = self.origin + self.motion.at(ray.time);
+        Ray::new_at_time(
+            self.basis.to_local(&(ray.origin - origin)),
+            self.basis.to_local(&ray.direction),
+            ray.time,
+        )
+    }
+
+    /// A direction in the shape's coordinates, such as a normal, turned into the world.
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.basis.local(v)
+    }
+
+    /// The hit of `ray` at `t`, where the shape's outward normal is `normal` in its own
+    /// coordinates.
+    pub fn hit_record(
+        &self,
+        ray: &Ray,
+        t: f64,
+        normal: &Vec3,
+        (u, v): (f64, f64),
+        material: &Arc<dyn Material>,
+    ) -> HitRecord {
+        let mut hit_record = HitRecord::new(material.clone());
+        hit_record.t = t;
+        hit_record.point = ray.at(t);
+        hit_record.set_face_normal(ray, &Vec3::unit_vector(self.vector(normal)));
+        hit_record.u = u;
+        hit_record.v = v;
+        hit_record
+    }
+
+    /// The world box around `local`, a box in the shape's coordinates, wherever the shape
+    /// is between `time_start` and `time_end`.
+    pub fn bounding_box(&self, local: &AABB, time_start: f64, time_end: f64) -> AABB {
+        let mut corners = Vec::with_capacity(16);
+        for time in [time_start, time_end] {
+            let origin = self.origin + self.motion.at(time);
+            for i in 0..8 {
+                let corner = Vec3::new(
+                    if i & 1 == 0 { local.min.x } else { local.max.x },
+                    if i & 2 == 0 { local.min.y } else { local.max.y },
+                    if i & 4 == 0 { local.min.z } else { local.max.z },
+                );
+                corners.push(origin + self.basis.local(&corner));
+            }
+        }
+        triangle::bounds(&corners)
+    }
+}
@@ -1,6 +1,9 @@
 use crate::aabb::AABB;
+use crate::animation::Animation;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::transform::Transform;
 use crate::vec::Vec3;
 use std::sync::Arc;
 
@@ -13,6 +16,9 @@ pub struct HitRecord {
     pub v: f64,
     pub front_face: bool,
     pub material: Arc<dyn Material>,
+    /// Wavelength carried by the ray in spectral mode, for materials that depend on it.
+    /// Hittables leave it `None` and the integrator fills it in.
+    pub wavelength: Option<f64>,
 }
 
 impl HitRecord {
@@ -25,6 +31,7 @@ impl HitRecord {
             v: 0.0,
             front_face: true,
             material,
+            wavelength: None,
         }
     }
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: &Vec3) {
@@ -40,128 +47,304 @@ impl HitRecord {
 pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB>;
+
+    /// Every hit between `t_min` and `t_max`, nearest first, so constructive solid geometry
+    /// can tell where a ray enters and leaves a shape. By default the hits are found one
+    /// after the other with `hit`.
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        let mut hits = Vec::new();
+        let mut t = t_min;
+        while let Some(hit) = self.hit(ray, t, t_max) {
+            // just past the last hit, so a surface isn't found twice
+            t = hit.t + 1e-9 * hit.t.abs().max(1.0);
+            hits.push(hit);
+        }
+        hits
+    }
+
+    /// Density, with respect to solid angle, of `random` choosing `direction` from `origin`.
+    /// Only hittables that can be sampled as lights need to implement this.
+    fn pdf_value(&self, _origin: &Vec3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// A direction from `origin` towards a random point on the hittable.
+    fn random(&self, _origin: &Vec3, _sampler: &mut Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
-pub struct Translate {
+/// A hittable placed in the world by an affine transform.
+///
+/// Rays are carried into the object's space, so many instances can share one object.
+pub struct Instance {
     hittable: Arc<dyn Hittable>,
-    offset: Vec3,
+    /// From object to world space
+    transform: Transform,
+}
+
+impl Instance {
+    pub fn new(hittable: Arc<dyn Hittable>, transform: Transform) -> Self {
+        Instance {
+            hittable,
+            transform,
+        }
+    }
+}
+
+/// `ray` carried from world space into the object space of `transform`.
+fn object_ray(transform: &Transform, ray: &Ray) -> Ray {
+    // the direction isn't normalized, so t is the same in both spaces
+    Ray::new_at_time(
+        transform.inverse.transform_point(&ray.origin),
+        transform.inverse.transform_vector(&ray.direction),
+        ray.time,
+    )
+}
+
+/// A hit in the object space of `transform` carried back into world space.
+fn world_hit(transform: &Transform, mut hit: HitRecord) -> HitRecord {
+    hit.point = transform.point(&hit.point);
+    // already facing the ray, and an affine map keeps it that way
+    hit.normal = Vec3::unit_vector(transform.normal(&hit.normal));
+    hit
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let hit = self
+            .hittable
+            .hit(&object_ray(&self.transform, ray), t_min, t_max)?;
+        Some(world_hit(&self.transform, hit))
+    }
+
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        let hits = self
+            .hittable
+            .hit_all(&object_ray(&self.transform, ray), t_min, t_max);
+        hits.into_iter()
+            .map(|hit| world_hit(&self.transform, hit))
+            .collect()
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        self.hittable
+            .bounding_box(time_start, time_end)
+            .map(|b| self.transform.bounding_box(&b))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        // solid angle changes under non-uniform scaling by |det M⁻¹| / |M⁻¹ω|³
+        let direction = Vec3::unit_vector(*direction);
+        let object_direction = self.transform.inverse.transform_vector(&direction);
+        let object_origin = self.transform.inverse.transform_point(origin);
+        let length = object_direction.length();
+        self.hittable.pdf_value(&object_origin, &object_direction)
+            * self.transform.inverse.determinant3().abs()
+            / (length * length * length)
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let object_origin = self.transform.inverse.transform_point(origin);
+        self.transform
+            .vector(&self.hittable.random(&object_origin, sampler))
+    }
+}
+
+/// A hittable whose transform changes over time, for motion blur. Each ray sees it where it
+/// is at the ray's time.
+pub struct AnimatedInstance {
+    hittable: Arc<dyn Hittable>,
+    animation: Animation,
+}
+
+impl AnimatedInstance {
+    pub fn new(hittable: Arc<dyn Hittable>, animation: Animation) -> Self {
+        AnimatedInstance {
+            hittable,
+            animation,
+        }
+    }
+}
+
+impl Hittable for AnimatedInstance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let transform = self.animation.at(ray.time);
+        let hit = self
+            .hittable
+            .hit(&object_ray(&transform, ray), t_min, t_max)?;
+        Some(world_hit(&transform, hit))
+    }
+
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        let transform = self.animation.at(ray.time);
+        let hits = self
+            .hittable
+            .hit_all(&object_ray(&transform, ray), t_min, t_max);
+        hits.into_iter()
+            .map(|hit| world_hit(&transform, hit))
+            .collect()
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        self.hittable
+            .bounding_box(time_start, time_end)
+            .map(|b| self.animation.bounding_box(&b, time_start, time_end))
+    }
+}
+
+/// Moves a hittable by `offset`; an `Instance` with a translation.
+pub struct Translate {
+    instance: Instance,
 }
 
 impl Translate {
     pub fn new(hittable: Arc<dyn Hittable>, offset: Vec3) -> Self {
-        Translate { hittable, offset }
+        Translate {
+            instance: Instance::new(hittable, Transform::translation(offset)),
+        }
     }
 }
+
 impl Hittable for Translate {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let ray_moved = Ray::new_at_time(ray.origin - self.offset, ray.direction, ray.time);
-        if let Some(mut hit) = self.hittable.hit(&ray_moved, t_min, t_max) {
-            hit.point += self.offset;
-            let normal = hit.normal;
-            hit.set_face_normal(&ray_moved, &normal);
-            Some(hit)
-        } else {
-            None
-        }
+        self.instance.hit(ray, t_min, t_max)
     }
 
     fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
-        if let Some(b) = self.hittable.bounding_box(time_start, time_end) {
-            Some(AABB::new(b.min + self.offset, b.max + self.offset))
-        } else {
-            None
-        }
+        self.instance.bounding_box(time_start, time_end)
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.instance.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        self.instance.random(origin, sampler)
     }
 }
 
+/// Rotates a hittable by `angle` degrees around the y axis; an `Instance` with a rotation.
 pub struct RotateY {
-    hittable: Arc<dyn Hittable>,
-    sin_theta: f64,
-    cos_theta: f64,
-    bounding_box: Option<AABB>,
+    instance: Instance,
 }
 
 impl RotateY {
     pub fn new(hittable: Arc<dyn Hittable>, angle: f64) -> Self {
-        let radians = angle.to_radians();
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
-        let bounding_box = if let Some(bounding_box) = hittable.bounding_box(0.0, 1.0) {
-            let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
-            let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
-
-            for i in 0..2 {
-                for j in 0..2 {
-                    for k in 0..2 {
-                        let x =
-                            i as f64 * bounding_box.max.x + (1.0 - i as f64) * bounding_box.min.x;
-                        let y =
-                            j as f64 * bounding_box.max.y + (1.0 - j as f64) * bounding_box.min.y;
-                        let z =
-                            k as f64 * bounding_box.max.z + (1.0 - k as f64) * bounding_box.min.z;
-
-                        let new_x = cos_theta * x + sin_theta * z;
-                        let new_z = -sin_theta * x + cos_theta * z;
-
-                        let tester = Vec3::new(new_x, y, new_z);
-
-                        min.x = min.x.min(tester.x);
-                        max.x = max.x.max(tester.x);
-
-                        min.y = min.y.min(tester.y);
-                        max.y = max.y.max(tester.y);
-
-                        min.z = min.z.min(tester.z);
-                        max.z = max.z.max(tester.z);
-                    }
-                }
-            }
-            Some(AABB::new(min, max))
-        } else {
-            None
-        };
-
         RotateY {
-            hittable,
-            sin_theta,
-            cos_theta,
-            bounding_box,
+            instance: Instance::new(hittable, Transform::rotation_y(angle)),
         }
     }
 }
 
 impl Hittable for RotateY {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut origin = ray.origin;
-        let mut direction = ray.direction;
+        self.instance.hit(ray, t_min, t_max)
+    }
 
-        origin.x = self.cos_theta * ray.origin.x - self.sin_theta * ray.origin.z;
-        origin.z = self.sin_theta * ray.origin.x + self.cos_theta * ray.origin.z;
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        self.instance.bounding_box(time_start, time_end)
+    }
 
-        direction.x = self.cos_theta * ray.direction.x - self.sin_theta * ray.direction.z;
-        direction.z = self.sin_theta * ray.direction.x + self.cos_theta * ray.direction.z;
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        self.instance.pdf_value(origin, direction)
+    }
 
-        let ray_rotated = Ray::new_at_time(origin, direction, ray.time);
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        self.instance.random(origin, sampler)
+    }
+}
 
-        if let Some(mut hit) = self.hittable.hit(&ray_rotated, t_min, t_max) {
-            let mut point = hit.point;
-            let mut normal = hit.normal;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::sphere::Sphere;
+    use crate::vec::Vec3;
+    use std::f64::consts::PI;
 
-            point.x = self.cos_theta * hit.point.x + self.sin_theta * hit.point.z;
-            point.z = -self.sin_theta * hit.point.x + self.cos_theta * hit.point.z;
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(DiffuseLight::new_color(Vec3::new(1.0, 1.0, 1.0))),
+        ))
+    }
 
-            normal.x = self.cos_theta * hit.normal.x + self.sin_theta * hit.normal.z;
-            normal.z = -self.sin_theta * hit.normal.x + self.cos_theta * hit.normal.z;
+    /// A unit sphere stretched into an ellipsoid with half-axes 3, 1, 1 around (0, 0, -5).
+    fn ellipsoid() -> Instance {
+        let transform = Transform::scaling(Vec3::new(3.0, 1.0, 1.0))
+            .unwrap()
+            .then(&Transform::translation(Vec3::new(0.0, 0.0, -5.0)));
+        Instance::new(unit_sphere(), transform)
+    }
 
-            hit.point = point;
-            hit.set_face_normal(&ray_rotated, &normal);
-            Some(hit)
-        } else {
-            None
-        }
+    #[test]
+    fn instance_hits_transformed_shape() {
+        let ellipsoid = ellipsoid();
+        let ray = Ray::new_at_time(Vec3::new(2.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = ellipsoid.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let expected_z = -5.0 + (1.0 - (2.5_f64 / 3.0).powi(2)).sqrt();
+        assert!((hit.point.z - expected_z).abs() < 1e-9);
+        assert!(hit.front_face);
+        // the normal of x²/9 + y² + z² = 1 is proportional to (x/9, y, z)
+        let expected = Vec3::unit_vector(Vec3::new(2.5 / 9.0, 0.0, hit.point.z + 5.0));
+        assert!((hit.normal - expected).length() < 1e-9);
+
+        let bounds = ellipsoid.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!(bounds.max, Vec3::new(3.0, 1.0, -4.0));
+    }
+
+    #[test]
+    fn animated_instance_moves_with_the_ray_time() {
+        use crate::animation::Keyframe;
+
+        let animation = Animation::new(vec![
+            Keyframe::new(0.0),
+            Keyframe::new(1.0)
+                .offset(Vec3::new(4.0, 0.0, 0.0))
+                .scale(Vec3::new(2.0, 2.0, 2.0)),
+        ])
+        .unwrap();
+        let moving = AnimatedInstance::new(unit_sphere(), animation);
+        let down = |x: f64, time: f64| {
+            let ray = Ray::new_at_time(Vec3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), time);
+            moving.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.t)
+        };
+        assert_eq!(down(0.0, 0.0), Some(4.0));
+        assert_eq!(down(4.0, 0.0), None);
+        assert!(down(4.0, 1.0).is_some_and(|t| (t - 3.0).abs() < 1e-9));
+        assert!(down(2.0, 0.5).is_some_and(|t| (t - 3.5).abs() < 1e-9));
+
+        let swept = moving.bounding_box(0.0, 1.0).unwrap();
+        assert!((swept.min.x + 1.0).abs() < 1e-9 && (swept.max.x - 6.0).abs() < 1e-9);
+        assert!((swept.max.y - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn inside_hits_keep_their_face() {
+        let ellipsoid = ellipsoid();
+        let ray = Ray::new_at_time(Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let hit = ellipsoid.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!(!hit.front_face);
+        assert!(Vec3::dot(&hit.normal, &ray.direction) < 0.0);
     }
 
-    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
-        self.bounding_box
+    #[test]
+    fn scaled_light_density_integrates_to_one() {
+        let ellipsoid = ellipsoid();
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let mut sampler = Sampler::new(4);
+        let n = 200_000;
+        let sum: f64 = (0..n)
+            .map(|_| ellipsoid.pdf_value(&origin, &Vec3::random_unit_vector(&mut sampler)))
+            .sum();
+        let total = sum / n as f64 * 4.0 * PI;
+        assert!((total - 1.0).abs() < 0.02, "integral is {}", total);
+
+        for _ in 0..100 {
+            let direction = ellipsoid.random(&origin, &mut sampler);
+            assert!(ellipsoid.pdf_value(&origin, &direction) > 0.0);
+        }
     }
 }
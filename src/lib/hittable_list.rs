@@ -1,6 +1,9 @@
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::Vec3;
+use rand::Rng;
 use std::sync::Arc;
 use std::vec::Vec;
 
@@ -42,19 +45,37 @@ impl Hittable for HittableList {
         if self.objects.is_empty() {
             return None;
         }
-        let mut bounding_boxes = self
+        let bounding_boxes = self
             .objects
             .iter()
-            .map(|x| x.bounding_box(time_start, time_end));
-        if bounding_boxes.any(|x| x.is_none()) {
-            return None;
-        }
-        let bounding_boxes = bounding_boxes.map(|x| x.unwrap()).collect::<Vec<AABB>>();
-        let first = bounding_boxes.first().unwrap().clone();
+            .map(|x| x.bounding_box(time_start, time_end))
+            .collect::<Option<Vec<AABB>>>()?;
+        let first = *bounding_boxes.first().unwrap();
         Some(
             bounding_boxes
                 .iter()
                 .fold(first, |b, o| AABB::surrounding_box(&b, o)),
         )
     }
+
+    /// Picks one of the objects uniformly, so the density is the average of theirs.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|o| o.pdf_value(origin, direction))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let index = sampler.gen_range(0..self.objects.len());
+        self.objects[index].random(origin, sampler)
+    }
 }
@@ -0,0 +1,317 @@
+use crate::error::Error;
+use crate::vec::Vec3;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Linear radiance for every pixel of an image, row 0 at the top.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Vec3>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vec3::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Vec3) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Vec3] {
+        &mut self.pixels
+    }
+
+    /// Pixels as 8-bit RGB, gamma-corrected for gamma=2.0.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 3);
+        for p in &self.pixels {
+            bytes.push(to_byte(p.x));
+            bytes.push(to_byte(p.y));
+            bytes.push(to_byte(p.z));
+        }
+        bytes
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), Error> {
+        let file = File::create(&path).map_err(|e| {
+            Error::from(e).explanation(&format!(
+                "Unable to create image file {}",
+                path.as_ref().display()
+            ))
+        })?;
+        let mut writer = BufWriter::new(file);
+        self.write(&mut writer, format)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write(&self, writer: &mut impl Write, format: ImageFormat) -> Result<(), Error> {
+        match format {
+            ImageFormat::PpmAscii => write_ppm_ascii(self, writer),
+            ImageFormat::Ppm => write_ppm(self, writer),
+            ImageFormat::Png => write_png(self, writer),
+            ImageFormat::Pfm => write_pfm(self, writer),
+            ImageFormat::Exr => write_exr(self, writer),
+        }
+    }
+}
+
+fn to_byte(value: f64) -> u8 {
+    // NaN clamps to 0
+    (256.0 * value.max(0.0).sqrt().clamp(0.0, 0.999)) as u8
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    /// Plain text P3 PPM
+    PpmAscii,
+    /// Binary P6 PPM
+    Ppm,
+    Png,
+    /// Portable float map, 32-bit linear RGB
+    Pfm,
+    /// OpenEXR, uncompressed 32-bit linear RGB
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Result<ImageFormat, Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm-ascii" => Ok(ImageFormat::PpmAscii),
+            "ppm" => Ok(ImageFormat::Ppm),
+            "png" => Ok(ImageFormat::Png),
+            "pfm" => Ok(ImageFormat::Pfm),
+            "exr" => Ok(ImageFormat::Exr),
+            _ => Err(Error::argument(&format!("Unknown image format '{}'", name))),
+        }
+    }
+
+    /// The format implied by a file name's extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<ImageFormat, Error> {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some(extension) if extension != "ppm-ascii" => ImageFormat::from_name(extension),
+            _ => Err(Error::argument(&format!(
+                "Can't tell the image format of '{}'",
+                path.as_ref().display()
+            ))),
+        }
+    }
+}
+
+pub fn write_ppm_ascii(image: &Framebuffer, writer: &mut impl Write) -> Result<(), Error> {
+    writeln!(writer, "P3\n{} {}\n255", image.width, image.height)?;
+    for rgb in image.to_rgb8().chunks(3) {
+        writeln!(writer, "{} {} {}", rgb[0], rgb[1], rgb[2])?;
+    }
+    Ok(())
+}
+
+pub fn write_ppm(image: &Framebuffer, writer: &mut impl Write) -> Result<(), Error> {
+    write!(writer, "P6\n{} {}\n255\n", image.width, image.height)?;
+    writer.write_all(&image.to_rgb8())?;
+    Ok(())
+}
+
+pub fn write_png(image: &Framebuffer, writer: &mut impl Write) -> Result<(), Error> {
+    let png_error = |e: png::EncodingError| match e {
+        png::EncodingError::IoError(e) => Error::from(e),
+        e => Error::new(crate::error::Kind::UnknownError)
+            .explanation(&format!("Unable to encode PNG: {}", e)),
+    };
+    let mut encoder = png::Encoder::new(writer, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut png_writer = encoder.write_header().map_err(png_error)?;
+    png_writer
+        .write_image_data(&image.to_rgb8())
+        .map_err(png_error)?;
+    png_writer.finish().map_err(png_error)?;
+    Ok(())
+}
+
+/// Little-endian PFM, which stores rows from the bottom up.
+pub fn write_pfm(image: &Framebuffer, writer: &mut impl Write) -> Result<(), Error> {
+    write!(writer, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+    for y in (0..image.height).rev() {
+        for x in 0..image.width {
+            let p = image.get(x, y);
+            for value in [p.x, p.y, p.z] {
+                writer.write_all(&(value as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_exr_attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Single-part scanline OpenEXR without compression.
+pub fn write_exr(image: &Framebuffer, writer: &mut impl Write) -> Result<(), Error> {
+    const FLOAT: i32 = 2;
+    // channels are stored in alphabetical order
+    let channels = ["B", "G", "R"];
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&20000630_i32.to_le_bytes());
+    header.extend_from_slice(&2_i32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for name in channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&FLOAT.to_le_bytes());
+        channel_list.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+        channel_list.extend_from_slice(&1_i32.to_le_bytes()); // x sampling
+        channel_list.extend_from_slice(&1_i32.to_le_bytes()); // y sampling
+    }
+    channel_list.push(0);
+    write_exr_attribute(&mut header, "channels", "chlist", &channel_list);
+    write_exr_attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for value in [0, 0, image.width as i32 - 1, image.height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    write_exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1.0_f32.to_le_bytes(),
+    );
+    write_exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1.0_f32.to_le_bytes(),
+    );
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // one scanline per block, preceded by a table of block offsets
+    let line_size = image.width * channels.len() * 4;
+    let block_size = 8 + line_size;
+    let first_block = header.len() + image.height * 8;
+    for y in 0..image.height {
+        writer.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(line_size);
+    for y in 0..image.height {
+        line.clear();
+        for channel in 0..channels.len() {
+            for x in 0..image.width {
+                let p = image.get(x, y);
+                let value = match channel {
+                    0 => p.z,
+                    1 => p.y,
+                    _ => p.x,
+                };
+                line.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&(line_size as i32).to_le_bytes())?;
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn gradient() -> Framebuffer {
+        let mut image = Framebuffer::new(3, 2);
+        image.set(0, 0, Vec3::new(1.0, 0.0, 0.0));
+        image.set(2, 1, Vec3::new(0.25, 4.0, -1.0));
+        image
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            ImageFormat::from_path("a/out.PNG").unwrap(),
+            ImageFormat::Png
+        );
+        assert_eq!(ImageFormat::from_path("out.exr").unwrap(), ImageFormat::Exr);
+        assert!(ImageFormat::from_path("out").is_err());
+        assert!(ImageFormat::from_path("out.jpg").is_err());
+    }
+
+    #[test]
+    fn ppm_is_gamma_corrected_bytes() {
+        let mut bytes = Vec::new();
+        write_ppm(&gradient(), &mut bytes).unwrap();
+        let (header, data) = bytes.split_at(11);
+        assert_eq!(header, b"P6\n3 2\n255\n");
+        assert_eq!(data.len(), 18);
+        assert_eq!(&data[..3], &[255, 0, 0]);
+        assert_eq!(&data[15..], &[128, 255, 0]);
+    }
+
+    #[test]
+    fn pfm_keeps_hdr_values_bottom_up() {
+        let mut bytes = Vec::new();
+        write_pfm(&gradient(), &mut bytes).unwrap();
+        let data = &bytes[b"PF\n3 2\n-1.0\n".len()..];
+        assert_eq!(data.len(), 3 * 2 * 3 * 4);
+        // first stored row is the bottom one, so pixel (2, 1) is the third pixel
+        let green = f32::from_le_bytes([data[28], data[29], data[30], data[31]]);
+        assert_eq!(green, 4.0);
+    }
+
+    #[test]
+    fn exr_offsets_point_at_blocks() {
+        let image = gradient();
+        let mut bytes = Vec::new();
+        write_exr(&image, &mut bytes).unwrap();
+        assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+
+        let blocks_start = bytes.len() - 2 * (8 + 3 * 3 * 4);
+        let table = &bytes[blocks_start - 16..blocks_start];
+        let first = u64::from_le_bytes(table[..8].try_into().unwrap()) as usize;
+        let second = u64::from_le_bytes(table[8..].try_into().unwrap()) as usize;
+        assert_eq!(first, blocks_start);
+        assert_eq!(second - first, 8 + 3 * 3 * 4);
+        assert_eq!(&bytes[second..second + 4], &1_i32.to_le_bytes());
+    }
+
+    #[test]
+    fn png_round_trips_through_decoder() {
+        let mut bytes = Vec::new();
+        write_png(&gradient(), &mut bytes).unwrap();
+        let decoder = png::Decoder::new(&bytes[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        assert_eq!(data, gradient().to_rgb8());
+    }
+}
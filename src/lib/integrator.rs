@@ -0,0 +1,391 @@
+use crate::environment::Environment;
+use crate::hittable::{HitRecord, Hittable};
+use crate::light::Light;
+use crate::material::Lobes;
+use crate::pdf::{HittablePdf, Pdf};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::spectrum;
+use crate::vec::Vec3;
+use rand::Rng;
+use std::sync::Arc;
+
+/// Unidirectional path tracer.
+///
+/// At every diffuse or glossy bounce the lights are sampled directly (next-event estimation) in
+/// addition to following the material's own sampling. Light reached both ways is weighted
+/// with the power heuristic, so neither strategy's weak spots show up as noise. An
+/// environment that can be sampled is treated as one more light; when there are other
+/// lights, each bounce samples one of the two at random. Point, spot and directional
+/// lights can't be hit, so every one of them is sampled at every such bounce.
+///
+/// In spectral mode each path carries a single wavelength on its rays, so materials like
+/// dispersive glass can depend on it. Colors along the path are upsampled to their value at
+/// that wavelength, and the result is converted back to RGB.
+pub struct PathTracer<'a> {
+    pub world: &'a dyn Hittable,
+    /// Hittables to sample directly, `None` for a plain random walk
+    pub lights: Option<&'a dyn Hittable>,
+    /// Lights without a surface, reached only by sampling them
+    pub punctual_lights: &'a [Arc<dyn Light>],
+    /// Seen by rays that leave the scene
+    pub background: &'a dyn Environment,
+    pub max_depth: i32,
+    pub spectral: bool,
+}
+
+impl<'a> PathTracer<'a> {
+    pub fn ray_color(&self, ray: &Ray, sampler: &mut Sampler) -> Vec3 {
+        if !self.spectral {
+            return self.trace(ray, self.max_depth, None, sampler);
+        }
+        let wavelength = spectrum::sample_wavelength(sampler);
+        let ray =
+            Ray::new_at_time(ray.origin, ray.direction, ray.time).with_wavelength(Some(wavelength));
+        let radiance = self.trace(&ray, self.max_depth, None, sampler);
+        spectrum::sample_to_rgb(radiance.x, wavelength)
+    }
+
+    /// Probability of sampling `lights` rather than the environment at a bounce.
+    fn lights_probability(&self) -> f64 {
+        match (self.lights.is_some(), self.background.is_sampled()) {
+            (true, true) => 0.5,
+            (true, false) => 1.0,
+            (false, _) => 0.0,
+        }
+    }
+
+    /// `rgb` as seen along `ray`: unchanged without a wavelength, otherwise the value of its
+    /// spectrum at the wavelength in every channel.
+    fn in_spectrum(&self, rgb: Vec3, ray: &Ray) -> Vec3 {
+        match ray.wavelength {
+            Some(wavelength) => {
+                let value = spectrum::rgb_to_spectrum(&rgb, wavelength);
+                Vec3::new(value, value, value)
+            }
+            None => rgb,
+        }
+    }
+
+    /// `scatter_pdf` is the density with which the previous bounce chose `ray`, or `None`
+    /// when light sampling couldn't have produced it (camera rays and specular bounces).
+    fn trace(
+        &self,
+        ray: &Ray,
+        depth: i32,
+        scatter_pdf: Option<f64>,
+        sampler: &mut Sampler,
+    ) -> Vec3 {
+        if depth <= 0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let mut hit = match self.world.hit(ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return self.escaped(ray, scatter_pdf),
+        };
+        hit.wavelength = ray.wavelength;
+
+        let mut color = self.in_spectrum(hit.material.emitted(&hit), ray);
+        if let (Some(lights), Some(scatter_pdf)) = (self.lights, scatter_pdf) {
+            if color != Vec3::new(0.0, 0.0, 0.0) {
+                let light_pdf =
+                    self.lights_probability() * lights.pdf_value(&ray.origin, &ray.direction);
+                color *= power_heuristic(scatter_pdf, light_pdf);
+            }
+        }
+
+        let wo = -Vec3::unit_vector(ray.direction);
+        // specular lobes can't be evaluated towards a light, and the bounce below can only
+        // reach a light if it has a depth left to do so
+        let lobes = hit.material.lobes(&hit);
+        if lobes.intersects(Lobes::DIFFUSE | Lobes::GLOSSY) && depth > 1 {
+            color += self.sample_light(ray, &hit, &wo, sampler);
+            for light in self.punctual_lights {
+                color += self.sample_punctual(&**light, ray, &hit, &wo);
+            }
+        }
+
+        match hit.material.sample(&wo, &hit, sampler) {
+            None => color,
+            Some(sample) => {
+                let scattered = Ray::new_at_time(hit.point, sample.wi, ray.time)
+                    .with_wavelength(ray.wavelength);
+                let scatter_pdf = if sample.lobe.contains(Lobes::SPECULAR) {
+                    None
+                } else {
+                    Some(sample.pdf)
+                };
+                color
+                    + self.in_spectrum(sample.weight, ray)
+                        * self.trace(&scattered, depth - 1, scatter_pdf, sampler)
+            }
+        }
+    }
+
+    /// Radiance of the environment along `ray`, which left the scene. `scatter_pdf` is as
+    /// in `trace`.
+    fn escaped(&self, ray: &Ray, scatter_pdf: Option<f64>) -> Vec3 {
+        let direction = Vec3::unit_vector(ray.direction);
+        let mut radiance = self.in_spectrum(self.background.radiance(&direction), ray);
+        if let Some(scatter_pdf) = scatter_pdf {
+            if self.background.is_sampled() {
+                let light_pdf = (1.0 - self.lights_probability()) * self.background.pdf(&direction);
+                radiance *= power_heuristic(scatter_pdf, light_pdf);
+            }
+        }
+        radiance
+    }
+
+    /// Light arriving directly from a point picked on `lights` or from the environment.
+    fn sample_light(&self, ray: &Ray, hit: &HitRecord, wo: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let lights_probability = self.lights_probability();
+        let (direction, light_pdf, environment) = match self.lights {
+            Some(lights)
+                if lights_probability == 1.0 || sampler.gen::<f64>() < lights_probability =>
+            {
+                let pdf = HittablePdf::new(lights, hit.point);
+                let direction = pdf.generate(sampler);
+                (direction, lights_probability * pdf.value(&direction), false)
+            }
+            _ if self.background.is_sampled() => {
+                let direction = self.background.sample(sampler);
+                let pdf = (1.0 - lights_probability) * self.background.pdf(&direction);
+                (direction, pdf, true)
+            }
+            _ => return Vec3::new(0.0, 0.0, 0.0),
+        };
+        if light_pdf <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let wi = Vec3::unit_vector(direction);
+        let bsdf = hit.material.eval(wo, &wi, hit);
+        if bsdf == Vec3::new(0.0, 0.0, 0.0) {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let to_light =
+            Ray::new_at_time(hit.point, direction, ray.time).with_wavelength(ray.wavelength);
+        // each strategy only counts the light it's weighted against in `trace` and `escaped`
+        let emitted = match (self.world.hit(&to_light, 0.001, f64::INFINITY), environment) {
+            (Some(light_hit), false) => light_hit.material.emitted(&light_hit),
+            (None, true) => self.background.radiance(&wi),
+            _ => return Vec3::new(0.0, 0.0, 0.0),
+        };
+        let weight = power_heuristic(light_pdf, hit.material.pdf(wo, &wi, hit));
+        self.in_spectrum(bsdf, ray) * self.in_spectrum(emitted, ray) * (weight / light_pdf)
+    }
+
+    /// Light arriving directly from a light without a surface, unless something is in the way.
+    fn sample_punctual(&self, light: &dyn Light, ray: &Ray, hit: &HitRecord, wo: &Vec3) -> Vec3 {
+        let sample = match light.sample(&hit.point) {
+            Some(sample) => sample,
+            None => return Vec3::new(0.0, 0.0, 0.0),
+        };
+        let bsdf = hit.material.eval(wo, &sample.direction, hit);
+        if bsdf == Vec3::new(0.0, 0.0, 0.0) {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let to_light =
+            Ray::new_at_time(hit.point, sample.direction, ray.time).with_wavelength(ray.wavelength);
+        if self
+            .world
+            .hit(&to_light, 0.001, sample.distance - 0.001)
+            .is_some()
+        {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        self.in_spectrum(bsdf, ray) * self.in_spectrum(sample.irradiance, ray)
+    }
+}
+
+/// Multiple importance sampling weight of a strategy with density `pdf` against one with `other`.
+fn power_heuristic(pdf: f64, other: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other * other);
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{EnvironmentLight, SolidBackground};
+    use crate::hittable_list::HittableList;
+    use crate::light::PointLight;
+    use crate::material::{Conductor, DiffuseLight, Lambertian, Material};
+    use crate::rectangle::XZRectangle;
+    use crate::texture::SolidColor;
+    use std::f64::consts::PI;
+
+    fn grey() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    /// Hides an environment from light sampling.
+    struct Unsampled<'a>(&'a dyn Environment);
+
+    impl Environment for Unsampled<'_> {
+        fn radiance(&self, direction: &Vec3) -> Vec3 {
+            self.0.radiance(direction)
+        }
+    }
+
+    impl std::fmt::Display for Unsampled<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    fn black() -> SolidBackground {
+        SolidBackground::new(Vec3::new(0.0, 0.0, 0.0))
+    }
+
+    /// A dim sky with a bright patch.
+    fn sky() -> EnvironmentLight {
+        let mut pixels = vec![Vec3::new(0.2, 0.3, 0.5); 8 * 4];
+        pixels[8 + 3] = Vec3::new(20.0, 18.0, 15.0);
+        EnvironmentLight::new(8, 4, pixels)
+    }
+
+    /// Mean radiance seen looking straight down at a floor under a small light.
+    fn mean_radiance(
+        floor: Arc<dyn Material>,
+        background: &dyn Environment,
+        use_lights: bool,
+        spectral: bool,
+    ) -> Vec3 {
+        let mut world = HittableList::new();
+        world.add(Arc::new(XZRectangle::new(
+            -100.0, 100.0, -100.0, 100.0, 0.0, floor,
+        )));
+        let light = Arc::new(XZRectangle::new(
+            -0.5,
+            0.5,
+            -0.5,
+            0.5,
+            2.0,
+            Arc::new(DiffuseLight::new_color(Vec3::new(4.0, 4.0, 4.0))),
+        ));
+        world.add(light.clone());
+        let tracer = PathTracer {
+            world: &world,
+            lights: if use_lights { Some(&*light) } else { None },
+            punctual_lights: &[],
+            background: if use_lights {
+                background
+            } else {
+                &Unsampled(background)
+            },
+            max_depth: 4,
+            spectral,
+        };
+
+        let ray = Ray::new_at_time(Vec3::new(0.7, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let n = 200_000;
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for sample in 0..n {
+            sum += tracer.ray_color(&ray, &mut Sampler::for_sample(5, 0, 0, sample));
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn light_sampling_agrees_with_random_walk() {
+        let rough_silver = || -> Arc<dyn Material> {
+            let roughness = Arc::new(SolidColor::new_color(Vec3::new(0.5, 0.5, 0.5)));
+            Arc::new(Conductor::preset("silver", roughness).unwrap())
+        };
+        for floor in [grey, rough_silver] {
+            let walk = mean_radiance(floor(), &black(), false, false);
+            let sampled = mean_radiance(floor(), &black(), true, false);
+            assert!(
+                (walk.x - sampled.x).abs() < 0.03 * walk.x,
+                "{} vs {}",
+                walk,
+                sampled
+            );
+        }
+    }
+
+    #[test]
+    fn environment_sampling_agrees_with_random_walk() {
+        let sky = sky();
+        let walk = mean_radiance(grey(), &sky, false, false);
+        let sampled = mean_radiance(grey(), &sky, true, false);
+        assert!(
+            (walk - sampled).length() < 0.03 * walk.length(),
+            "{} vs {}",
+            walk,
+            sampled
+        );
+    }
+
+    #[test]
+    fn point_lights_cast_hard_shadows() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(XZRectangle::new(
+            -100.0,
+            100.0,
+            -100.0,
+            100.0,
+            0.0,
+            grey(),
+        )));
+        // a small shade halfway up, off to the side of the point looked at
+        world.add(Arc::new(XZRectangle::new(2.5, 3.5, -0.5, 0.5, 1.0, grey())));
+        let intensity = Vec3::new(8.0, 8.0, 8.0);
+        let punctual_lights: Vec<Arc<dyn Light>> = vec![Arc::new(PointLight::new(
+            Vec3::new(0.0, 2.0, 0.0),
+            intensity,
+        ))];
+        let tracer = PathTracer {
+            world: &world,
+            lights: None,
+            punctual_lights: &punctual_lights,
+            background: &black(),
+            max_depth: 2,
+            spectral: false,
+        };
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let mut sampler = Sampler::new(1);
+
+        // straight below the light, the floor reflects albedo / π of the irradiance I / d²
+        let lit = tracer.ray_color(
+            &Ray::new_at_time(Vec3::new(0.0, 1.0, 0.0), down, 0.0),
+            &mut sampler,
+        );
+        assert!(
+            (lit - intensity * (0.5 / PI / 4.0)).length() < 1e-9,
+            "{}",
+            lit
+        );
+
+        let ray = Ray::new_at_time(Vec3::new(6.0, 0.5, 0.0), down, 0.0);
+        assert_eq!(
+            tracer.ray_color(&ray, &mut sampler),
+            Vec3::new(0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn spectral_mode_keeps_grey_grey() {
+        let rgb = mean_radiance(grey(), &black(), true, false);
+        let spectral = mean_radiance(grey(), &black(), true, true);
+        for (a, b) in [
+            (rgb.x, spectral.x),
+            (rgb.y, spectral.y),
+            (rgb.z, spectral.z),
+        ] {
+            assert!((a - b).abs() < 0.05 * a, "{} vs {}", rgb, spectral);
+        }
+    }
+
+    #[test]
+    fn power_heuristic_weights_sum_to_one() {
+        for (a, b) in [(1.0, 3.0), (0.5, 0.0), (2.0, 2.0)] {
+            assert!((power_heuristic(a, b) + power_heuristic(b, a) - 1.0).abs() < 1e-12);
+        }
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+}
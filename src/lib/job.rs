@@ -1,6 +1,7 @@
+use crate::error::Error;
 use std::vec::Vec;
 
-#[derive(PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Job {
     pub height_range: std::ops::Range<usize>,
     pub width_range: std::ops::Range<usize>,
@@ -12,31 +13,201 @@ impl Job {
             width_range,
         }
     }
+
+    pub fn pixel_count(&self) -> usize {
+        self.height_range.len() * self.width_range.len()
+    }
+}
+
+/// A rectangle of pixels, with y counted from the top of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// The order in which jobs are handed to the render threads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Row by row from the top left
+    Scanline,
+    /// Outwards from the middle of the image, ring by ring
+    Spiral,
+    /// Along a Hilbert curve, so consecutive tiles are neighbours
+    Hilbert,
+    /// The jobs expected to take longest first, so no long job starts last. The renderer
+    /// estimates the cost from the samples each pixel still needs; without an estimate
+    /// the biggest tiles go first.
+    CostliestFirst,
+}
+
+impl TileOrder {
+    pub fn from_name(name: &str) -> Result<TileOrder, Error> {
+        match name {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            "costliest" => Ok(TileOrder::CostliestFirst),
+            _ => Err(Error::argument(&format!(
+                "Unknown tile order '{}', expected scanline, spiral, hilbert or costliest",
+                name
+            ))),
+        }
+    }
 }
 
-pub fn create_jobs(height: usize, width: usize) -> Vec<Job> {
-    let mut jobs = Vec::new();
-    let height = (0..height).collect::<Vec<_>>();
-    let height_chunks = height.chunks(1).map(|s| {
-        let start = *s.first().unwrap();
-        let end = *s.last().unwrap() + 1;
-        start..end
-    });
-    for h in height_chunks.rev() {
-        jobs.push(Job::new(h.clone(), 0..width));
+/// How an image is cut into jobs: tiles of at most `width` by `height` pixels, rendered
+/// in `order`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tiling {
+    pub width: usize,
+    pub height: usize,
+    pub order: TileOrder,
+}
+
+impl Tiling {
+    /// Square tiles in scanline order.
+    pub fn square(size: usize) -> Tiling {
+        Tiling {
+            width: size,
+            height: size,
+            order: TileOrder::Scanline,
+        }
+    }
+
+    pub fn order(mut self, order: TileOrder) -> Tiling {
+        self.order = order;
+        self
+    }
+}
+
+impl Default for Tiling {
+    fn default() -> Self {
+        Tiling::square(32)
+    }
+}
+
+pub fn create_jobs(height: usize, width: usize, tiling: &Tiling) -> Vec<Job> {
+    create_region_jobs(height, Region::new(0, 0, width, height), tiling)
+}
+
+/// Jobs covering `region` of an image `image_height` pixels high, in the order of `tiling`.
+/// Tiles start at the top left of the region, so those on its right and bottom edges may be
+/// smaller. Job ranges use the renderer's row numbering, where row 0 is at the bottom.
+pub fn create_region_jobs(image_height: usize, region: Region, tiling: &Tiling) -> Vec<Job> {
+    let (tile_width, tile_height) = (tiling.width.max(1), tiling.height.max(1));
+    let columns = region.width.div_ceil(tile_width);
+    let rows = region.height.div_ceil(tile_height);
+    if columns == 0 || rows == 0 {
+        return Vec::new();
+    }
+
+    // tile coordinates in scanline order, then rearranged
+    let mut tiles: Vec<(usize, usize)> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .collect();
+    match tiling.order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let centre = ((columns - 1) as f64 / 2.0, (rows - 1) as f64 / 2.0);
+            tiles.sort_by(|a, b| spiral_key(*a, centre).total_cmp(&spiral_key(*b, centre)));
+        }
+        TileOrder::Hilbert => {
+            let size = columns.max(rows).next_power_of_two();
+            tiles.sort_by_key(|&(column, row)| hilbert_index(size, column, row));
+        }
+        TileOrder::CostliestFirst => {}
+    }
+
+    let mut jobs: Vec<Job> = tiles
+        .into_iter()
+        .map(|(column, row)| {
+            // rows from the top of the region, turned into the renderer's numbering
+            let top = region.y + row * tile_height;
+            let bottom = top
+                .saturating_add(tile_height)
+                .min(region.y + region.height);
+            let left = region.x + column * tile_width;
+            let right = left.saturating_add(tile_width).min(region.x + region.width);
+            Job::new(image_height - bottom..image_height - top, left..right)
+        })
+        .collect();
+    if tiling.order == TileOrder::CostliestFirst {
+        sort_by_cost(&mut jobs, |job| job.pixel_count() as f64);
     }
     jobs
 }
 
+/// Puts the jobs with the highest `cost` first, keeping the order of equally costly ones.
+/// Costs must not be negative.
+pub fn sort_by_cost<F: Fn(&Job) -> f64>(jobs: &mut [Job], cost: F) {
+    // the bits of non-negative floats sort like the floats themselves
+    jobs.sort_by_cached_key(|job| std::cmp::Reverse(cost(job).max(0.0).to_bits()));
+}
+
+/// Sorts tiles ring by ring around `centre`, and within a ring by angle.
+fn spiral_key((column, row): (usize, usize), centre: (f64, f64)) -> f64 {
+    let (dx, dy) = (column as f64 - centre.0, row as f64 - centre.1);
+    let ring = dx.abs().max(dy.abs()).round();
+    // the angle, in [0, 2π), only breaks ties within a ring
+    let angle = dy.atan2(dx) + std::f64::consts::PI;
+    ring * 8.0 + angle
+}
+
+/// Position of `x`, `y` along a Hilbert curve filling a `size` by `size` grid, where `size`
+/// is a power of two.
+fn hilbert_index(size: usize, mut x: usize, mut y: usize) -> usize {
+    let mut index = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        index += s * s * ((3 * rx) ^ ry);
+        // rotate the quadrant so the curve continues where the last one ended
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    /// Rows of the given height as wide as the image.
+    fn bands(height: usize) -> Tiling {
+        Tiling {
+            width: usize::MAX,
+            height,
+            order: TileOrder::Scanline,
+        }
+    }
+
     #[test]
     fn construct_jobs() {
-        let first = Job::new(0..1, 0..50);
-        let last = Job::new(99..100, 0..50);
+        let first = Job::new(98..100, 0..50);
+        let last = Job::new(0..2, 0..50);
 
-        let jobs = create_jobs(100, 50);
+        let jobs = create_jobs(100, 50, &bands(2));
         for j in &jobs {
             println!(
                 "{} {} : {} {}",
@@ -44,16 +215,16 @@ mod tests {
             );
         }
         assert_eq!(50, jobs.len());
-        assert!(jobs.contains(&first));
-        assert!(jobs.contains(&last));
+        assert_eq!(jobs[0], first);
+        assert_eq!(jobs[49], last);
     }
 
     #[test]
     fn construct_jobs10() {
-        let first = Job::new(0..1, 0..100);
-        let last = Job::new(199..200, 0..100);
+        let first = Job::new(190..200, 0..100);
+        let last = Job::new(0..10, 0..100);
 
-        let jobs = create_jobs(200, 100);
+        let jobs = create_jobs(200, 100, &bands(10));
         for j in &jobs {
             println!(
                 "{} {} : {} {}",
@@ -64,4 +235,57 @@ mod tests {
         assert!(jobs.contains(&first));
         assert!(jobs.contains(&last));
     }
+
+    #[test]
+    fn tiles_cover_the_region_once_in_every_order() {
+        let region = Region::new(3, 5, 70, 45);
+        for order in [
+            TileOrder::Scanline,
+            TileOrder::Spiral,
+            TileOrder::Hilbert,
+            TileOrder::CostliestFirst,
+        ] {
+            let jobs = create_region_jobs(60, region, &Tiling::square(16).order(order));
+            assert_eq!(jobs.len(), 5 * 3);
+            let mut covered = vec![0; 60 * 80];
+            for job in &jobs {
+                for j in job.height_range.clone() {
+                    for i in job.width_range.clone() {
+                        covered[j * 80 + i] += 1;
+                    }
+                }
+            }
+            // rows 5 to 49 from the top are rows 10 to 54 from the bottom
+            for j in 0..60 {
+                for i in 0..80 {
+                    let inside = (3..73).contains(&i) && (10..55).contains(&j);
+                    assert_eq!(covered[j * 80 + i], usize::from(inside), "{:?}", order);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn orders_start_where_expected() {
+        let tiling = Tiling::square(10);
+        let spiral = create_jobs(50, 50, &tiling.order(TileOrder::Spiral));
+        assert_eq!(spiral[0], Job::new(20..30, 20..30));
+        let costliest = create_jobs(45, 45, &tiling.order(TileOrder::CostliestFirst));
+        assert_eq!(costliest[0].pixel_count(), 100);
+        assert_eq!(costliest.last().unwrap().pixel_count(), 25);
+
+        // consecutive tiles of a Hilbert curve always touch
+        let hilbert = create_jobs(80, 80, &tiling.order(TileOrder::Hilbert));
+        for pair in hilbert.windows(2) {
+            let distance = pair[0]
+                .width_range
+                .start
+                .abs_diff(pair[1].width_range.start)
+                + pair[0]
+                    .height_range
+                    .start
+                    .abs_diff(pair[1].height_range.start);
+            assert_eq!(distance, 10);
+        }
+    }
 }
@@ -1,17 +1,45 @@
-#![feature(destructuring_assignment)]
 pub mod aabb;
+pub mod adaptive;
+pub mod animation;
+pub mod builtin;
+pub mod bvh;
 pub mod bvh_node;
 pub mod camera;
+pub mod capsule;
 pub mod constant_medium;
+pub mod csg;
+pub mod cylinder;
+pub mod environment;
 pub mod error;
+pub mod frame;
 pub mod hittable;
 pub mod hittable_list;
+pub mod image_output;
+pub mod integrator;
 pub mod job;
+pub mod light;
 pub mod material;
+pub mod microfacet;
 pub mod mybox;
+pub mod obj;
+pub mod onb;
+pub mod pdf;
 pub mod perlin;
+pub mod polynomial;
+pub mod progressive;
+pub mod quad;
 pub mod ray;
 pub mod rectangle;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod scene_parser;
+pub mod sdf;
+pub mod sky;
+pub mod spectrum;
 pub mod sphere;
 pub mod texture;
+pub mod torus;
+pub mod transform;
+pub mod triangle;
 pub mod vec;
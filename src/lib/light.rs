@@ -0,0 +1,253 @@
+//! Lights given by where they are and how much light they give off, rather than by an
+//! emissive material on some geometry.
+//!
+//! Point, spot and directional lights have no surface, so rays can never hit them and the
+//! integrator reaches them only by sampling them directly. Area lights are emissive shapes
+//! whose radiance follows from their power; they go into the world like any other object.
+//!
+//! Quantities are physical, taking scene units as metres: power in watts, intensity in
+//! watts per steradian, irradiance in watts per square metre and radiance in watts per
+//! square metre per steradian. A color tints each of them channel by channel.
+
+use crate::material::Sides;
+use crate::vec::Vec3;
+use std::f64::consts::PI;
+
+/// Light reaching a point from a light without a surface.
+pub struct LightSample {
+    /// Unit vector from the point towards the light
+    pub direction: Vec3,
+    /// How far the light is along `direction`, infinite for a directional light
+    pub distance: f64,
+    /// Irradiance on a surface facing the light
+    pub irradiance: Vec3,
+}
+
+/// A light that is a single point or a single direction.
+pub trait Light: Send + Sync {
+    /// The light arriving at `point`, `None` when it doesn't shine there.
+    fn sample(&self, point: &Vec3) -> Option<LightSample>;
+}
+
+/// Shines equally in every direction from one point.
+pub struct PointLight {
+    position: Vec3,
+    intensity: Vec3,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+
+    /// A point light giving off `power` in total.
+    pub fn from_power(position: Vec3, power: Vec3) -> Self {
+        PointLight::new(position, power / (4.0 * PI))
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: &Vec3) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            irradiance: self.intensity / distance_squared,
+        })
+    }
+}
+
+/// A point light limited to a cone. Its intensity is full up to `cone_angle - falloff_angle`
+/// from the axis and fades smoothly to nothing at `cone_angle`.
+pub struct SpotLight {
+    position: Vec3,
+    /// Unit vector along the axis of the cone
+    direction: Vec3,
+    cos_outer: f64,
+    cos_inner: f64,
+    intensity: Vec3,
+}
+
+impl SpotLight {
+    /// A spot light at `position` pointing along `direction`, with angles in degrees.
+    pub fn new(
+        position: Vec3,
+        direction: Vec3,
+        cone_angle: f64,
+        falloff_angle: f64,
+        intensity: Vec3,
+    ) -> Self {
+        let outer = cone_angle.clamp(0.0, 180.0);
+        let inner = (outer - falloff_angle.max(0.0)).max(0.0);
+        SpotLight {
+            position,
+            direction: Vec3::unit_vector(direction),
+            cos_outer: outer.to_radians().cos(),
+            cos_inner: inner.to_radians().cos(),
+            intensity,
+        }
+    }
+
+    /// A spot light giving off `power` in total.
+    pub fn from_power(
+        position: Vec3,
+        direction: Vec3,
+        cone_angle: f64,
+        falloff_angle: f64,
+        power: Vec3,
+    ) -> Self {
+        let light = SpotLight::new(position, direction, cone_angle, falloff_angle, power);
+        // the falloff is symmetric about the middle of the fade, so it integrates over the
+        // cosine like a hard edge halfway through
+        let solid_angle = 2.0 * PI * (1.0 - 0.5 * (light.cos_inner + light.cos_outer));
+        SpotLight {
+            intensity: if solid_angle > 0.0 {
+                power / solid_angle
+            } else {
+                Vec3::new(0.0, 0.0, 0.0)
+            },
+            ..light
+        }
+    }
+
+    /// How much of the intensity leaves at `cos_theta` from the axis.
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            1.0
+        } else if cos_theta <= self.cos_outer {
+            0.0
+        } else {
+            let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: &Vec3) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+        let falloff = self.falloff(-Vec3::dot(&direction, &self.direction));
+        if falloff == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: self.intensity * (falloff / distance_squared),
+        })
+    }
+}
+
+/// Parallel light from infinitely far away, like sunlight.
+pub struct DirectionalLight {
+    /// Unit vector the light travels along
+    direction: Vec3,
+    irradiance: Vec3,
+}
+
+impl DirectionalLight {
+    /// Light travelling along `direction` with `irradiance` on a surface facing it.
+    pub fn new(direction: Vec3, irradiance: Vec3) -> Self {
+        DirectionalLight {
+            direction: Vec3::unit_vector(direction),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: &Vec3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+/// Radiance of a diffuse emitter that gives off `power` in total from `area` on each of
+/// its emitting `sides`.
+pub fn area_radiance(power: Vec3, area: f64, sides: Sides) -> Vec3 {
+    power / (PI * area * sides.count() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    /// Power of a light found by summing its intensity over random directions around it.
+    fn power(light: &dyn Light, position: Vec3) -> Vec3 {
+        let mut sampler = Sampler::new(3);
+        let n = 200_000;
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            // one metre away the irradiance equals the intensity
+            let point = position + Vec3::random_unit_vector(&mut sampler);
+            if let Some(sample) = light.sample(&point) {
+                sum += sample.irradiance;
+            }
+        }
+        sum * (4.0 * PI / n as f64)
+    }
+
+    #[test]
+    fn point_light_falls_off_with_the_square_of_distance() {
+        let light = PointLight::from_power(Vec3::new(0.0, 2.0, 0.0), Vec3::new(100.0, 50.0, 0.0));
+        let sample = light.sample(&Vec3::new(0.0, -2.0, 0.0)).unwrap();
+        assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, 4.0);
+        let expected = 100.0 / (4.0 * PI * 16.0);
+        assert!((sample.irradiance.x - expected).abs() < 1e-12);
+        assert!((sample.irradiance.y - expected / 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spot_light_shines_within_its_cone_with_its_power() {
+        let position = Vec3::new(1.0, 1.0, 1.0);
+        let light = SpotLight::from_power(
+            position,
+            Vec3::new(0.0, -2.0, 0.0),
+            30.0,
+            10.0,
+            Vec3::new(10.0, 10.0, 10.0),
+        );
+        let below = |degrees: f64| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            light.sample(&(position + Vec3::new(sin, -cos, 0.0)))
+        };
+        let full = below(0.0).unwrap().irradiance;
+        assert_eq!(below(19.0).unwrap().irradiance, full);
+        let fading = below(25.0).unwrap().irradiance;
+        assert!(fading.x > 0.0 && fading.x < full.x);
+        assert!(below(31.0).is_none());
+
+        let total = power(&light, position);
+        assert!((total.x - 10.0).abs() < 0.2, "{}", total);
+    }
+
+    #[test]
+    fn directional_light_is_the_same_everywhere() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -3.0, 0.0), Vec3::new(2.0, 2.0, 2.0));
+        for point in [Vec3::new(0.0, 0.0, 0.0), Vec3::new(-50.0, 7.0, 3.0)] {
+            let sample = light.sample(&point).unwrap();
+            assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+            assert_eq!(sample.distance, f64::INFINITY);
+            assert_eq!(sample.irradiance, Vec3::new(2.0, 2.0, 2.0));
+        }
+    }
+}
@@ -1,12 +1,91 @@
 use crate::hittable::HitRecord;
-use crate::ray::Ray;
+use crate::microfacet::{self, Ggx};
+use crate::onb::Onb;
+use crate::pdf::{CosinePdf, Pdf, SpherePdf};
+use crate::sampler::Sampler;
+use crate::spectrum;
 use crate::texture::{SolidColor, Texture};
 use crate::vec::Vec3;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::ops::BitOr;
 use std::sync::Arc;
 
+/// Kinds of scattering a material does, as a set of flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lobes(u8);
+
+impl Lobes {
+    pub const NONE: Lobes = Lobes(0);
+    /// Spread over the whole hemisphere or sphere, like a matte surface.
+    pub const DIFFUSE: Lobes = Lobes(1);
+    /// Concentrated around some direction, like rough metal.
+    pub const GLOSSY: Lobes = Lobes(2);
+    /// A single direction, like a mirror. It can't be evaluated, only sampled.
+    pub const SPECULAR: Lobes = Lobes(4);
+
+    /// Whether every flag in `other` is set.
+    pub fn contains(self, other: Lobes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether any flag in `other` is set.
+    pub fn intersects(self, other: Lobes) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for Lobes {
+    type Output = Lobes;
+
+    fn bitor(self, other: Lobes) -> Lobes {
+        Lobes(self.0 | other.0)
+    }
+}
+
+/// A direction picked by `Material::sample`.
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    /// Unit direction the light arrives from
+    pub wi: Vec3,
+    /// `eval / pdf` for the sampled direction, or the fraction of light carried along a
+    /// specular one
+    pub weight: Vec3,
+    /// Density of the sample with respect to solid angle, 0 for specular samples
+    pub pdf: f64,
+    /// The lobe the sample was drawn from
+    pub lobe: Lobes,
+}
+
+/// How a surface or medium scatters light.
+///
+/// Directions are unit vectors in world space pointing away from the hit: `wo` back along
+/// the incoming ray and `wi` towards where the light comes from.
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)>;
-    fn emitted(&self, _u: f64, _v: f64, _point: &Vec3) -> Vec3 {
+    /// The BSDF times the cosine of `wi` to the normal, the fraction of light arriving from
+    /// `wi` per unit solid angle that leaves along `wo`. Specular lobes aren't included.
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3, _hit_record: &HitRecord) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    /// Density with which `sample` picks `wi`, with respect to solid angle.
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3, _hit_record: &HitRecord) -> f64 {
+        0.0
+    }
+
+    /// A direction for light to arrive from, or `None` if the light is absorbed.
+    fn sample(
+        &self,
+        wo: &Vec3,
+        hit_record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<BsdfSample>;
+
+    /// The kinds of scattering at the hit.
+    fn lobes(&self, hit_record: &HitRecord) -> Lobes;
+
+    /// Radiance given off at the hit, towards where the ray came from.
+    fn emitted(&self, _hit_record: &HitRecord) -> Vec3 {
         Vec3::new(0.0, 0.0, 0.0)
     }
 }
@@ -28,17 +107,40 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        let mut scatter_direction = hit_record.normal + Vec3::random_unit_vector();
-        if scatter_direction.near_zero() {
-            scatter_direction = hit_record.normal;
-        }
-        let scattered = Ray::new_at_time(hit_record.point, scatter_direction, ray.time);
-        let attenuation = self
-            .albedo
-            .value(hit_record.u, hit_record.v, &hit_record.point);
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> Vec3 {
+        self.albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point)
+            * self.pdf(wo, wi, hit_record)
+    }
 
-        Some((attenuation, scattered))
+    fn pdf(&self, _wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> f64 {
+        CosinePdf::new(&hit_record.normal).value(wi)
+    }
+
+    fn sample(
+        &self,
+        _wo: &Vec3,
+        hit_record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<BsdfSample> {
+        let pdf = CosinePdf::new(&hit_record.normal);
+        let wi = Vec3::unit_vector(pdf.generate(sampler));
+        let density = pdf.value(&wi);
+        if density <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi,
+            weight: self
+                .albedo
+                .value(hit_record.u, hit_record.v, &hit_record.point),
+            pdf: density,
+            lobe: Lobes::DIFFUSE,
+        })
+    }
+
+    fn lobes(&self, _hit_record: &HitRecord) -> Lobes {
+        Lobes::DIFFUSE
     }
 }
 
@@ -55,32 +157,130 @@ impl Metal {
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
         }
     }
+
+    /// Density of the direction of `reflected + fuzz * p`, for `p` uniform in the unit ball,
+    /// along the unit vector `wi`: the volume of the fuzz ball inside a thin cone around `wi`,
+    /// per unit solid angle.
+    fn fuzz_pdf(&self, reflected: &Vec3, wi: &Vec3) -> f64 {
+        let b = Vec3::dot(wi, reflected);
+        let discriminant = b * b - reflected.length_squared() + self.fuzz * self.fuzz;
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let far = b + discriminant.sqrt();
+        if far <= 0.0 {
+            return 0.0;
+        }
+        let near = (b - discriminant.sqrt()).max(0.0);
+        (far.powi(3) - near.powi(3)) / (4.0 * PI * self.fuzz.powi(3))
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        let reflected = Vec3::reflect(&Vec3::unit_vector(ray.direction), &hit_record.normal);
-        let scattered = Ray::new_at_time(
-            hit_record.point,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
-            ray.time,
-        );
-        let attenuation = self.albedo;
-        if Vec3::dot(&scattered.direction, &hit_record.normal) > 0.0 {
-            Some((attenuation, scattered))
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> Vec3 {
+        self.albedo * self.pdf(wo, wi, hit_record)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> f64 {
+        if self.fuzz <= 0.0 || Vec3::dot(wi, &hit_record.normal) <= 0.0 {
+            return 0.0;
+        }
+        self.fuzz_pdf(&Vec3::reflect(&-*wo, &hit_record.normal), wi)
+    }
+
+    fn sample(
+        &self,
+        wo: &Vec3,
+        hit_record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<BsdfSample> {
+        let reflected = Vec3::reflect(&-*wo, &hit_record.normal);
+        if self.fuzz <= 0.0 {
+            return Some(BsdfSample {
+                wi: reflected,
+                weight: self.albedo,
+                pdf: 0.0,
+                lobe: Lobes::SPECULAR,
+            });
+        }
+        let direction = reflected + self.fuzz * Vec3::random_in_unit_sphere(sampler);
+        if Vec3::dot(&direction, &hit_record.normal) <= 0.0 {
+            return None;
+        }
+        let wi = Vec3::unit_vector(direction);
+        Some(BsdfSample {
+            wi,
+            weight: self.albedo,
+            pdf: self.fuzz_pdf(&reflected, &wi),
+            lobe: Lobes::GLOSSY,
+        })
+    }
+
+    fn lobes(&self, _hit_record: &HitRecord) -> Lobes {
+        if self.fuzz > 0.0 {
+            Lobes::GLOSSY
         } else {
-            None
+            Lobes::SPECULAR
+        }
+    }
+}
+
+/// Index of refraction as a function of wavelength.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    /// `n = a + b / λ²`, with λ in micrometres
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with λ in micrometres
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl Ior {
+    /// The index at `wavelength` in nanometres. RGB rendering has no wavelength and uses
+    /// the index at the sodium D line, the one glass catalogs quote.
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let micrometres = wavelength.unwrap_or(spectrum::WAVELENGTH_D) / 1000.0;
+        let l2 = micrometres * micrometres;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt()
+            }
+        }
+    }
+
+    /// Index on the far side of the surface at a hit over the index on the near side.
+    fn relative(&self, hit_record: &HitRecord) -> f64 {
+        let ir = self.at(hit_record.wavelength);
+        if hit_record.front_face {
+            ir
+        } else {
+            1.0 / ir
         }
     }
 }
 
 pub struct Dielectric {
-    pub ir: f64, // Index of Refraction
+    pub ior: Ior,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
-        Dielectric { ir }
+        Dielectric {
+            ior: Ior::Constant(ir),
+        }
+    }
+
+    /// A dielectric whose index varies with wavelength, which disperses light in spectral mode.
+    pub fn with_ior(ior: Ior) -> Self {
+        Dielectric { ior }
     }
 
     fn reflectance(cosine: f64, reflection_index: f64) -> f64 {
@@ -90,55 +290,108 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if hit_record.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
-        };
-        let unit_direction = Vec3::unit_vector(ray.direction);
-        let cos_theta = Vec3::dot(&-unit_direction, &hit_record.normal).min(1.0);
+    fn sample(
+        &self,
+        wo: &Vec3,
+        hit_record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<BsdfSample> {
+        let refraction_ratio = 1.0 / self.ior.relative(hit_record);
+        let unit_direction = -*wo;
+        let cos_theta = Vec3::dot(wo, &hit_record.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
         let direction = if cannot_refract
-            || Self::reflectance(cos_theta, refraction_ratio) > rand::random::<f64>()
+            || Self::reflectance(cos_theta, refraction_ratio) > sampler.gen::<f64>()
         {
             Vec3::reflect(&unit_direction, &hit_record.normal)
         } else {
             Vec3::refract(&unit_direction, &hit_record.normal, refraction_ratio)
         };
 
-        let scattered = Ray::new_at_time(hit_record.point, direction, ray.time);
-        Some((attenuation, scattered))
+        Some(BsdfSample {
+            wi: Vec3::unit_vector(direction),
+            weight: Vec3::new(1.0, 1.0, 1.0),
+            pdf: 0.0,
+            lobe: Lobes::SPECULAR,
+        })
+    }
+
+    fn lobes(&self, _hit_record: &HitRecord) -> Lobes {
+        Lobes::SPECULAR
+    }
+}
+
+/// The sides of a surface that give off light, relative to its outward normal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sides {
+    Front,
+    Back,
+    Both,
+}
+
+impl Sides {
+    /// How many sides emit.
+    pub fn count(self) -> usize {
+        match self {
+            Sides::Both => 2,
+            _ => 1,
+        }
     }
 }
 
 pub struct DiffuseLight {
     emit: Arc<dyn Texture>,
+    sides: Sides,
 }
 
 impl DiffuseLight {
     pub fn new(emit: Arc<dyn Texture>) -> Self {
-        DiffuseLight { emit }
+        DiffuseLight {
+            emit,
+            sides: Sides::Both,
+        }
     }
 
     pub fn new_color(color: Vec3) -> Self {
-        DiffuseLight {
-            emit: Arc::new(SolidColor::new_color(color)),
-        }
+        DiffuseLight::new(Arc::new(SolidColor::new_color(color)))
+    }
+
+    /// Emits only from `sides`, both by default.
+    pub fn sides(mut self, sides: Sides) -> Self {
+        self.sides = sides;
+        self
     }
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, _ray: &Ray, _hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+    fn sample(
+        &self,
+        _wo: &Vec3,
+        _hit_record: &HitRecord,
+        _sampler: &mut Sampler,
+    ) -> Option<BsdfSample> {
         None
     }
 
-    fn emitted(&self, u: f64, v: f64, point: &Vec3) -> Vec3 {
-        self.emit.value(u, v, point)
+    fn lobes(&self, _hit_record: &HitRecord) -> Lobes {
+        Lobes::NONE
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Vec3 {
+        let emits = match self.sides {
+            Sides::Front => hit_record.front_face,
+            Sides::Back => !hit_record.front_face,
+            Sides::Both => true,
+        };
+        if emits {
+            self.emit
+                .value(hit_record.u, hit_record.v, &hit_record.point)
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        }
     }
 }
 
@@ -159,11 +412,769 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        let scattered = Ray::new_at_time(hit_record.point, Vec3::random_in_unit_sphere(), ray.time);
-        let attenuation = self
-            .albedo
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> Vec3 {
+        self.albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point)
+            * self.pdf(wo, wi, hit_record)
+    }
+
+    fn pdf(&self, _wo: &Vec3, wi: &Vec3, _hit_record: &HitRecord) -> f64 {
+        SpherePdf.value(wi)
+    }
+
+    fn sample(
+        &self,
+        _wo: &Vec3,
+        hit_record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<BsdfSample> {
+        let wi = SpherePdf.generate(sampler);
+        Some(BsdfSample {
+            wi,
+            weight: self
+                .albedo
+                .value(hit_record.u, hit_record.v, &hit_record.point),
+            pdf: SpherePdf.value(&wi),
+            lobe: Lobes::DIFFUSE,
+        })
+    }
+
+    fn lobes(&self, _hit_record: &HitRecord) -> Lobes {
+        Lobes::DIFFUSE
+    }
+}
+
+/// A scalar material parameter read from a texture, as the mean of its channels.
+fn scalar(texture: &dyn Texture, hit_record: &HitRecord) -> f64 {
+    let value = texture.value(hit_record.u, hit_record.v, &hit_record.point);
+    (value.x + value.y + value.z) / 3.0
+}
+
+/// Shading frame at a hit and `wo` in it.
+fn shading_frame(hit_record: &HitRecord, wo: &Vec3) -> (Onb, Vec3) {
+    let frame = Onb::build_from_w(&hit_record.normal);
+    let mut local = frame.to_local(wo);
+    // shading normals can lean away from the ray
+    local.z = local.z.max(1e-6);
+    (frame, Vec3::unit_vector(local))
+}
+
+/// A sample in the shading frame turned into world space.
+fn world_sample(frame: &Onb, wi: &Vec3, weight: Vec3, pdf: f64, lobe: Lobes) -> BsdfSample {
+    BsdfSample {
+        wi: Vec3::unit_vector(frame.local(wi)),
+        weight,
+        pdf,
+        lobe,
+    }
+}
+
+/// Rough metal with a complex index of refraction `eta + i k` per color channel, reflecting
+/// off a GGX distribution of microfacets.
+pub struct Conductor {
+    pub eta: Arc<dyn Texture>,
+    pub k: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+}
+
+impl Conductor {
+    pub fn new(eta: Arc<dyn Texture>, k: Arc<dyn Texture>, roughness: Arc<dyn Texture>) -> Self {
+        Conductor { eta, k, roughness }
+    }
+
+    /// Measured indices of common metals at the red, green and blue wavelengths.
+    pub fn preset(name: &str, roughness: Arc<dyn Texture>) -> Option<Self> {
+        let (eta, k) = match name {
+            "gold" => (
+                Vec3::new(0.143, 0.374, 1.442),
+                Vec3::new(3.983, 2.386, 1.603),
+            ),
+            "silver" => (
+                Vec3::new(0.155, 0.117, 0.138),
+                Vec3::new(4.828, 3.122, 2.147),
+            ),
+            "copper" => (
+                Vec3::new(0.200, 0.924, 1.102),
+                Vec3::new(3.912, 2.452, 2.142),
+            ),
+            "aluminium" => (
+                Vec3::new(1.657, 0.880, 0.521),
+                Vec3::new(9.224, 6.270, 4.837),
+            ),
+            _ => return None,
+        };
+        Some(Conductor::new(
+            Arc::new(SolidColor::new_color(eta)),
+            Arc::new(SolidColor::new_color(k)),
+            roughness,
+        ))
+    }
+
+    fn fresnel(&self, cos_i: f64, hit_record: &HitRecord) -> Vec3 {
+        let (u, v, p) = (hit_record.u, hit_record.v, &hit_record.point);
+        microfacet::fresnel_conductor(cos_i, &self.eta.value(u, v, p), &self.k.value(u, v, p))
+    }
+}
+
+impl Material for Conductor {
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> Vec3 {
+        let (frame, wo) = shading_frame(hit_record, wo);
+        let wi = frame.to_local(wi);
+        if wi.z <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let ggx = Ggx::from_roughness(scalar(&*self.roughness, hit_record));
+        let h = Vec3::unit_vector(wo + wi);
+        self.fresnel(Vec3::dot(&wo, &h), hit_record) * (ggx.d(&h) * ggx.g(&wo, &wi) / (4.0 * wo.z))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> f64 {
+        let (frame, wo) = shading_frame(hit_record, wo);
+        let wi = frame.to_local(wi);
+        if wi.z <= 0.0 {
+            return 0.0;
+        }
+        let ggx = Ggx::from_roughness(scalar(&*self.roughness, hit_record));
+        let h = Vec3::unit_vector(wo + wi);
+        // reflecting about the normal maps densities by 1 / (4 wo.h)
+        ggx.visible_pdf(&wo, &h) / (4.0 * Vec3::dot(&wo, &h))
+    }
+
+    fn sample(
+        &self,
+        wo: &Vec3,
+        hit_record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<BsdfSample> {
+        let (frame, local_wo) = shading_frame(hit_record, wo);
+        let ggx = Ggx::from_roughness(scalar(&*self.roughness, hit_record));
+        let h = ggx.sample_visible(&local_wo, sampler);
+        let wi = microfacet::reflect(&local_wo, &h);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        // sampling visible normals leaves only Fresnel and the masking of the outgoing ray
+        let weight = self.fresnel(Vec3::dot(&local_wo, &h), hit_record)
+            * (ggx.g(&local_wo, &wi) / ggx.g1(&local_wo));
+        let pdf = ggx.visible_pdf(&local_wo, &h) / (4.0 * Vec3::dot(&local_wo, &h));
+        Some(world_sample(&frame, &wi, weight, pdf, Lobes::GLOSSY))
+    }
+
+    fn lobes(&self, _hit_record: &HitRecord) -> Lobes {
+        Lobes::GLOSSY
+    }
+}
+
+/// A dielectric boundary made of GGX microfacets, in the shading frame, with `eta` the
+/// index on the far side over the index on the side of `wo`.
+///
+/// Transmission doesn't scale radiance by the squared ratio of the indices, like the smooth
+/// `Dielectric`.
+struct RoughBoundary {
+    ggx: Ggx,
+    eta: f64,
+}
+
+impl RoughBoundary {
+    /// Microfacet normal between `wo` and `wi`, or `None` if no facet connects them.
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<Vec3> {
+        let refracted = wi.z < 0.0;
+        let h = if refracted {
+            *wo + self.eta * *wi
+        } else {
+            *wo + *wi
+        };
+        if h.length_squared() == 0.0 {
+            return None;
+        }
+        let h = Vec3::unit_vector(if h.z < 0.0 { -h } else { h });
+        if Vec3::dot(wo, &h) <= 0.0 || (Vec3::dot(wi, &h) < 0.0) != refracted {
+            return None;
+        }
+        Some(h)
+    }
+
+    /// The BSDF times the cosine of `wi`, and whether `wi` is refracted.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> (f64, bool) {
+        let refracted = wi.z < 0.0;
+        let h = match self.half_vector(wo, wi) {
+            Some(h) if wi.z != 0.0 => h,
+            _ => return (0.0, refracted),
+        };
+        let (cos_o, cos_i) = (Vec3::dot(wo, &h), Vec3::dot(wi, &h));
+        let reflectance = microfacet::fresnel_dielectric(cos_o, self.eta);
+        let dg = self.ggx.d(&h) * self.ggx.g(wo, wi);
+        let value = if refracted {
+            let denominator = (cos_o + self.eta * cos_i).powi(2);
+            (1.0 - reflectance) * dg * self.eta * self.eta * (cos_o * cos_i).abs()
+                / (wo.z * denominator)
+        } else {
+            reflectance * dg / (4.0 * wo.z)
+        };
+        (value, refracted)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let h = match self.half_vector(wo, wi) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+        let (cos_o, cos_i) = (Vec3::dot(wo, &h), Vec3::dot(wi, &h));
+        let reflectance = microfacet::fresnel_dielectric(cos_o, self.eta);
+        let visible = self.ggx.visible_pdf(wo, &h);
+        if wi.z < 0.0 {
+            let denominator = (cos_o + self.eta * cos_i).powi(2);
+            (1.0 - reflectance) * visible * self.eta * self.eta * cos_i.abs() / denominator
+        } else {
+            reflectance * visible / (4.0 * cos_o)
+        }
+    }
+
+    /// Reflection or refraction off a sampled microfacet: the direction, whether it was
+    /// refracted, and its weight.
+    fn sample(&self, wo: &Vec3, sampler: &mut Sampler) -> Option<(Vec3, bool, f64)> {
+        let h = self.ggx.sample_visible(wo, sampler);
+        let reflectance = microfacet::fresnel_dielectric(Vec3::dot(wo, &h), self.eta);
+        // picking reflection with the Fresnel probability cancels it from the weight
+        let (wi, refracted) = if sampler.gen::<f64>() < reflectance {
+            (microfacet::reflect(wo, &h), false)
+        } else {
+            (microfacet::refract(wo, &h, self.eta)?, true)
+        };
+        if (wi.z < 0.0) != refracted || wi.z == 0.0 {
+            return None;
+        }
+        Some((wi, refracted, self.ggx.g(wo, &wi) / self.ggx.g1(wo)))
+    }
+}
+
+/// Frosted glass: a dielectric boundary made of GGX microfacets.
+pub struct RoughDielectric {
+    pub ior: Ior,
+    pub roughness: Arc<dyn Texture>,
+}
+
+impl RoughDielectric {
+    pub fn new(ior: Ior, roughness: Arc<dyn Texture>) -> Self {
+        RoughDielectric { ior, roughness }
+    }
+
+    fn boundary(&self, hit_record: &HitRecord) -> RoughBoundary {
+        RoughBoundary {
+            ggx: Ggx::from_roughness(scalar(&*self.roughness, hit_record)),
+            eta: self.ior.relative(hit_record),
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> Vec3 {
+        let (frame, wo) = shading_frame(hit_record, wo);
+        let (value, _) = self.boundary(hit_record).eval(&wo, &frame.to_local(wi));
+        Vec3::new(value, value, value)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> f64 {
+        let (frame, wo) = shading_frame(hit_record, wo);
+        self.boundary(hit_record).pdf(&wo, &frame.to_local(wi))
+    }
+
+    fn sample(
+        &self,
+        wo: &Vec3,
+        hit_record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<BsdfSample> {
+        let (frame, wo) = shading_frame(hit_record, wo);
+        let boundary = self.boundary(hit_record);
+        let (wi, _, weight) = boundary.sample(&wo, sampler)?;
+        Some(world_sample(
+            &frame,
+            &wi,
+            Vec3::new(weight, weight, weight),
+            boundary.pdf(&wo, &wi),
+            Lobes::GLOSSY,
+        ))
+    }
+
+    fn lobes(&self, _hit_record: &HitRecord) -> Lobes {
+        Lobes::GLOSSY
+    }
+}
+
+/// Roughness of the clear coat on `Principled`, as a GGX alpha.
+const CLEARCOAT_ALPHA: f64 = 0.05;
+
+/// A layered all-round material in the spirit of Disney's principled BSDF.
+///
+/// A diffuse base with a sheen at grazing angles sits under a GGX specular layer and an
+/// optional clear coat. `metallic` turns the base into a conductor tinted by `base_color`,
+/// and `transmission` into rough glass of index `ior`. Every parameter but `ior` is a
+/// texture, and scalar ones read the mean of the texture's channels.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    /// Reflectance of the dielectric base, where 0.5 is 4% at normal incidence.
+    pub specular: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub ior: Ior,
+}
+
+/// Parameters of a `Principled` material at one point.
+struct PrincipledParameters {
+    base_color: Vec3,
+    metallic: f64,
+    ggx: Ggx,
+    /// Specular reflectance at normal incidence
+    f0: Vec3,
+    clearcoat: f64,
+    sheen: f64,
+    transmission: f64,
+}
+
+impl Principled {
+    /// A plastic-like dielectric with the given color; the other parameters are public.
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        let constant = |value: f64| -> Arc<dyn Texture> {
+            Arc::new(SolidColor::new_color(Vec3::new(value, value, value)))
+        };
+        Principled {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            clearcoat: constant(0.0),
+            sheen: constant(0.0),
+            transmission: constant(0.0),
+            ior: Ior::Constant(1.5),
+        }
+    }
+
+    fn parameters(&self, hit_record: &HitRecord) -> PrincipledParameters {
+        let base_color = self
+            .base_color
             .value(hit_record.u, hit_record.v, &hit_record.point);
-        Some((attenuation, scattered))
+        let metallic = scalar(&*self.metallic, hit_record).clamp(0.0, 1.0);
+        let dielectric_f0 = 0.08 * scalar(&*self.specular, hit_record).max(0.0);
+        PrincipledParameters {
+            base_color,
+            metallic,
+            ggx: Ggx::from_roughness(scalar(&*self.roughness, hit_record)),
+            f0: (1.0 - metallic) * Vec3::new(dielectric_f0, dielectric_f0, dielectric_f0)
+                + metallic * base_color,
+            clearcoat: scalar(&*self.clearcoat, hit_record).max(0.0),
+            sheen: scalar(&*self.sheen, hit_record).max(0.0),
+            transmission: (1.0 - metallic)
+                * scalar(&*self.transmission, hit_record).clamp(0.0, 1.0),
+        }
+    }
+
+    /// The shading frame at a hit, `wo` in it, and the reflecting layers and the glass.
+    fn layers(
+        &self,
+        wo: &Vec3,
+        hit_record: &HitRecord,
+    ) -> (Onb, Vec3, PrincipledParameters, RoughBoundary) {
+        let (frame, wo) = shading_frame(hit_record, wo);
+        let parameters = self.parameters(hit_record);
+        let glass = RoughBoundary {
+            ggx: parameters.ggx,
+            eta: self.ior.relative(hit_record),
+        };
+        (frame, wo, parameters, glass)
+    }
+}
+
+impl PrincipledParameters {
+    /// Probabilities of sampling the diffuse, specular and clear coat lobes from `wo`.
+    fn lobe_probabilities(&self, wo: &Vec3) -> Option<[f64; 3]> {
+        let diffuse = (1.0 - self.metallic) * (spectrum::luminance(&self.base_color) + self.sheen);
+        let specular = spectrum::luminance(&microfacet::fresnel_schlick(self.f0, wo.z));
+        let clearcoat = 0.25
+            * self.clearcoat
+            * microfacet::fresnel_schlick(Vec3::new(0.04, 0.04, 0.04), wo.z).x;
+        let total = diffuse + specular + clearcoat;
+        if total <= 0.0 {
+            return None;
+        }
+        Some([diffuse / total, specular / total, clearcoat / total])
+    }
+
+    /// The reflecting layers' BRDF, without the transmitted part.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        if wi.z <= 0.0 || wo.z <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let h = Vec3::unit_vector(*wo + *wi);
+        let cos_d = Vec3::dot(wi, &h);
+        let diffuse = (1.0 - self.metallic)
+            * (self.base_color / PI
+                + self.sheen * (1.0 - cos_d).max(0.0).powi(5) * Vec3::new(1.0, 1.0, 1.0));
+        let specular = microfacet::fresnel_schlick(self.f0, cos_d)
+            * self.ggx.d(&h)
+            * (self.ggx.g(wo, wi) / (4.0 * wo.z * wi.z));
+        let coat = Ggx {
+            alpha: CLEARCOAT_ALPHA,
+        };
+        let clearcoat = 0.25
+            * self.clearcoat
+            * microfacet::fresnel_schlick(Vec3::new(0.04, 0.04, 0.04), cos_d).x
+            * coat.d(&h)
+            * coat.g(wo, wi)
+            / (4.0 * wo.z * wi.z);
+        diffuse + specular + Vec3::new(clearcoat, clearcoat, clearcoat)
+    }
+
+    /// Density with which `sample` picks `wi`.
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let probabilities = match self.lobe_probabilities(wo) {
+            Some(p) => p,
+            None => return 0.0,
+        };
+        if wi.z <= 0.0 {
+            return 0.0;
+        }
+        let h = Vec3::unit_vector(*wo + *wi);
+        // reflecting about the normal maps densities by 1 / (4 wo.h)
+        let jacobian = 1.0 / (4.0 * Vec3::dot(wo, &h));
+        let coat = Ggx {
+            alpha: CLEARCOAT_ALPHA,
+        };
+        probabilities[0] * wi.z / PI
+            + probabilities[1] * self.ggx.visible_pdf(wo, &h) * jacobian
+            + probabilities[2] * coat.visible_pdf(wo, &h) * jacobian
+    }
+
+    /// A direction from one of the reflecting lobes, and which kind of lobe it was.
+    fn sample(&self, wo: &Vec3, sampler: &mut Sampler) -> Option<(Vec3, Lobes)> {
+        let probabilities = self.lobe_probabilities(wo)?;
+        let choice = sampler.gen::<f64>();
+        let (wi, lobe) = if choice < probabilities[0] {
+            (Vec3::random_cosine_direction(sampler), Lobes::DIFFUSE)
+        } else if choice < probabilities[0] + probabilities[1] {
+            let h = self.ggx.sample_visible(wo, sampler);
+            (microfacet::reflect(wo, &h), Lobes::GLOSSY)
+        } else {
+            let coat = Ggx {
+                alpha: CLEARCOAT_ALPHA,
+            };
+            let h = coat.sample_visible(wo, sampler);
+            (microfacet::reflect(wo, &h), Lobes::GLOSSY)
+        };
+        if wi.z <= 0.0 {
+            None
+        } else {
+            Some((wi, lobe))
+        }
+    }
+}
+
+/// Mixes the reflecting layers and the glass of a `Principled` material by its transmission.
+fn principled_eval(
+    parameters: &PrincipledParameters,
+    glass: &RoughBoundary,
+    wo: &Vec3,
+    wi: &Vec3,
+) -> Vec3 {
+    let t = parameters.transmission;
+    let mut value = (1.0 - t) * wi.z.max(0.0) * parameters.eval(wo, wi);
+    if t > 0.0 {
+        let (transmitted, refracted) = glass.eval(wo, wi);
+        let tint = if refracted {
+            parameters.base_color
+        } else {
+            Vec3::new(1.0, 1.0, 1.0)
+        };
+        value += t * transmitted * tint;
+    }
+    value
+}
+
+fn principled_pdf(
+    parameters: &PrincipledParameters,
+    glass: &RoughBoundary,
+    wo: &Vec3,
+    wi: &Vec3,
+) -> f64 {
+    let t = parameters.transmission;
+    let mut pdf = (1.0 - t) * parameters.pdf(wo, wi);
+    if t > 0.0 {
+        pdf += t * glass.pdf(wo, wi);
+    }
+    pdf
+}
+
+impl Material for Principled {
+    fn eval(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> Vec3 {
+        let (frame, wo, parameters, glass) = self.layers(wo, hit_record);
+        principled_eval(&parameters, &glass, &wo, &frame.to_local(wi))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, hit_record: &HitRecord) -> f64 {
+        let (frame, wo, parameters, glass) = self.layers(wo, hit_record);
+        principled_pdf(&parameters, &glass, &wo, &frame.to_local(wi))
+    }
+
+    fn sample(
+        &self,
+        wo: &Vec3,
+        hit_record: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<BsdfSample> {
+        let (frame, wo, parameters, glass) = self.layers(wo, hit_record);
+        // pick the glass or the reflecting layers in proportion to their weight
+        let (wi, lobe) = if sampler.gen::<f64>() < parameters.transmission {
+            let (wi, _, _) = glass.sample(&wo, sampler)?;
+            (wi, Lobes::GLOSSY)
+        } else {
+            parameters.sample(&wo, sampler)?
+        };
+        let pdf = principled_pdf(&parameters, &glass, &wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let weight = principled_eval(&parameters, &glass, &wo, &wi) / pdf;
+        Some(world_sample(&frame, &wi, weight, pdf, lobe))
+    }
+
+    fn lobes(&self, _hit_record: &HitRecord) -> Lobes {
+        Lobes::DIFFUSE | Lobes::GLOSSY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glass_disperses_blue_more_than_red() {
+        // Schott N-BK7
+        let bk7 = Ior::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        assert!((bk7.at(Some(587.6)) - 1.5168).abs() < 1e-4);
+        assert!(bk7.at(Some(450.0)) > bk7.at(Some(650.0)));
+        let cauchy = Ior::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        assert!(cauchy.at(Some(450.0)) > cauchy.at(None));
+        assert_eq!(Ior::Constant(1.5).at(Some(450.0)), 1.5);
+    }
+
+    /// A hit on the xy plane facing +z.
+    fn hit_record(material: Arc<dyn Material>) -> HitRecord {
+        let mut hit = HitRecord::new(material);
+        hit.normal = Vec3::new(0.0, 0.0, 1.0);
+        hit.front_face = true;
+        hit
+    }
+
+    fn constant(value: f64) -> Arc<dyn Texture> {
+        Arc::new(SolidColor::new_color(Vec3::new(value, value, value)))
+    }
+
+    /// Checks that a material's samples agree with `pdf` and `eval`, and that they land in
+    /// each cell of a grid over the sphere as often as the integral of `pdf` over the cell
+    /// predicts.
+    fn check_sampling(hit: &HitRecord, wo: &Vec3) {
+        let material = &*hit.material;
+        let (n_theta, n_phi, subdivisions) = (8, 16, 16);
+        let cell = |w: &Vec3| {
+            let i = ((1.0 - w.z) / 2.0 * n_theta as f64) as usize;
+            let phi = w.y.atan2(w.x) + PI;
+            let j = (phi / (2.0 * PI) * n_phi as f64) as usize;
+            i.min(n_theta - 1) * n_phi + j.min(n_phi - 1)
+        };
+
+        let n = 200_000;
+        let mut counts = vec![0usize; n_theta * n_phi];
+        let mut sampler = Sampler::new(7);
+        for _ in 0..n {
+            let sample = match material.sample(wo, hit, &mut sampler) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            counts[cell(&sample.wi)] += 1;
+            let pdf = material.pdf(wo, &sample.wi, hit);
+            assert!(
+                (sample.pdf - pdf).abs() <= 1e-6 * pdf.max(1.0),
+                "{} vs {}",
+                sample.pdf,
+                pdf
+            );
+            let expected = material.eval(wo, &sample.wi, hit) / pdf;
+            assert!(
+                (sample.weight - expected).length() <= 1e-6 * expected.length().max(1.0),
+                "{} vs {}",
+                sample.weight,
+                expected
+            );
+        }
+
+        // equal area cells: uniform in cos(theta) and phi
+        let (d_z, d_phi) = (
+            2.0 / (n_theta * subdivisions) as f64,
+            2.0 * PI / (n_phi * subdivisions) as f64,
+        );
+        let mut expected = vec![0.0; n_theta * n_phi];
+        for a in 0..n_theta * subdivisions {
+            let z = 1.0 - (a as f64 + 0.5) * d_z;
+            let r = (1.0 - z * z).sqrt();
+            for b in 0..n_phi * subdivisions {
+                let phi = (b as f64 + 0.5) * d_phi - PI;
+                let w = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                expected[cell(&w)] += material.pdf(wo, &w, hit) * d_z * d_phi;
+            }
+        }
+        for (count, expected) in counts.iter().zip(expected) {
+            let fraction = *count as f64 / n as f64;
+            let tolerance = 5.0 * (expected / n as f64).sqrt() + 2e-3;
+            assert!(
+                (fraction - expected).abs() < tolerance,
+                "{} vs {}",
+                fraction,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn samplers_match_their_densities() {
+        let wo = Vec3::unit_vector(Vec3::new(0.6, 0.2, 0.7));
+        let mut principled =
+            Principled::new(Arc::new(SolidColor::new_color(Vec3::new(0.8, 0.3, 0.2))));
+        principled.roughness = constant(0.5);
+        principled.sheen = constant(0.5);
+        principled.transmission = constant(0.4);
+        let materials: Vec<Arc<dyn Material>> = vec![
+            Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.6, 0.7))),
+            Arc::new(Metal::new(Vec3::new(0.9, 0.8, 0.7), 0.5)),
+            Arc::new(Isotropic::new_color(Vec3::new(0.5, 0.5, 0.5))),
+            Arc::new(Conductor::preset("copper", constant(0.5)).unwrap()),
+            Arc::new(RoughDielectric::new(Ior::Constant(1.5), constant(0.5))),
+            Arc::new(principled),
+        ];
+        for material in materials {
+            let mut hit = hit_record(material);
+            check_sampling(&hit, &wo);
+            // and from the back of the surface
+            hit.front_face = false;
+            check_sampling(&hit, &wo);
+        }
+    }
+
+    #[test]
+    fn specular_materials_sample_a_single_direction() {
+        let wo = Vec3::unit_vector(Vec3::new(1.0, 0.0, 1.0));
+        let mirror = hit_record(Arc::new(Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0)));
+        assert_eq!(mirror.material.lobes(&mirror), Lobes::SPECULAR);
+        let sample = mirror
+            .material
+            .sample(&wo, &mirror, &mut Sampler::new(1))
+            .unwrap();
+        assert!((sample.wi - Vec3::new(-wo.x, 0.0, wo.z)).length() < 1e-12);
+        assert_eq!((sample.pdf, sample.lobe), (0.0, Lobes::SPECULAR));
+        assert_eq!(
+            mirror.material.eval(&wo, &sample.wi, &mirror),
+            Vec3::new(0.0, 0.0, 0.0)
+        );
+
+        let glass = hit_record(Arc::new(Dielectric::new(1.5)));
+        assert_eq!(glass.material.lobes(&glass), Lobes::SPECULAR);
+        let mut sampler = Sampler::new(2);
+        for _ in 0..100 {
+            let sample = glass.material.sample(&wo, &glass, &mut sampler).unwrap();
+            assert_eq!(sample.lobe, Lobes::SPECULAR);
+            assert!((sample.wi.length() - 1.0).abs() < 1e-12);
+        }
+
+        let light = hit_record(Arc::new(DiffuseLight::new_color(Vec3::new(1.0, 1.0, 1.0))));
+        assert_eq!(light.material.lobes(&light), Lobes::NONE);
+        assert!(light.material.sample(&wo, &light, &mut sampler).is_none());
+    }
+
+    #[test]
+    fn lobes_combine_as_flags() {
+        let lobes = Lobes::DIFFUSE | Lobes::GLOSSY;
+        assert!(lobes.contains(Lobes::DIFFUSE) && lobes.contains(Lobes::GLOSSY));
+        assert!(!lobes.contains(Lobes::DIFFUSE | Lobes::SPECULAR));
+        assert!(lobes.intersects(Lobes::GLOSSY | Lobes::SPECULAR));
+        assert!(!Lobes::NONE.intersects(lobes));
+    }
+
+    #[test]
+    fn smooth_conductor_is_a_mirror() {
+        let gold = hit_record(Arc::new(Conductor::preset("gold", constant(0.0)).unwrap()));
+        let wo = Vec3::unit_vector(Vec3::new(-1.0, 0.0, 1.0));
+        let sample = gold
+            .material
+            .sample(&wo, &gold, &mut Sampler::new(1))
+            .expect("expected a reflection");
+        let mirrored = Vec3::unit_vector(Vec3::new(1.0, 0.0, 1.0));
+        assert!((sample.wi - mirrored).length() < 1e-2);
+        // gold reflects red more than blue
+        assert!(sample.weight.x > 0.9 && sample.weight.z < sample.weight.x);
+    }
+
+    #[test]
+    fn principled_samples_follow_their_density() {
+        let mut material =
+            Principled::new(Arc::new(SolidColor::new_color(Vec3::new(0.8, 0.3, 0.2))));
+        material.roughness = constant(0.4);
+        material.clearcoat = constant(1.0);
+        material.sheen = constant(0.5);
+        material.metallic = constant(0.3);
+        let hit = hit_record(Arc::new(Lambertian::new_color(Vec3::new(0.0, 0.0, 0.0))));
+        let parameters = material.parameters(&hit);
+        let wo = Vec3::unit_vector(Vec3::new(0.5, 0.2, 0.6));
+        let mut sampler = Sampler::new(3);
+
+        // the sampled weights and uniform samples of the BRDF estimate the same albedo
+        let n = 200_000;
+        let (mut sampled, mut uniform) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
+        for _ in 0..n {
+            if let Some((wi, _)) = parameters.sample(&wo, &mut sampler) {
+                sampled += parameters.eval(&wo, &wi) * (wi.z / parameters.pdf(&wo, &wi));
+            }
+            let mut wi = Vec3::random_unit_vector(&mut sampler);
+            wi.z = wi.z.abs();
+            uniform += parameters.eval(&wo, &wi) * (wi.z * 2.0 * PI);
+        }
+        let (sampled, uniform) = (sampled / n as f64, uniform / n as f64);
+        assert!(
+            (sampled - uniform).length() < 0.02,
+            "{} vs {}",
+            sampled,
+            uniform
+        );
+    }
+
+    #[test]
+    fn nearly_smooth_rough_dielectric_refracts_like_glass() {
+        let hit = hit_record(Arc::new(RoughDielectric::new(
+            Ior::Constant(1.5),
+            constant(0.0),
+        )));
+        let direction = Vec3::unit_vector(Vec3::new(1.0, 0.0, -2.0));
+        let expected = Vec3::refract(&direction, &hit.normal, 1.0 / 1.5);
+        let mut refracted = 0;
+        for seed in 0..100 {
+            if let Some(sample) = hit
+                .material
+                .sample(&-direction, &hit, &mut Sampler::new(seed))
+            {
+                if sample.wi.z < 0.0 {
+                    refracted += 1;
+                    assert!((sample.wi - Vec3::unit_vector(expected)).length() < 1e-2);
+                }
+            }
+        }
+        // about 5% are reflected
+        assert!(refracted > 85);
     }
 }
@@ -0,0 +1,199 @@
+//! Building blocks of the microfacet materials: the GGX (Trowbridge-Reitz) distribution of
+//! normals and Fresnel reflectance.
+//!
+//! Directions are in a local shading frame with the surface normal along z, both pointing
+//! away from the surface.
+
+use crate::sampler::Sampler;
+use crate::vec::Vec3;
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// Isotropic GGX distribution of microfacet normals.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    /// Perceptually linear roughness in [0, 1], squared. Perfectly smooth surfaces are
+    /// approximated by a very narrow distribution.
+    pub fn from_roughness(roughness: f64) -> Self {
+        let r = roughness.clamp(0.0, 1.0);
+        Ggx {
+            alpha: (r * r).max(1e-3),
+        }
+    }
+
+    /// Area of microfacets with normal `h` per unit of surface area and solid angle.
+    pub fn d(&self, h: &Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.z * h.z * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    /// Smith's auxiliary function, the ratio of hidden to visible microfacet area along `w`.
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both directions, height correlated.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// A microfacet normal drawn from those visible from `wo`, following Heitz,
+    /// "Sampling the GGX Distribution of Visible Normals" (2018).
+    pub fn sample_visible(&self, wo: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let vh = Vec3::unit_vector(Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z));
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(vh, t1);
+
+        let r = sampler.gen::<f64>().sqrt();
+        let phi = 2.0 * PI * sampler.gen::<f64>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vec3::unit_vector(Vec3::new(
+            self.alpha * nh.x,
+            self.alpha * nh.y,
+            nh.z.max(1e-6),
+        ))
+    }
+
+    /// Density with which `sample_visible` picks `h`, with respect to solid angle.
+    pub fn visible_pdf(&self, wo: &Vec3, h: &Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * Vec3::dot(wo, h).max(0.0) * self.d(h) / wo.z
+    }
+}
+
+/// `wo` mirrored about `h`.
+pub fn reflect(wo: &Vec3, h: &Vec3) -> Vec3 {
+    2.0 * Vec3::dot(wo, h) * *h - *wo
+}
+
+/// `wo` refracted through a facet with normal `h` on its side, where `eta` is the index on
+/// the far side over the index on `wo`'s side. `None` on total internal reflection.
+pub fn refract(wo: &Vec3, h: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = Vec3::dot(wo, h);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wo / eta + (cos_i / eta - cos_t) * *h)
+}
+
+/// Unpolarized reflectance of a dielectric boundary, with `eta` as in `refract`.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (rs * rs + rp * rp) / 2.0
+}
+
+/// Unpolarized reflectance of a conductor with the complex index `eta + i k`, per channel.
+pub fn fresnel_conductor(cos_i: f64, eta: &Vec3, k: &Vec3) -> Vec3 {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos2.sqrt() * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        (rs + rp) / 2.0
+    };
+    Vec3::new(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+/// Schlick's approximation of reflectance, from the reflectance at normal incidence.
+pub fn fresnel_schlick(f0: Vec3, cos_i: f64) -> Vec3 {
+    let weight = (1.0 - cos_i.clamp(0.0, 1.0)).powi(5);
+    f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Midpoint sum of `f` over the upper hemisphere.
+    fn integrate_hemisphere(f: impl Fn(&Vec3) -> f64) -> f64 {
+        let (n_theta, n_phi) = (2000, 64);
+        let (d_theta, d_phi) = (PI / 2.0 / n_theta as f64, 2.0 * PI / n_phi as f64);
+        let mut sum = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let w = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                sum += f(&w) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn distributions_are_normalized() {
+        let wo = Vec3::unit_vector(Vec3::new(0.6, 0.0, 0.8));
+        for roughness in [0.3, 0.7, 1.0] {
+            let ggx = Ggx::from_roughness(roughness);
+            let projected = integrate_hemisphere(|h| ggx.d(h) * h.z);
+            assert!((projected - 1.0).abs() < 1e-3, "{}", projected);
+            let visible = integrate_hemisphere(|h| ggx.visible_pdf(&wo, h));
+            assert!((visible - 1.0).abs() < 1e-3, "{}", visible);
+        }
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        // glass reflects 4% head on and everything at grazing angles
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-9);
+        // total internal reflection from inside
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+        // a conductor without absorption is a dielectric
+        let conductor =
+            fresnel_conductor(0.7, &Vec3::new(1.5, 1.5, 1.5), &Vec3::new(0.0, 0.0, 0.0));
+        assert!((conductor.x - fresnel_dielectric(0.7, 1.5)).abs() < 1e-9);
+    }
+}
@@ -2,8 +2,8 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::hittable_list::HittableList;
 use crate::material::Material;
+use crate::quad::Quad;
 use crate::ray::Ray;
-use crate::rectangle::{XYRectangle, XZRectangle, YZRectangle};
 use crate::vec::Vec3;
 use std::sync::Arc;
 
@@ -15,52 +15,22 @@ pub struct MyBox {
 
 impl MyBox {
     pub fn new(box_min: Vec3, box_max: Vec3, material: Arc<dyn Material>) -> Self {
+        let d = box_max - box_min;
+        let dx = Vec3::new(d.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, d.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, d.z);
+        // u × v points out of the box on every side
         let mut sides = HittableList::new();
-        sides.add(Arc::new(XYRectangle::new(
-            box_min.x,
-            box_max.x,
-            box_min.y,
-            box_max.y,
-            box_max.z,
-            material.clone(),
-        )));
-        sides.add(Arc::new(XYRectangle::new(
-            box_min.x,
-            box_max.x,
-            box_min.y,
-            box_max.y,
-            box_min.z,
-            material.clone(),
-        )));
-
-        sides.add(Arc::new(XZRectangle::new(
-            box_min.x,
-            box_max.x,
-            box_min.z,
-            box_max.z,
-            box_max.y,
-            material.clone(),
-        )));
-        sides.add(Arc::new(XZRectangle::new(
-            box_min.x,
-            box_max.x,
-            box_min.z,
-            box_max.z,
-            box_min.y,
-            material.clone(),
-        )));
-
-        sides.add(Arc::new(YZRectangle::new(
-            box_min.y,
-            box_max.y,
-            box_min.z,
-            box_max.z,
-            box_max.x,
-            material.clone(),
-        )));
-        sides.add(Arc::new(YZRectangle::new(
-            box_min.y, box_max.y, box_min.z, box_max.z, box_min.x, material,
-        )));
+        for (origin, u, v) in [
+            (box_min + dz, dx, dy),
+            (box_min + dx, -dx, dy),
+            (box_min + dx + dz, -dz, dy),
+            (box_min, dz, dy),
+            (box_min + dy + dz, dx, -dz),
+            (box_min, dx, dz),
+        ] {
+            sides.add(Arc::new(Quad::new(origin, u, v, material.clone())));
+        }
         MyBox {
             box_min,
             box_max,
@@ -0,0 +1,475 @@
+//! Wavefront OBJ and MTL import.
+//!
+//! Faces are grouped by object or group name (`o`, `g`) and material (`usemtl`);
+//! every group becomes one `TriangleMesh`. Polygons are triangulated as fans.
+
+use crate::error::Error;
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use crate::triangle::TriangleMesh;
+use crate::vec::Vec3;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Loads an OBJ file and the MTL libraries it references, relative to the OBJ file.
+/// Faces without a known material use `default_material`.
+pub fn load_file<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Material>,
+) -> Result<HittableList, Error> {
+    let path = path.as_ref();
+    let source = read(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    parse(&source, directory, default_material).map_err(|e| in_file(e, path))
+}
+
+/// Builds meshes from the text of an OBJ file. `mtllib` files are read from `directory`.
+pub fn parse(
+    source: &str,
+    directory: &Path,
+    default_material: Arc<dyn Material>,
+) -> Result<HittableList, Error> {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut materials = HashMap::new();
+    let mut groups: Vec<Group> = Vec::new();
+    let mut group_name = String::new();
+    let mut material_name = String::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = Line::new(number + 1, line);
+        let keyword = match line.keyword() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "v" => positions.push(line.vec3()?),
+            "vn" => normals.push(line.vec3()?),
+            "vt" => {
+                let values = line.numbers()?;
+                match values[..] {
+                    [u] => uvs.push((u, 0.0)),
+                    [u, v, ..] => uvs.push((u, v)),
+                    _ => return Err(line.error("'vt' needs at least one coordinate")),
+                }
+            }
+            "f" => {
+                let counts = (positions.len(), uvs.len(), normals.len());
+                let corners = line
+                    .arguments()
+                    .map(|(column, corner)| parse_corner(&line, column, corner, counts))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if corners.len() < 3 {
+                    return Err(line.error("A face needs at least three vertices"));
+                }
+                let group = match groups
+                    .iter()
+                    .position(|g| g.name == group_name && g.material == material_name)
+                {
+                    Some(index) => &mut groups[index],
+                    None => {
+                        groups.push(Group::new(&group_name, &material_name));
+                        groups.last_mut().unwrap()
+                    }
+                };
+                for i in 1..corners.len() - 1 {
+                    group.faces.push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "o" | "g" => group_name = line.rest().to_owned(),
+            "usemtl" => material_name = line.rest().to_owned(),
+            "mtllib" => {
+                for (_, file) in line.arguments() {
+                    let path = directory.join(file);
+                    let source = read(&path)?;
+                    let library = parse_mtl(&source, directory).map_err(|e| in_file(e, &path))?;
+                    materials.extend(library);
+                }
+            }
+            // smoothing groups, curves and other statements have no effect on the meshes
+            _ => {}
+        }
+    }
+
+    let mut list = HittableList::new();
+    for group in groups {
+        let material = materials
+            .get(&group.material)
+            .cloned()
+            .unwrap_or_else(|| default_material.clone());
+        list.add(Arc::new(
+            group.into_mesh(&positions, &uvs, &normals, material),
+        ));
+    }
+    Ok(list)
+}
+
+/// Materials defined in an MTL file, by name. Texture maps are read from `directory`.
+///
+/// Emissive materials (`Ke`) become `DiffuseLight`, transparent ones (`d` < 1, `Tr` > 0 or
+/// `illum` 4, 6, 7, 9) `Dielectric`, mirrors (`illum` 3 or 5) `Metal` with a fuzz derived
+/// from `Ns`, and everything else `Lambertian` with `Kd` or `map_Kd`.
+pub fn parse_mtl(
+    source: &str,
+    directory: &Path,
+) -> Result<HashMap<String, Arc<dyn Material>>, Error> {
+    let mut definitions: Vec<MtlDefinition> = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = Line::new(number + 1, line);
+        let keyword = match line.keyword() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            definitions.push(MtlDefinition::new(line.rest()));
+            continue;
+        }
+        let definition = match definitions.last_mut() {
+            Some(definition) => definition,
+            None => return Err(line.error(&format!("'{}' before any 'newmtl'", keyword))),
+        };
+        match keyword {
+            "Kd" => definition.diffuse = line.vec3()?,
+            "Ks" => definition.specular = line.vec3()?,
+            "Ke" => definition.emission = line.vec3()?,
+            "Ns" => definition.shininess = line.number()?,
+            "Ni" => definition.index_of_refraction = line.number()?,
+            "d" => definition.opacity = line.number()?,
+            "Tr" => definition.opacity = 1.0 - line.number()?,
+            "illum" => definition.illumination = line.number()? as i32,
+            "map_Kd" => {
+                // options such as `-s 1 1 1` come before the file name
+                let file = match line.arguments().last() {
+                    Some((_, file)) => file,
+                    None => return Err(line.error("'map_Kd' needs a file name")),
+                };
+                definition.diffuse_map = Some(directory.join(file).display().to_string());
+            }
+            _ => {}
+        }
+    }
+    Ok(definitions
+        .into_iter()
+        .map(|d| (d.name.clone(), d.material()))
+        .collect())
+}
+
+fn read(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path)
+        .map_err(|e| Error::from(e).explanation(&format!("Unable to read {}", path.display())))
+}
+
+/// Adds the file name to a parse error.
+fn in_file(error: Error, path: &Path) -> Error {
+    let explanation = match &error.explanation {
+        Some(e) => format!("{}: {}", path.display(), e),
+        None => path.display().to_string(),
+    };
+    error.explanation(&explanation)
+}
+
+/// One line of an OBJ or MTL file, without its comment.
+struct Line<'a> {
+    number: usize,
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn new(number: usize, text: &'a str) -> Self {
+        let text = match text.find('#') {
+            Some(index) => &text[..index],
+            None => text,
+        };
+        Line { number, text }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        let column = self.text.len() - self.text.trim_start().len() + 1;
+        Error::parse(self.number, column, message)
+    }
+
+    fn keyword(&self) -> Option<&'a str> {
+        self.text.split_whitespace().next()
+    }
+
+    /// The words after the keyword, with their columns.
+    fn arguments(&self) -> impl Iterator<Item = (usize, &'a str)> {
+        let text = self.text;
+        text.split_whitespace()
+            .skip(1)
+            .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize + 1, word))
+    }
+
+    /// Everything after the keyword, for names that may contain spaces.
+    fn rest(&self) -> &'a str {
+        let trimmed = self.text.trim_start();
+        let keyword = self.keyword().unwrap_or("");
+        trimmed[keyword.len()..].trim()
+    }
+
+    fn numbers(&self) -> Result<Vec<f64>, Error> {
+        self.arguments()
+            .map(|(column, word)| {
+                word.parse::<f64>().map_err(|_| {
+                    Error::parse(
+                        self.number,
+                        column,
+                        &format!("Expected a number, found '{}'", word),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn number(&self) -> Result<f64, Error> {
+        match self.numbers()?[..] {
+            [n] => Ok(n),
+            _ => Err(self.error("Expected one number")),
+        }
+    }
+
+    /// Three numbers; a fourth, like the `w` of a position, is ignored.
+    fn vec3(&self) -> Result<Vec3, Error> {
+        match self.numbers()?[..] {
+            [x, y, z] | [x, y, z, _] => Ok(Vec3::new(x, y, z)),
+            _ => Err(self.error("Expected three numbers")),
+        }
+    }
+}
+
+/// Indices of a face corner's position, texture coordinate and normal.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`. Indices start at 1; negative ones count back
+/// from the most recent element.
+fn parse_corner(
+    line: &Line,
+    column: usize,
+    corner: &str,
+    (positions, uvs, normals): (usize, usize, usize),
+) -> Result<Corner, Error> {
+    let error = |message: String| Error::parse(line.number, column, &message);
+    let index = |text: &str, count: usize| -> Result<usize, Error> {
+        let i = text
+            .parse::<i64>()
+            .map_err(|_| error(format!("Invalid face vertex '{}'", corner)))?;
+        let resolved = if i < 0 { count as i64 + i } else { i - 1 };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(error(format!("Face vertex '{}' is out of range", corner)));
+        }
+        Ok(resolved as usize)
+    };
+    let mut parts = corner.split('/');
+    let position = index(parts.next().unwrap_or(""), positions)?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(text) => Some(index(text, uvs)?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(text) => Some(index(text, normals)?),
+    };
+    if parts.next().is_some() {
+        return Err(error(format!("Invalid face vertex '{}'", corner)));
+    }
+    Ok((position, uv, normal))
+}
+
+struct Group {
+    name: String,
+    material: String,
+    faces: Vec<[Corner; 3]>,
+}
+
+impl Group {
+    fn new(name: &str, material: &str) -> Self {
+        Group {
+            name: name.to_owned(),
+            material: material.to_owned(),
+            faces: Vec::new(),
+        }
+    }
+
+    /// A mesh with its own vertex buffers, holding only the vertices this group uses.
+    /// Normals and texture coordinates are kept only if every corner has them.
+    fn into_mesh(
+        self,
+        positions: &[Vec3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+        material: Arc<dyn Material>,
+    ) -> TriangleMesh {
+        let corners = self.faces.iter().flatten();
+        let has_uvs = corners.clone().all(|c| c.1.is_some());
+        let has_normals = corners.clone().all(|c| c.2.is_some());
+
+        let mut indices = HashMap::new();
+        let mut mesh_positions = Vec::new();
+        let mut mesh_uvs = Vec::new();
+        let mut mesh_normals = Vec::new();
+        let mut triangles = Vec::with_capacity(self.faces.len());
+        for face in &self.faces {
+            let mut triangle = [0; 3];
+            for (slot, &(p, t, n)) in triangle.iter_mut().zip(face) {
+                let key = (p, t.filter(|_| has_uvs), n.filter(|_| has_normals));
+                *slot = *indices.entry(key).or_insert_with(|| {
+                    mesh_positions.push(positions[p]);
+                    if let Some(t) = key.1 {
+                        mesh_uvs.push(uvs[t]);
+                    }
+                    if let Some(n) = key.2 {
+                        mesh_normals.push(normals[n]);
+                    }
+                    mesh_positions.len() - 1
+                });
+            }
+            triangles.push(triangle);
+        }
+        TriangleMesh::new(mesh_positions, mesh_normals, mesh_uvs, triangles, material)
+    }
+}
+
+struct MtlDefinition {
+    name: String,
+    diffuse: Vec3,
+    specular: Vec3,
+    emission: Vec3,
+    shininess: f64,
+    index_of_refraction: f64,
+    opacity: f64,
+    illumination: i32,
+    diffuse_map: Option<String>,
+}
+
+impl MtlDefinition {
+    fn new(name: &str) -> Self {
+        MtlDefinition {
+            name: name.to_owned(),
+            diffuse: Vec3::new(0.8, 0.8, 0.8),
+            specular: Vec3::new(0.0, 0.0, 0.0),
+            emission: Vec3::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            index_of_refraction: 1.5,
+            opacity: 1.0,
+            illumination: 2,
+            diffuse_map: None,
+        }
+    }
+
+    fn material(&self) -> Arc<dyn Material> {
+        let black = Vec3::new(0.0, 0.0, 0.0);
+        if self.emission != black {
+            Arc::new(DiffuseLight::new_color(self.emission))
+        } else if self.opacity < 1.0 || [4, 6, 7, 9].contains(&self.illumination) {
+            Arc::new(Dielectric::new(self.index_of_refraction))
+        } else if self.illumination == 3 || self.illumination == 5 {
+            // Phong exponent to roughness, as in Walter et al. "Microfacet Models for Refraction"
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            Arc::new(Metal::new(self.specular, fuzz))
+        } else {
+            let albedo: Arc<dyn Texture> = match &self.diffuse_map {
+                Some(file) => Arc::new(ImageTexture::new_from_file(file)),
+                None => Arc::new(SolidColor::new_color(self.diffuse)),
+            };
+            Arc::new(Lambertian::new_texture(albedo))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Kind;
+    use crate::hittable::{HitRecord, Hittable};
+    use crate::ray::Ray;
+
+    fn grey() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    const QUADS: &str = "
+# two unit squares, the second one with normals and texture coordinates
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+o first
+f 1 2 3 4
+o second
+f -4/1/1 -3/2/1 -2/3/1 -1/4/1  # same square, relative indices
+usemtl shiny
+f 1/1/1 2/2/1 3/3/1
+";
+
+    #[test]
+    fn faces_are_grouped_and_triangulated() {
+        let list = parse(QUADS, Path::new(""), grey()).unwrap();
+        // first, second, and second with the `shiny` material
+        assert_eq!(list.objects.len(), 3);
+
+        let ray = Ray::new_at_time(Vec3::new(0.25, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = list.objects[1].hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-12 && (hit.v - 0.75).abs() < 1e-12);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        // the upper left half isn't covered by the single triangle
+        assert!(list.objects[2].hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn bad_faces_report_their_position() {
+        for (source, position) in [
+            ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n", (4, 7)),
+            ("v 0 0 0\nv 1 0 0\nf 1 2\n", (3, 1)),
+            ("v 0 0 zero\n", (1, 7)),
+        ] {
+            let error = parse(source, Path::new(""), grey()).err().unwrap();
+            match error.kind {
+                Kind::ParseError { line, column } => assert_eq!((line, column), position),
+                _ => panic!("expected a parse error for {:?}", source),
+            }
+        }
+    }
+
+    #[test]
+    fn mtl_materials_map_onto_existing_ones() {
+        let materials = parse_mtl(
+            "newmtl lamp\nKe 4 4 4\nnewmtl glass\nNi 1.33\nd 0.2\nnewmtl mirror\nillum 3\nKs 0.9 0.9 0.9\nNs 1000\nnewmtl matte\nKd 0.1 0.2 0.3\n",
+            Path::new(""),
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 4);
+        let emitted = |name: &str| {
+            let material = materials[name].clone();
+            material.emitted(&HitRecord::new(material.clone()))
+        };
+        assert_eq!(emitted("lamp"), Vec3::new(4.0, 4.0, 4.0));
+        assert_eq!(emitted("matte"), Vec3::new(0.0, 0.0, 0.0));
+        assert!(parse_mtl("Kd 1 1 1\n", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn mtllib_is_read_next_to_the_obj() {
+        let directory = std::env::temp_dir().join(format!("obj-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("lamp.mtl"), "newmtl lamp\nKe 1 2 3\n").unwrap();
+        std::fs::write(
+            directory.join("lamp.obj"),
+            "mtllib lamp.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl lamp\nf 1 2 3\n",
+        )
+        .unwrap();
+        let list = load_file(directory.join("lamp.obj"), grey()).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let ray = Ray::new_at_time(Vec3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = list.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(hit.material.emitted(&hit), Vec3::new(1.0, 2.0, 3.0));
+    }
+}
@@ -0,0 +1,36 @@
+use crate::vec::Vec3;
+
+/// Orthonormal basis, used to turn directions sampled around the z axis into world space.
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// A basis whose `w` axis points along `n`.
+    pub fn build_from_w(n: &Vec3) -> Self {
+        let w = Vec3::unit_vector(*n);
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vec3::unit_vector(Vec3::cross(w, a));
+        let u = Vec3::cross(w, v);
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    /// The inverse of `local`, the coordinates of a world space direction in this basis.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(a, &self.u),
+            Vec3::dot(a, &self.v),
+            Vec3::dot(a, &self.w),
+        )
+    }
+}
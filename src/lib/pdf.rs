@@ -0,0 +1,229 @@
+use crate::hittable::Hittable;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::vec::Vec3;
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// A probability density over directions, with respect to solid angle.
+pub trait Pdf {
+    fn value(&self, direction: &Vec3) -> f64;
+    fn generate(&self, sampler: &mut Sampler) -> Vec3;
+}
+
+/// Directions around a normal, weighted by the cosine to it.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        CosinePdf {
+            uvw: Onb::build_from_w(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = Vec3::dot(&Vec3::unit_vector(*direction), &self.uvw.w);
+        (cosine / PI).max(0.0)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        self.uvw.local(&Vec3::random_cosine_direction(sampler))
+    }
+}
+
+/// Every direction equally likely.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        Vec3::random_unit_vector(sampler)
+    }
+}
+
+/// Directions from `origin` towards a hittable, usually a light.
+pub struct HittablePdf<'a> {
+    hittable: &'a dyn Hittable,
+    origin: Vec3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(hittable: &'a dyn Hittable, origin: Vec3) -> Self {
+        HittablePdf { hittable, origin }
+    }
+}
+
+impl<'a> Pdf for HittablePdf<'a> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.hittable.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        self.hittable.random(&self.origin, sampler)
+    }
+}
+
+/// Picks one of two densities with equal probability.
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(first: &'a dyn Pdf, second: &'a dyn Pdf) -> Self {
+        MixturePdf {
+            pdfs: [first, second],
+        }
+    }
+}
+
+impl<'a> Pdf for MixturePdf<'a> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.pdfs[0].value(direction) + 0.5 * self.pdfs[1].value(direction)
+    }
+
+    fn generate(&self, sampler: &mut Sampler) -> Vec3 {
+        if sampler.gen::<f64>() < 0.5 {
+            self.pdfs[0].generate(sampler)
+        } else {
+            self.pdfs[1].generate(sampler)
+        }
+    }
+}
+
+/// A discrete distribution over `0..n` in proportion to a list of weights, sampled by
+/// inverting its cumulative distribution.
+pub struct Distribution1D {
+    /// Cumulative probabilities, ending at 1
+    cdf: Vec<f64>,
+    total: f64,
+}
+
+impl Distribution1D {
+    /// Weights must not be negative. If they are all 0 every index is equally likely.
+    pub fn new(weights: &[f64]) -> Self {
+        let total: f64 = weights.iter().sum();
+        let mut sum = 0.0;
+        let mut cdf: Vec<f64> = weights
+            .iter()
+            .map(|w| {
+                sum += if total > 0.0 {
+                    w / total
+                } else {
+                    1.0 / weights.len() as f64
+                };
+                sum
+            })
+            .collect();
+        if let Some(last) = cdf.last_mut() {
+            *last = 1.0;
+        }
+        Distribution1D { cdf, total }
+    }
+
+    /// Sum of the weights.
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    pub fn len(&self) -> usize {
+        self.cdf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cdf.is_empty()
+    }
+
+    pub fn probability(&self, index: usize) -> f64 {
+        self.cdf[index] - if index == 0 { 0.0 } else { self.cdf[index - 1] }
+    }
+
+    pub fn sample(&self, sampler: &mut Sampler) -> usize {
+        let x = sampler.gen::<f64>();
+        // the first entry whose range ends past x, which skips entries of weight 0
+        self.cdf
+            .partition_point(|c| *c <= x)
+            .min(self.cdf.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::rectangle::XZRectangle;
+    use crate::sphere::Sphere;
+    use std::sync::Arc;
+
+    /// Monte Carlo estimate of the integral of `pdf` over the sphere of directions.
+    fn integral(pdf: &dyn Pdf) -> f64 {
+        let mut sampler = Sampler::new(1);
+        let n = 200_000;
+        let sum: f64 = (0..n)
+            .map(|_| pdf.value(&Vec3::random_unit_vector(&mut sampler)))
+            .sum();
+        sum / n as f64 * 4.0 * PI
+    }
+
+    fn light() -> Arc<DiffuseLight> {
+        Arc::new(DiffuseLight::new_color(Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    #[test]
+    fn densities_integrate_to_one() {
+        let rectangle = XZRectangle::new(-1.0, 1.0, -1.0, 1.0, 1.0, light());
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -2.0), 1.0, light());
+        let cosine = CosinePdf::new(&Vec3::new(1.0, 1.0, 0.0));
+        let to_rectangle = HittablePdf::new(&rectangle, Vec3::new(0.0, 0.0, 0.0));
+        let to_sphere = HittablePdf::new(&sphere, Vec3::new(0.0, 0.0, 0.0));
+        let mixture = MixturePdf::new(&to_rectangle, &to_sphere);
+        for pdf in [
+            &cosine as &dyn Pdf,
+            &SpherePdf,
+            &to_rectangle,
+            &to_sphere,
+            &mixture,
+        ] {
+            let total = integral(pdf);
+            assert!((total - 1.0).abs() < 0.02, "integral is {}", total);
+        }
+    }
+
+    #[test]
+    fn distribution_follows_its_weights() {
+        let weights = [0.0, 2.0, 0.0, 1.0, 1.0];
+        let distribution = Distribution1D::new(&weights);
+        assert_eq!(distribution.total(), 4.0);
+        let mut counts = [0usize; 5];
+        let mut sampler = Sampler::new(3);
+        let n = 100_000;
+        for _ in 0..n {
+            counts[distribution.sample(&mut sampler)] += 1;
+        }
+        for (i, weight) in weights.iter().enumerate() {
+            assert!((distribution.probability(i) - weight / 4.0).abs() < 1e-12);
+            assert!((counts[i] as f64 / n as f64 - weight / 4.0).abs() < 0.01);
+        }
+        let uniform = Distribution1D::new(&[0.0, 0.0]);
+        assert_eq!(uniform.probability(1), 0.5);
+    }
+
+    #[test]
+    fn generated_directions_have_density() {
+        let rectangle = XZRectangle::new(-1.0, 1.0, -1.0, 1.0, 1.0, light());
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -2.0), 1.0, light());
+        let mut sampler = Sampler::new(2);
+        for hittable in [&rectangle as &dyn Hittable, &sphere] {
+            let pdf = HittablePdf::new(hittable, Vec3::new(0.0, 0.0, 0.0));
+            for _ in 0..100 {
+                assert!(pdf.value(&pdf.generate(&mut sampler)) > 0.0);
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::sampler::Sampler;
 use crate::vec::Vec3;
 use rand::Rng;
 
@@ -10,16 +11,16 @@ pub struct Perlin {
     perm_z: [i32; POINT_COUNT],
 }
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(sampler: &mut Sampler) -> Self {
         let mut random_vectors = [Vec3::new(0.0, 0.0, 0.0); POINT_COUNT];
         for r in &mut random_vectors {
-            *r = Vec3::unit_vector(Vec3::random_range(-1.0, 1.0));
+            *r = Vec3::unit_vector(Vec3::random_range(sampler, -1.0, 1.0));
         }
         Perlin {
             random_vectors,
-            perm_x: Self::perlin_generate_perm(),
-            perm_y: Self::perlin_generate_perm(),
-            perm_z: Self::perlin_generate_perm(),
+            perm_x: Self::perlin_generate_perm(sampler),
+            perm_y: Self::perlin_generate_perm(sampler),
+            perm_z: Self::perlin_generate_perm(sampler),
         }
     }
 
@@ -52,22 +53,19 @@ impl Perlin {
         Self::perlin_interpolation(c, u, v, w)
     }
 
-    fn perlin_generate_perm() -> [i32; POINT_COUNT] {
+    fn perlin_generate_perm(sampler: &mut Sampler) -> [i32; POINT_COUNT] {
         let mut p: [i32; POINT_COUNT] = [0; POINT_COUNT];
-        for i in 0..POINT_COUNT {
-            p[i] = i as i32;
+        for (i, v) in p.iter_mut().enumerate() {
+            *v = i as i32;
         }
-        Self::permute(&mut p, POINT_COUNT);
+        Self::permute(&mut p, POINT_COUNT, sampler);
         p
     }
 
-    fn permute(p: &mut [i32; POINT_COUNT], n: usize) {
-        let mut rng = rand::thread_rng();
+    fn permute(p: &mut [i32; POINT_COUNT], n: usize, sampler: &mut Sampler) {
         for i in (1..n).rev() {
-            let target = rng.gen_range(0..i);
-            let tmp = p[i];
-            p[i] = p[target];
-            p[target] = tmp;
+            let target = sampler.gen_range(0..i);
+            p.swap(i, target);
         }
     }
     fn perlin_interpolation(c: [[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
@@ -76,14 +74,14 @@ impl Perlin {
         let ww = w * w * (3.0 - 2.0 * w);
         let mut accumulator = 0.0;
 
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
+        for (i, ci) in c.iter().enumerate() {
+            for (j, cij) in ci.iter().enumerate() {
+                for (k, cijk) in cij.iter().enumerate() {
                     let weight_v = Vec3::new(u - i as f64, v - j as f64, w - k as f64);
                     accumulator += (i as f64 * uu + (1.0 - i as f64) * (1.0 - uu))
                         * (j as f64 * vv + (1.0 - j as f64) * (1.0 - vv))
                         * (k as f64 * ww + (1.0 - k as f64) * (1.0 - ww))
-                        * Vec3::dot(&c[i][j][k], &weight_v);
+                        * Vec3::dot(cijk, &weight_v);
                 }
             }
         }
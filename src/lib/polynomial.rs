@@ -0,0 +1,132 @@
+//! Real roots of polynomials up to degree four, for intersecting rays with quadric and
+//! quartic surfaces.
+
+/// Real roots of a x² + b x + c, in increasing order.
+pub fn quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { Vec::new() } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    // avoids subtracting nearly equal numbers, see Numerical Recipes 5.6
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let mut roots = if q == 0.0 {
+        vec![0.0, 0.0]
+    } else {
+        vec![q / a, c / q]
+    };
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// Real roots of x³ + a x² + b x + c, in increasing order.
+pub fn cubic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // x = y - a / 3 gives y³ + p y + q
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let shift = -a / 3.0;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    let mut roots = if discriminant > 0.0 {
+        // one real root, Cardano's formula
+        let s = discriminant.sqrt();
+        vec![(-q / 2.0 + s).cbrt() + (-q / 2.0 - s).cbrt() + shift]
+    } else if p == 0.0 {
+        vec![shift]
+    } else {
+        // three real roots, Viète's trigonometric form
+        let r = 2.0 * (-p / 3.0).sqrt();
+        let phi = (3.0 * q / (p * r)).clamp(-1.0, 1.0).acos() / 3.0;
+        (0..3)
+            .map(|k| r * (phi - 2.0 * std::f64::consts::PI * k as f64 / 3.0).cos() + shift)
+            .collect()
+    };
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// Real roots of x⁴ + a x³ + b x² + c x + d, in increasing order, by Ferrari's method and
+/// polished with Newton's method.
+pub fn quartic_roots(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // x = y - a / 4 gives y⁴ + p y² + q y + r
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+    let shift = -a / 4.0;
+
+    let mut roots = Vec::with_capacity(4);
+    if q.abs() < 1e-12 {
+        // biquadratic
+        for z in quadratic_roots(1.0, p, r) {
+            if z >= 0.0 {
+                roots.push(z.sqrt());
+                roots.push(-z.sqrt());
+            }
+        }
+    } else {
+        // (y² + p/2 + m)² = 2m (y - q / 4m)² for a root m > 0 of the resolvent cubic
+        let m = cubic_roots(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let s = (2.0 * m).sqrt();
+        let k = q / (2.0 * s);
+        roots.extend(quadratic_roots(1.0, -s, p / 2.0 + m + k));
+        roots.extend(quadratic_roots(1.0, s, p / 2.0 + m - k));
+    }
+
+    let f = |x: f64| (((x + a) * x + b) * x + c) * x + d;
+    let df = |x: f64| ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+    let mut roots: Vec<f64> = roots
+        .into_iter()
+        .map(|y| {
+            let mut x = y + shift;
+            for _ in 0..2 {
+                let slope = df(x);
+                if slope != 0.0 {
+                    x -= f(x) / slope;
+                }
+            }
+            x
+        })
+        .collect();
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(actual: Vec<f64>, expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{:?} {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn roots_of_known_polynomials() {
+        assert_roots(quadratic_roots(2.0, -2.0, -12.0), &[-2.0, 3.0]);
+        assert_roots(quadratic_roots(1.0, 0.0, 1.0), &[]);
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(cubic_roots(0.0, -7.0, 6.0), &[-3.0, 1.0, 2.0]);
+        assert_roots(cubic_roots(0.0, 0.0, -8.0), &[2.0]);
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            quartic_roots(-10.0, 35.0, -50.0, 24.0),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x² + 1)(x - 0.5)(x + 7)
+        assert_roots(quartic_roots(6.5, -2.5, 6.5, -3.5), &[-7.0, 0.5]);
+        // (x² - 4)(x² - 9)
+        assert_roots(
+            quartic_roots(0.0, -13.0, 0.0, 36.0),
+            &[-3.0, -2.0, 2.0, 3.0],
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Samples accumulated over several passes, and checkpoint files to resume them from.
+//!
+//! Every camera sample draws its random numbers from `Sampler::for_sample` with the render
+//! seed, the pixel and the index of the sample. The seed and the number of samples each pixel
+//! has taken are therefore the whole random state of a render: a pass picks up at the next
+//! index of every pixel, and a resumed render gives the same image as one run in a single go,
+//! as long as it traces the samples the same way: checkpoints also record the settings that
+//! change what a sample sees.
+
+use crate::adaptive::{AdaptiveSampling, PixelEstimate};
+use crate::error::Error;
+use crate::image_output::Framebuffer;
+use crate::job::Region;
+use crate::render::RenderSettings;
+use crate::vec::Vec3;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8] = b"raytracer checkpoint 3\n";
+/// Values of 8 bytes after `MAGIC`, up to the pixels
+const HEADER_VALUES: usize = 13;
+/// A sum of 3 and a mean and squared deviation of 8 bytes each, then a count of 4
+const PIXEL_BYTES: u64 = 5 * 8 + 4;
+
+/// The running estimate of every pixel of a region of the image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accumulator {
+    pub image_width: usize,
+    pub image_height: usize,
+    pub region: Region,
+    /// Seed the samples were taken with; more samples must use the same one
+    pub seed: u64,
+    /// Whether the samples are single wavelengths, see `RenderSettings::spectral`
+    pub spectral: bool,
+    pub max_depth: i32,
+    /// Width over height of the view, which `image_width` and `image_height` only round
+    pub aspect_ratio: f64,
+    /// When pixels stop taking samples
+    pub adaptive: Option<AdaptiveSampling>,
+    /// Row by row from the top of the region
+    pixels: Vec<PixelEstimate>,
+}
+
+impl Accumulator {
+    /// No samples yet in `region` of an image `image_width` by `image_height` pixels, with
+    /// square pixels, traced the way the default `RenderSettings` trace them.
+    pub fn new(image_width: usize, image_height: usize, region: Region, seed: u64) -> Self {
+        let settings = RenderSettings::default();
+        Accumulator {
+            image_width,
+            image_height,
+            region,
+            seed,
+            spectral: settings.spectral,
+            max_depth: settings.max_depth,
+            aspect_ratio: image_width as f64 / image_height as f64,
+            adaptive: settings.adaptive,
+            pixels: vec![PixelEstimate::new(); region.width * region.height],
+        }
+    }
+
+    /// The estimate at `x`, `y` in the region, counted from its top left.
+    pub fn get(&self, x: usize, y: usize) -> &PixelEstimate {
+        &self.pixels[y * self.region.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, estimate: PixelEstimate) {
+        self.pixels[y * self.region.width + x] = estimate;
+    }
+
+    /// The mean of every pixel so far.
+    pub fn image(&self) -> Framebuffer {
+        let mut image = Framebuffer::new(self.region.width, self.region.height);
+        for (pixel, estimate) in image.pixels_mut().iter_mut().zip(&self.pixels) {
+            *pixel = estimate.mean();
+        }
+        image
+    }
+
+    /// The samples taken in every pixel, row by row from the top.
+    pub fn sample_counts(&self) -> Vec<u32> {
+        self.pixels.iter().map(PixelEstimate::count).collect()
+    }
+
+    /// Writes a checkpoint to `path`. The file is replaced only once it's complete, so a
+    /// render killed while saving keeps its previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        let write = || -> io::Result<()> {
+            let mut writer = BufWriter::new(File::create(&partial)?);
+            writer.write_all(MAGIC)?;
+            for value in [
+                self.seed,
+                self.image_width as u64,
+                self.image_height as u64,
+                self.region.x as u64,
+                self.region.y as u64,
+                self.region.width as u64,
+                self.region.height as u64,
+                self.spectral as u64,
+                self.max_depth as i64 as u64,
+                self.adaptive.is_some() as u64,
+                self.adaptive.map_or(0.0, |a| a.threshold).to_bits(),
+                self.adaptive.map_or(0, |a| a.min_samples) as i64 as u64,
+                self.aspect_ratio.to_bits(),
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            for pixel in &self.pixels {
+                let (sum, count, mean, m2) = pixel.parts();
+                for value in [sum.x, sum.y, sum.z, mean, m2] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                writer.write_all(&count.to_le_bytes())?;
+            }
+            writer.into_inner()?.sync_all()?;
+            std::fs::rename(&partial, path)
+        };
+        write().map_err(|e| {
+            Error::from(e).explanation(&format!("Unable to write checkpoint {}", path.display()))
+        })
+    }
+
+    /// Reads a checkpoint written by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let read = || -> io::Result<Accumulator> {
+            let file = File::open(path)?;
+            let length = file.metadata()?.len();
+            let mut reader = BufReader::new(file);
+            let mut magic = vec![0; MAGIC.len()];
+            reader.read_exact(&mut magic)?;
+            if magic != MAGIC {
+                return Err(invalid("not a checkpoint file"));
+            }
+            let [seed, image_width, image_height, x, y, width, height] = read_values(&mut reader)?;
+            let [spectral, max_depth, adaptive, threshold, min_samples, aspect_ratio] =
+                read_values(&mut reader)?;
+            let inside = |start: u64, length: u64, size: u64| {
+                length > 0 && start.checked_add(length).is_some_and(|end| end <= size)
+            };
+            if !inside(x, width, image_width) || !inside(y, height, image_height) {
+                return Err(invalid("the region is outside the image"));
+            }
+            // before making room for the pixels, which the header alone can't be trusted with
+            let pixel_bytes = width
+                .checked_mul(height)
+                .and_then(|n| n.checked_mul(PIXEL_BYTES));
+            let header_bytes = (MAGIC.len() + 8 * HEADER_VALUES) as u64;
+            if pixel_bytes != length.checked_sub(header_bytes) {
+                return Err(invalid("the file size doesn't match the region"));
+            }
+            let flag = |value: u64| match value {
+                0 => Ok(false),
+                1 => Ok(true),
+                _ => Err(invalid("a setting is neither on nor off")),
+            };
+            let aspect_ratio = f64::from_bits(aspect_ratio);
+            if !(aspect_ratio > 0.0 && aspect_ratio.is_finite()) {
+                return Err(invalid("the aspect ratio isn't positive"));
+            }
+            let region = Region::new(x as usize, y as usize, width as usize, height as usize);
+            let mut accumulator =
+                Accumulator::new(image_width as usize, image_height as usize, region, seed);
+            accumulator.spectral = flag(spectral)?;
+            accumulator.max_depth = max_depth as i64 as i32;
+            accumulator.aspect_ratio = aspect_ratio;
+            if flag(adaptive)? {
+                accumulator.adaptive = Some(AdaptiveSampling {
+                    threshold: f64::from_bits(threshold),
+                    min_samples: min_samples as i64 as i32,
+                });
+            }
+            for pixel in accumulator.pixels.iter_mut() {
+                let mut values = [0.0; 5];
+                for value in values.iter_mut() {
+                    *value = f64::from_bits(read_u64(&mut reader)?);
+                }
+                let mut count = [0; 4];
+                reader.read_exact(&mut count)?;
+                let [x, y, z, mean, m2] = values;
+                *pixel = PixelEstimate::from_parts(
+                    Vec3::new(x, y, z),
+                    u32::from_le_bytes(count),
+                    mean,
+                    m2,
+                );
+            }
+            Ok(accumulator)
+        };
+        read().map_err(|e| {
+            Error::from(e).explanation(&format!("Unable to read checkpoint {}", path.display()))
+        })
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_values<const N: usize>(reader: &mut impl Read) -> io::Result<[u64; N]> {
+    let mut values = [0; N];
+    for value in values.iter_mut() {
+        *value = read_u64(reader)?;
+    }
+    Ok(values)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let mut accumulator = Accumulator::new(8, 6, Region::new(2, 1, 3, 2), 42);
+        accumulator.spectral = true;
+        accumulator.max_depth = 7;
+        accumulator.aspect_ratio = 1.4;
+        accumulator.adaptive = Some(AdaptiveSampling {
+            threshold: 0.05,
+            min_samples: 12,
+        });
+        let mut estimate = PixelEstimate::new();
+        estimate.add(Vec3::new(0.5, 1.0, 2.0));
+        estimate.add(Vec3::new(0.25, 0.0, 4.0));
+        accumulator.set(2, 1, estimate);
+
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.bin", std::process::id()));
+        accumulator.save(&path).unwrap();
+        let loaded = Accumulator::load(&path).unwrap();
+        std::fs::write(&path, [MAGIC, b"short"].concat()).unwrap();
+        let truncated = Accumulator::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, accumulator);
+        assert_eq!(loaded.sample_counts(), vec![0, 0, 0, 0, 0, 2]);
+        assert!(truncated.is_err());
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        // a region of the image, then the settings of an RGB render
+        let header = |region: [u64; 6]| {
+            let mut bytes = MAGIC.to_vec();
+            let settings = [0, 50, 0, 0, 0, 1.5f64.to_bits()];
+            for value in [1].iter().chain(&region).chain(&settings) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes
+        };
+        let path = std::env::temp_dir().join(format!("oversized-{}.bin", std::process::id()));
+        let mut results = vec![];
+        for region in [
+            [u64::MAX, 4, u64::MAX, 0, 2, 4],
+            [u64::MAX, u64::MAX, 0, 0, u64::MAX, u64::MAX],
+            // terabytes of pixels claimed by a file of a hundred bytes or so
+            [1 << 20, 1 << 20, 0, 0, 1 << 20, 1 << 20],
+        ] {
+            std::fs::write(&path, header(region)).unwrap();
+            results.push(Accumulator::load(&path));
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let messages: Vec<_> = results
+            .into_iter()
+            .map(|r| r.unwrap_err().to_string())
+            .collect();
+        assert!(messages[0].contains("outside the image"), "{}", messages[0]);
+        for message in &messages[1..] {
+            assert!(message.contains("file size"), "{}", message);
+        }
+    }
+}
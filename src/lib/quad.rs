@@ -0,0 +1,347 @@
+use crate::aabb::AABB;
+use crate::frame::{Frame, Motion};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::triangle;
+use crate::vec::Vec3;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// A parallelogram spanned by the edges `u` and `v` from the corner `origin`, facing along
+/// `u × v`. Texture coordinates run from 0 to 1 along the edges.
+pub struct Quad {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    /// Unit normal on the front side
+    normal: Vec3,
+    /// `u × v` divided by its squared length, to find the edge coordinates of a point
+    w: Vec3,
+    area: f64,
+    material: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new(origin: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let n = Vec3::cross(u, v);
+        Quad {
+            origin,
+            u,
+            v,
+            normal: Vec3::unit_vector(n),
+            w: n / n.length_squared(),
+            area: n.length(),
+            material,
+        }
+    }
+
+    /// Swaps the front and the back of the quad, keeping its texture coordinates.
+    pub fn flipped(mut self) -> Self {
+        self.normal = -self.normal;
+        self
+    }
+
+    /// Ray parameter and edge coordinates of the point where `ray` crosses the quad.
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let t = plane_intersection(&self.origin, &self.normal, ray, t_min, t_max)?;
+        let planar = ray.at(t) - self.origin;
+        let alpha = Vec3::dot(&self.w, &Vec3::cross(planar, self.v));
+        let beta = Vec3::dot(&self.w, &Vec3::cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some((t, alpha, beta))
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, alpha, beta) = self.intersect(ray, t_min, t_max)?;
+        let mut hit_record = HitRecord::new(self.material.clone());
+        hit_record.u = alpha;
+        hit_record.v = beta;
+        hit_record.t = t;
+        hit_record.set_face_normal(ray, &self.normal);
+        hit_record.point = ray.at(t);
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
+        let o = self.origin;
+        Some(triangle::bounds(&[
+            o,
+            o + self.u,
+            o + self.v,
+            o + self.u + self.v,
+        ]))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let ray = Ray::new_at_time(*origin, *direction, 0.0);
+        match self.intersect(&ray, 0.001, f64::INFINITY) {
+            Some((t, _, _)) => area_pdf(t, direction, &self.normal, self.area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let point = self.origin + sampler.gen::<f64>() * self.u + sampler.gen::<f64>() * self.v;
+        point - *origin
+    }
+}
+
+/// A flat disk facing along `normal`. Texture coordinates map the square around the disk
+/// to 0 to 1, so an image is laid onto it like a decal.
+pub struct Disk {
+    center: Vec3,
+    radius: f64,
+    /// `w` is the unit normal on the front side
+    basis: Onb,
+    material: Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: Arc<dyn Material>) -> Self {
+        Disk {
+            center,
+            radius,
+            basis: Onb::build_from_w(&normal),
+            material,
+        }
+    }
+
+    /// Ray parameter and coordinates in the plane of the disk, relative to its center.
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let t = plane_intersection(&self.center, &self.basis.w, ray, t_min, t_max)?;
+        let local = self.basis.to_local(&(ray.at(t) - self.center));
+        if local.x * local.x + local.y * local.y > self.radius * self.radius {
+            return None;
+        }
+        Some((t, local.x, local.y))
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, x, y) = self.intersect(ray, t_min, t_max)?;
+        let mut hit_record = HitRecord::new(self.material.clone());
+        hit_record.u = 0.5 + 0.5 * x / self.radius;
+        hit_record.v = 0.5 + 0.5 * y / self.radius;
+        hit_record.t = t;
+        hit_record.set_face_normal(ray, &self.basis.w);
+        hit_record.point = ray.at(t);
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
+        // a disk reaches out r·sqrt(1 - n²) along each axis
+        let n = self.basis.w;
+        let reach = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let extent = Vec3::new(reach(n.x), reach(n.y), reach(n.z));
+        Some(triangle::bounds(&[
+            self.center - extent,
+            self.center + extent,
+        ]))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let ray = Ray::new_at_time(*origin, *direction, 0.0);
+        match self.intersect(&ray, 0.001, f64::INFINITY) {
+            Some((t, _, _)) => area_pdf(t, direction, &self.basis.w, self.area()),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        // uniform over the area
+        let r = self.radius * sampler.gen::<f64>().sqrt();
+        let phi = 2.0 * PI * sampler.gen::<f64>();
+        let point = self.center
+            + self
+                .basis
+                .local(&Vec3::new(r * phi.cos(), r * phi.sin(), 0.0));
+        point - *origin
+    }
+}
+
+/// The infinite plane through `point` facing along `normal`. It has no bounding box, so
+/// hierarchies keep it apart from the bounded objects.
+///
+/// Texture coordinates repeat every unit across the plane.
+pub struct Plane {
+    frame: Frame,
+    material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Self {
+        Plane::new_moving(point, normal, Motion::none(), material)
+    }
+
+    pub fn new_moving(
+        point: Vec3,
+        normal: Vec3,
+        motion: Motion,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Plane {
+            frame: Frame::new(point, normal, motion),
+            material,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = self.frame.to_local(ray);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let t = plane_intersection(&Vec3::new(0.0, 0.0, 0.0), &normal, &local, t_min, t_max)?;
+        let p = local.at(t);
+        let uv = (p.x.rem_euclid(1.0), p.y.rem_euclid(1.0));
+        Some(self.frame.hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
+        None
+    }
+}
+
+/// Where `ray` crosses the plane through `point` with the unit `normal`, if within range.
+fn plane_intersection(
+    point: &Vec3,
+    normal: &Vec3,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<f64> {
+    let denominator = Vec3::dot(normal, &ray.direction);
+    if denominator.abs() < 1e-12 {
+        // ray parallel to the plane
+        return None;
+    }
+    let t = Vec3::dot(normal, &(*point - ray.origin)) / denominator;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some(t)
+}
+
+/// Solid angle density of a point chosen uniformly on a flat shape of `area`, seen along
+/// `direction` at ray parameter `t`.
+fn area_pdf(t: f64, direction: &Vec3, normal: &Vec3, area: f64) -> f64 {
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = Vec3::dot(direction, normal).abs() / direction.length();
+    distance_squared / (cosine * area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn tilted_quad_hit_and_uv() {
+        // a 2 by 1 rectangle tilted 45 degrees around the x axis
+        let s = std::f64::consts::FRAC_1_SQRT_2;
+        let quad = Quad::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, s, -s),
+            material(),
+        );
+        let down =
+            |x: f64, z: f64| Ray::new_at_time(Vec3::new(x, 5.0, z), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let hit = quad.hit(&down(0.5, -0.5 * s), 0.001, 10.0).unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-12 && (hit.v - 0.5).abs() < 1e-12);
+        assert!((hit.point.y - 0.5 * s).abs() < 1e-12);
+        // u × v points up, towards the ray
+        assert!(hit.front_face);
+        assert!(quad.hit(&down(2.5, -0.5 * s), 0.001, 10.0).is_none());
+        assert!(quad
+            .flipped()
+            .hit(&down(1.0, -0.1), 0.001, 10.0)
+            .is_some_and(|h| !h.front_face));
+    }
+
+    #[test]
+    fn moving_plane() {
+        let plane = Plane::new_moving(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Motion::new(Vec3::new(0.0, 2.0, 0.0), 0.0, 2.0),
+            material(),
+        );
+        assert!(plane.bounding_box(0.0, 1.0).is_none());
+        let down = |time: f64| {
+            Ray::new_at_time(Vec3::new(0.25, 5.0, 3.5), Vec3::new(0.0, -1.0, 0.0), time)
+        };
+        for (time, t) in [(0.0, 4.0), (1.0, 3.0), (3.0, 1.0)] {
+            let hit = plane.hit(&down(time), 0.001, 10.0).unwrap();
+            assert!((hit.t - t).abs() < 1e-9);
+            assert!(hit.front_face && (0.0..1.0).contains(&hit.u) && (0.0..1.0).contains(&hit.v));
+        }
+    }
+
+    #[test]
+    fn flat_shapes_are_sampled_by_area() {
+        let shapes: [Box<dyn Hittable>; 2] = [
+            Box::new(Quad::new(
+                Vec3::new(-1.0, 2.0, -0.5),
+                Vec3::new(1.5, 0.5, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                material(),
+            )),
+            Box::new(Disk::new(
+                Vec3::new(0.5, 2.0, 0.0),
+                Vec3::new(0.3, -1.0, 0.2),
+                0.7,
+                material(),
+            )),
+        ];
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let mut sampler = Sampler::new(5);
+        for shape in &shapes {
+            let bounds = shape.bounding_box(0.0, 1.0).unwrap();
+            // the average of 1 / pdf over directions towards the shape is its solid angle
+            let mut solid_angle = 0.0;
+            let n = 20000;
+            for _ in 0..n {
+                let direction = shape.random(&origin, &mut sampler);
+                let point = origin + direction;
+                for axis in [|v: &Vec3| v.x, |v: &Vec3| v.y, |v: &Vec3| v.z] {
+                    assert!(axis(&bounds.min) <= axis(&point) && axis(&point) <= axis(&bounds.max));
+                }
+                solid_angle += 1.0 / shape.pdf_value(&origin, &direction) / n as f64;
+            }
+            // compared with uniform directions over the sphere
+            let mut hits = 0;
+            let m = 200000;
+            for _ in 0..m {
+                let direction = Vec3::random_unit_vector(&mut sampler);
+                let ray = Ray::new_at_time(origin, direction, 0.0);
+                if shape.hit(&ray, 0.001, f64::INFINITY).is_some() {
+                    hits += 1;
+                }
+            }
+            let expected = 4.0 * PI * hits as f64 / m as f64;
+            assert!(
+                (solid_angle - expected).abs() < 0.03 * expected,
+                "{} {}",
+                solid_angle,
+                expected
+            );
+        }
+    }
+}
@@ -4,6 +4,8 @@ pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
     pub time: f64,
+    /// Wavelength in nanometres the ray carries in spectral mode, `None` for RGB rendering
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -12,6 +14,7 @@ impl Ray {
             origin,
             direction,
             time,
+            wavelength: None,
         }
     }
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
@@ -20,9 +23,15 @@ impl Ray {
             origin,
             direction,
             time: 0.0,
+            wavelength: None,
         }
     }
 
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Self {
+        self.wavelength = wavelength;
+        self
+    }
+
     pub fn at(&self, t: f64) -> Vec3 {
         self.origin + t * self.direction
     }
@@ -0,0 +1,565 @@
+use crate::adaptive::{AdaptiveSampling, PixelEstimate};
+use crate::camera::Camera;
+use crate::environment::{Environment, SolidBackground};
+use crate::error::Error;
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::image_output::Framebuffer;
+use crate::integrator::PathTracer;
+use crate::job::{self, Job, Region, TileOrder, Tiling};
+use crate::light::Light;
+use crate::progressive::Accumulator;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::vec::Vec3;
+use rand::Rng;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub image_width: usize,
+    /// Height in pixels, instead of the width over `aspect_ratio` rounded down
+    pub fixed_height: Option<usize>,
+    pub aspect_ratio: f64,
+    /// Samples in every pixel, or the most any pixel takes with `adaptive`
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    /// Trace one wavelength per sample instead of RGB, see `PathTracer`
+    pub spectral: bool,
+    /// Stop sampling pixels once their estimate is precise enough
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+impl RenderSettings {
+    pub fn image_height(&self) -> usize {
+        self.fixed_height
+            .unwrap_or((self.image_width as f64 / self.aspect_ratio) as usize)
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            image_width: 400,
+            fixed_height: None,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            spectral: false,
+            adaptive: None,
+        }
+    }
+}
+
+/// Renders a world as seen by a camera into a linear `Framebuffer`.
+pub struct Renderer<'a> {
+    world: &'a dyn Hittable,
+    lights: Option<&'a dyn Hittable>,
+    punctual_lights: &'a [Arc<dyn Light>],
+    camera: &'a Camera,
+    settings: RenderSettings,
+    background: Arc<dyn Environment>,
+    region: Region,
+    tiling: Tiling,
+    seed: u64,
+}
+
+impl<'a> Renderer<'a> {
+    /// Fails unless the image is at least two pixels wide and high.
+    pub fn new(
+        world: &'a dyn Hittable,
+        camera: &'a Camera,
+        settings: RenderSettings,
+    ) -> Result<Self, Error> {
+        // pixel centers are spread from one edge of the view to the other
+        let (width, height) = (settings.image_width, settings.image_height());
+        if width < 2 || height < 2 {
+            return Err(Error::argument(&format!(
+                "Image of {}x{} pixels is too small, it needs at least 2x2",
+                width, height
+            )));
+        }
+        Ok(Renderer {
+            world,
+            lights: None,
+            punctual_lights: &[],
+            camera,
+            settings,
+            background: Arc::new(SolidBackground::new(Vec3::new(0.0, 0.0, 0.0))),
+            region: Region::new(0, 0, settings.image_width, settings.image_height()),
+            tiling: Tiling::default(),
+            seed: 0,
+        })
+    }
+
+    pub fn from_scene(scene: &'a Scene) -> Result<Self, Error> {
+        Ok(Renderer::new(&scene.world, &scene.camera, scene.settings)?
+            .environment(scene.background.clone())
+            .lights(&scene.lights)
+            .punctual_lights(&scene.punctual_lights))
+    }
+
+    /// Objects to sample directly at every diffuse bounce. They must also be part of the world.
+    pub fn lights(mut self, lights: &'a HittableList) -> Self {
+        self.lights = if lights.objects.is_empty() {
+            None
+        } else {
+            Some(lights)
+        };
+        self
+    }
+
+    /// Point, spot and directional lights, sampled at every diffuse bounce.
+    pub fn punctual_lights(mut self, lights: &'a [Arc<dyn Light>]) -> Self {
+        self.punctual_lights = lights;
+        self
+    }
+
+    /// Color of rays that hit nothing
+    pub fn background(self, background: Vec3) -> Self {
+        self.environment(Arc::new(SolidBackground::new(background)))
+    }
+
+    /// What rays that hit nothing see, such as an `EnvironmentLight`
+    pub fn environment(mut self, environment: Arc<dyn Environment>) -> Self {
+        self.background = environment;
+        self
+    }
+
+    /// Renders with the same seed, settings and scene give identical images.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Renders only `region` of the image; the framebuffer has the size of the region.
+    pub fn region(mut self, region: Region) -> Result<Self, Error> {
+        let (width, height) = (self.settings.image_width, self.settings.image_height());
+        if region.width == 0
+            || region.height == 0
+            || region.x + region.width > width
+            || region.y + region.height > height
+        {
+            return Err(Error::argument(&format!(
+                "Region {},{},{},{} is outside the {}x{} image",
+                region.x, region.y, region.width, region.height, width, height
+            )));
+        }
+        self.region = region;
+        Ok(self)
+    }
+
+    /// How the image is cut into jobs and in which order they're rendered.
+    pub fn tiling(mut self, tiling: Tiling) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    pub fn jobs(&self) -> Vec<Job> {
+        job::create_region_jobs(self.settings.image_height(), self.region, &self.tiling)
+    }
+
+    pub fn render(&self) -> Framebuffer {
+        self.render_with_progress(|| {})
+    }
+
+    /// Renders the image, calling `progress` each time one of `jobs()` is finished.
+    pub fn render_with_progress<F: Fn() + Sync>(&self, progress: F) -> Framebuffer {
+        self.render_counted(progress).0
+    }
+
+    /// Like `render_with_progress`, also returning the number of samples taken in each
+    /// pixel, row by row from the top.
+    pub fn render_counted<F: Fn() + Sync>(&self, progress: F) -> (Framebuffer, Vec<u32>) {
+        let mut accumulator = self.accumulator();
+        self.render_pass(&mut accumulator, self.settings.samples_per_pixel, progress);
+        (accumulator.image(), accumulator.sample_counts())
+    }
+
+    /// An accumulator without samples, for `render_pass`.
+    pub fn accumulator(&self) -> Accumulator {
+        let mut accumulator = Accumulator::new(
+            self.settings.image_width,
+            self.settings.image_height(),
+            self.region,
+            self.seed,
+        );
+        accumulator.spectral = self.settings.spectral;
+        accumulator.max_depth = self.settings.max_depth;
+        accumulator.aspect_ratio = self.settings.aspect_ratio;
+        accumulator.adaptive = self.settings.adaptive;
+        accumulator
+    }
+
+    /// Checks that `accumulator` holds samples of this render, with the same image size,
+    /// region, seed and tracing settings, so more can be added to it.
+    pub fn check_accumulator(&self, accumulator: &Accumulator) -> Result<(), Error> {
+        let expected = self.accumulator();
+        let size = |a: &Accumulator| (a.image_width, a.image_height);
+        if size(accumulator) != size(&expected) {
+            return Err(Error::argument(&format!(
+                "The checkpoint is of a {}x{} image, not {}x{}",
+                accumulator.image_width,
+                accumulator.image_height,
+                expected.image_width,
+                expected.image_height
+            )));
+        }
+        if accumulator.region != expected.region {
+            let r = accumulator.region;
+            return Err(Error::argument(&format!(
+                "The checkpoint covers the region {},{},{},{}",
+                r.x, r.y, r.width, r.height
+            )));
+        }
+        if accumulator.seed != expected.seed {
+            return Err(Error::argument(&format!(
+                "The checkpoint was rendered with seed {}",
+                accumulator.seed
+            )));
+        }
+        if accumulator.spectral != expected.spectral {
+            return Err(Error::argument(if accumulator.spectral {
+                "The checkpoint was rendered in spectral mode"
+            } else {
+                "The checkpoint was not rendered in spectral mode"
+            }));
+        }
+        if accumulator.max_depth != expected.max_depth {
+            return Err(Error::argument(&format!(
+                "The checkpoint was rendered with max depth {}",
+                accumulator.max_depth
+            )));
+        }
+        if accumulator.aspect_ratio != expected.aspect_ratio {
+            return Err(Error::argument(&format!(
+                "The checkpoint was rendered with aspect ratio {}",
+                accumulator.aspect_ratio
+            )));
+        }
+        if accumulator.adaptive != expected.adaptive {
+            return Err(Error::argument(&match accumulator.adaptive {
+                Some(a) => format!(
+                    "The checkpoint was rendered with adaptive threshold {} and min spp {}",
+                    a.threshold, a.min_samples
+                ),
+                None => "The checkpoint was rendered without adaptive sampling".to_string(),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Adds up to `samples` more samples to every pixel of `accumulator` that hasn't
+    /// finished, calling `progress` each time one of `jobs()` is done. The accumulator
+    /// must come from `accumulator` or pass `check_accumulator`.
+    pub fn render_pass<F: Fn() + Sync>(
+        &self,
+        accumulator: &mut Accumulator,
+        samples: i32,
+        progress: F,
+    ) {
+        let mut jobs = self.jobs();
+        if self.tiling.order == TileOrder::CostliestFirst {
+            job::sort_by_cost(&mut jobs, |job| self.remaining_samples(job, accumulator));
+        }
+
+        // every thread takes the next job in line, so they're started in order
+        let next = AtomicUsize::new(0);
+        let accumulator = Mutex::new(accumulator);
+        (0..rayon::current_num_threads())
+            .into_par_iter()
+            .for_each(|_| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                match jobs.get(index) {
+                    Some(job) => {
+                        self.work(job, &accumulator, samples);
+                        progress();
+                    }
+                    None => break,
+                }
+            });
+    }
+
+    /// Samples the pixels of `job` still need at most, an estimate of its cost.
+    fn remaining_samples(&self, job: &Job, accumulator: &Accumulator) -> f64 {
+        self.job_pixels(job)
+            .map(|(_, _, x, y)| {
+                let estimate = accumulator.get(x, y);
+                if self.is_pixel_finished(estimate) {
+                    0.0
+                } else {
+                    (self.settings.samples_per_pixel as u32).saturating_sub(estimate.count()) as f64
+                }
+            })
+            .sum()
+    }
+
+    /// The pixels of a job from the top row down: their column and row in the renderer's
+    /// numbering, and their place in the region counted from its top left.
+    fn job_pixels<'j>(
+        &self,
+        job: &'j Job,
+    ) -> impl Iterator<Item = (usize, usize, usize, usize)> + 'j {
+        let top = self.settings.image_height() - 1 - self.region.y;
+        let left = self.region.x;
+        job.height_range.clone().rev().flat_map(move |j| {
+            job.width_range
+                .clone()
+                .map(move |i| (i, j, i - left, top - j))
+        })
+    }
+
+    /// Whether every pixel of `accumulator` has all the samples it will take.
+    pub fn is_finished(&self, accumulator: &Accumulator) -> bool {
+        (0..self.region.height)
+            .all(|y| (0..self.region.width).all(|x| self.is_pixel_finished(accumulator.get(x, y))))
+    }
+
+    fn is_pixel_finished(&self, estimate: &PixelEstimate) -> bool {
+        estimate.count() >= self.settings.samples_per_pixel.max(0) as u32
+            || self.settings.adaptive.is_some_and(|a| a.is_done(estimate))
+    }
+
+    /// Adds up to `samples` more samples to every pixel of a job in `accumulator`.
+    fn work(&self, job: &Job, accumulator: &Mutex<&mut Accumulator>, samples: i32) {
+        let image_width = self.settings.image_width;
+        let image_height = self.settings.image_height();
+        let tracer = PathTracer {
+            world: self.world,
+            lights: self.lights,
+            punctual_lights: self.punctual_lights,
+            background: &*self.background,
+            max_depth: self.settings.max_depth,
+            spectral: self.settings.spectral,
+        };
+        let mut pixels: Vec<PixelEstimate> = {
+            let accumulator = accumulator.lock().unwrap();
+            self.job_pixels(job)
+                .map(|(_, _, x, y)| *accumulator.get(x, y))
+                .collect()
+        };
+        for ((i, j, _, _), estimate) in self.job_pixels(job).zip(pixels.iter_mut()) {
+            // samples are numbered per pixel, so passes continue where the last one ended
+            let end = estimate.count().saturating_add(samples.max(0) as u32);
+            while estimate.count() < end && !self.is_pixel_finished(estimate) {
+                let sample = estimate.count();
+                let mut sampler = Sampler::for_sample(self.seed, i, j, sample);
+                let u = (i as f64 + sampler.gen::<f64>()) / (image_width - 1) as f64;
+                let v = (j as f64 + sampler.gen::<f64>()) / (image_height - 1) as f64;
+                let ray = self.camera.get_ray(u, v, &mut sampler);
+                estimate.add(tracer.ray_color(&ray, &mut sampler));
+            }
+        }
+        let mut accumulator = accumulator.lock().unwrap();
+        for ((_, _, x, y), estimate) in self.job_pixels(job).zip(pixels) {
+            accumulator.set(x, y, estimate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Dielectric, DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+
+    fn camera(aspect_ratio: f64) -> Camera {
+        Camera::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            aspect_ratio,
+            0.0,
+            1.0,
+            0.0,
+            1.0,
+        )
+    }
+
+    fn settings() -> RenderSettings {
+        RenderSettings {
+            image_width: 8,
+            fixed_height: None,
+            aspect_ratio: 2.0,
+            samples_per_pixel: 4,
+            max_depth: 4,
+            spectral: false,
+            adaptive: None,
+        }
+    }
+
+    #[test]
+    fn empty_world_renders_background_only() {
+        let world = HittableList::new();
+        let camera = camera(2.0);
+        let background = Vec3::new(0.25, 0.5, 0.75);
+        let image = Renderer::new(&world, &camera, settings())
+            .unwrap()
+            .background(background)
+            .render();
+        assert_eq!((image.width, image.height), (8, 4));
+        assert!(image.pixels().iter().all(|p| *p == background));
+    }
+
+    #[test]
+    fn images_need_two_pixels_each_way() {
+        let world = HittableList::new();
+        let camera = camera(2.0);
+        let size = |image_width, aspect_ratio| RenderSettings {
+            image_width,
+            aspect_ratio,
+            ..settings()
+        };
+        assert!(Renderer::new(&world, &camera, size(1, 1.0)).is_err());
+        assert!(Renderer::new(&world, &camera, size(3, 2.0)).is_err());
+        assert!(Renderer::new(&world, &camera, size(2, 1.0)).is_ok());
+    }
+
+    #[test]
+    fn region_is_placed_from_the_top() {
+        // a light filling the left half of the view
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(-1000.5, 0.0, -1.0),
+            1000.0,
+            Arc::new(DiffuseLight::new_color(Vec3::new(1.0, 1.0, 1.0))),
+        )));
+        let camera = camera(2.0);
+        let full = Renderer::new(&world, &camera, settings()).unwrap().render();
+        assert_eq!(full.get(0, 0), Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(full.get(7, 3), Vec3::new(0.0, 0.0, 0.0));
+
+        let cropped = Renderer::new(&world, &camera, settings())
+            .unwrap()
+            .region(Region::new(6, 1, 2, 3))
+            .unwrap()
+            .render();
+        assert_eq!((cropped.width, cropped.height), (2, 3));
+        assert_eq!(cropped.get(1, 2), full.get(7, 3));
+        assert!(Renderer::new(&world, &camera, settings())
+            .unwrap()
+            .region(Region::new(6, 1, 3, 3))
+            .is_err());
+    }
+
+    #[test]
+    fn same_seed_renders_identical_images() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, -100.5, -1.0),
+            100.0,
+            Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5))),
+        )));
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Dielectric::new(1.5)),
+        )));
+        let camera = camera(2.0);
+        let render = |seed| {
+            Renderer::new(&world, &camera, settings())
+                .unwrap()
+                .background(Vec3::new(0.7, 0.8, 1.0))
+                .seed(seed)
+                .render()
+        };
+        assert_eq!(render(3), render(3));
+        assert_ne!(render(3), render(4));
+    }
+
+    #[test]
+    fn adaptive_sampling_spends_samples_on_noise() {
+        // a diffuse ball on diffuse ground, shading each other, under a plain sky
+        let mut world = HittableList::new();
+        let grey = Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5)));
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, -100.5, -1.0),
+            100.0,
+            grey.clone(),
+        )));
+        world.add(Arc::new(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, grey)));
+        let camera = camera(2.0);
+        let settings = RenderSettings {
+            samples_per_pixel: 256,
+            adaptive: Some(AdaptiveSampling {
+                threshold: 0.02,
+                min_samples: 8,
+            }),
+            ..settings()
+        };
+        let (image, counts) = Renderer::new(&world, &camera, settings)
+            .unwrap()
+            .background(Vec3::new(0.7, 0.8, 1.0))
+            .render_counted(|| {});
+        assert_eq!(counts.len(), image.width * image.height);
+        assert_eq!(counts[0], 8);
+        assert!((image.get(0, 0) - Vec3::new(0.7, 0.8, 1.0)).length() < 1e-12);
+        assert!(counts.iter().all(|n| *n >= 8 && *n <= 256));
+        assert!(counts.iter().any(|n| *n > 64), "{:?}", counts);
+    }
+
+    #[test]
+    fn passes_add_up_to_a_single_render() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            Vec3::new(0.0, -100.5, -1.0),
+            100.0,
+            Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5))),
+        )));
+        let camera = camera(2.0);
+        let renderer = Renderer::new(&world, &camera, settings())
+            .unwrap()
+            .background(Vec3::new(0.7, 0.8, 1.0))
+            .seed(9);
+        let mut accumulator = renderer.accumulator();
+        renderer.render_pass(&mut accumulator, 3, || {});
+        assert!(!renderer.is_finished(&accumulator));
+        assert!(accumulator.sample_counts().iter().all(|n| *n == 3));
+        renderer.render_pass(&mut accumulator, 3, || {});
+        assert!(renderer.is_finished(&accumulator));
+        assert!(accumulator.sample_counts().iter().all(|n| *n == 4));
+
+        let single = renderer.render();
+        for (a, b) in accumulator.image().pixels().iter().zip(single.pixels()) {
+            assert!((*a - *b).length() < 1e-12);
+        }
+        let other_seed = Renderer::new(&world, &camera, settings()).unwrap().seed(10);
+        assert!(other_seed.check_accumulator(&accumulator).is_err());
+        let changed = |settings: RenderSettings| {
+            Renderer::new(&world, &camera, settings)
+                .unwrap()
+                .seed(9)
+                .check_accumulator(&accumulator)
+        };
+        assert!(changed(RenderSettings {
+            spectral: true,
+            ..settings()
+        })
+        .is_err());
+        assert!(changed(RenderSettings {
+            max_depth: 5,
+            ..settings()
+        })
+        .is_err());
+        assert!(changed(RenderSettings {
+            fixed_height: Some(4),
+            aspect_ratio: 2.1,
+            ..settings()
+        })
+        .is_err());
+        assert!(changed(RenderSettings {
+            adaptive: Some(AdaptiveSampling::new(0.05)),
+            ..settings()
+        })
+        .is_err());
+        assert!(changed(RenderSettings {
+            samples_per_pixel: 8,
+            ..settings()
+        })
+        .is_ok());
+        assert!(renderer.check_accumulator(&accumulator).is_ok());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::ray::Ray;
+use rand::rngs::SmallRng;
+use rand::{Error, RngCore, SeedableRng};
+
+/// Source of every random number used while building and rendering a scene.
+///
+/// Each camera sample gets its own sampler seeded from the render seed, the pixel and the
+/// sample index, so the result doesn't depend on how the work is scheduled across threads.
+pub struct Sampler {
+    rng: SmallRng,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Sampler {
+            rng: SmallRng::seed_from_u64(mix(seed)),
+        }
+    }
+
+    pub fn for_sample(seed: u64, x: usize, y: usize, sample: u32) -> Self {
+        Sampler::new(mix(mix(mix(seed) ^ x as u64) ^ y as u64) ^ sample as u64)
+    }
+
+    /// A sampler determined by the ray alone, for hittables that need random numbers
+    /// inside `Hittable::hit`. Every camera sample traces different rays, so the streams
+    /// differ between samples and seeds while staying reproducible.
+    pub fn for_ray(ray: &Ray) -> Self {
+        let mut seed = ray.time.to_bits();
+        for v in [ray.origin, ray.direction] {
+            for component in [v.x, v.y, v.z] {
+                seed = mix(seed ^ component.to_bits());
+            }
+        }
+        Sampler::new(seed)
+    }
+}
+
+/// splitmix64 finalizer, spreads nearby seeds far apart
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl RngCore for Sampler {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn same_sample_same_numbers() {
+        let a = Sampler::for_sample(7, 3, 4, 5).gen::<f64>();
+        let b = Sampler::for_sample(7, 3, 4, 5).gen::<f64>();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn neighbouring_samples_differ() {
+        let first = Sampler::for_sample(7, 3, 4, 5).gen::<u64>();
+        for (seed, x, y, sample) in [(8, 3, 4, 5), (7, 4, 4, 5), (7, 3, 5, 5), (7, 3, 4, 6)] {
+            assert_ne!(first, Sampler::for_sample(seed, x, y, sample).gen::<u64>());
+        }
+        // swapping x and y must not give the same stream
+        assert_ne!(
+            Sampler::for_sample(7, 3, 4, 5).gen::<u64>(),
+            Sampler::for_sample(7, 4, 3, 5).gen::<u64>()
+        );
+    }
+}
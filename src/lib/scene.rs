@@ -0,0 +1,1866 @@
+use crate::adaptive::AdaptiveSampling;
+use crate::animation::{Animation, Keyframe};
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::capsule::Capsule;
+use crate::constant_medium::ConstantMedium;
+use crate::csg::{CsgDifference, CsgIntersection, CsgUnion};
+use crate::cylinder::{Cone, Cylinder};
+use crate::environment::{Environment, EnvironmentLight, SolidBackground};
+use crate::error::Error;
+use crate::frame::Motion;
+use crate::hittable::{AnimatedInstance, Hittable, Instance};
+use crate::hittable_list::HittableList;
+use crate::light::{self, DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{
+    Conductor, Dielectric, DiffuseLight, Ior, Isotropic, Lambertian, Material, Metal, Principled,
+    RoughDielectric, Sides,
+};
+use crate::mybox::MyBox;
+use crate::obj;
+use crate::quad::{Disk, Plane, Quad};
+use crate::rectangle::{XYRectangle, XZRectangle, YZRectangle};
+use crate::render::RenderSettings;
+use crate::sampler::Sampler;
+use crate::scene_parser::{self, Node};
+use crate::sdf::{
+    Mandelbulb, Repeat, RoundedBox, Sdf, SdfHittable, SdfSphere, SdfTorus, SmoothUnion, Twist,
+};
+use crate::sky::{self, Sky};
+use crate::sphere::Sphere;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+use crate::torus::Torus;
+use crate::transform::{Matrix, Transform};
+use crate::triangle::Triangle;
+use crate::vec::Vec3;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+pub struct Scene {
+    pub world: HittableList,
+    /// Emitters in `world` that the renderer samples directly
+    pub lights: HittableList,
+    /// Point, spot and directional lights, which aren't in `world`
+    pub punctual_lights: Vec<Arc<dyn Light>>,
+    pub camera: Camera,
+    /// What rays that leave the scene see
+    pub background: Arc<dyn Environment>,
+    pub settings: RenderSettings,
+}
+
+const HITTABLES: [&str; 25] = [
+    "sphere",
+    "triangle",
+    "mesh",
+    "quad",
+    "disk",
+    "plane",
+    "cylinder",
+    "cone",
+    "torus",
+    "capsule",
+    "xy_rect",
+    "xz_rect",
+    "yz_rect",
+    "box",
+    "constant_medium",
+    "translate",
+    "rotate_y",
+    "transform",
+    "animate",
+    "bvh",
+    "list",
+    "union",
+    "intersection",
+    "difference",
+    "sdf",
+];
+
+/// Names of the shapes inside an `sdf`
+const SDF_SHAPES: [&str; 7] = [
+    "sphere",
+    "rounded_box",
+    "torus",
+    "mandelbulb",
+    "smooth_union",
+    "repeat",
+    "twist",
+];
+
+pub fn load_file<P: AsRef<Path>>(path: P, sampler: &mut Sampler) -> Result<Scene, Error> {
+    let source = std::fs::read_to_string(&path).map_err(|e| {
+        Error::from(e).explanation(&format!(
+            "Unable to read scene file {}",
+            path.as_ref().display()
+        ))
+    })?;
+    let directory = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+    parse(&source, directory, sampler)
+}
+
+/// Builds a scene from the text of a scene file. See `scene_parser` for the syntax.
+/// Images and models it names are read relative to `directory`. Random parts of the
+/// scene, such as noise textures, are drawn from `sampler`.
+pub fn parse(source: &str, directory: &Path, sampler: &mut Sampler) -> Result<Scene, Error> {
+    let nodes = scene_parser::parse(source)?;
+    let shutter = match nodes.iter().find(|node| node.name == "camera") {
+        Some(node) => shutter(node)?,
+        None => (0.0, 1.0),
+    };
+    let mut loader = Loader {
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        emissive: HashSet::new(),
+        directory,
+        shutter,
+        sampler,
+    };
+
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+    let mut punctual_lights = Vec::new();
+    let mut settings = RenderSettings::default();
+    let mut background: Option<Arc<dyn Environment>> = None;
+    let mut camera_node = None;
+
+    for node in &nodes {
+        match node.name.as_str() {
+            "camera" => {
+                if camera_node.is_some() {
+                    return Err(node.error("Duplicate camera"));
+                }
+                camera_node = Some(node);
+            }
+            "render" => settings = render_settings(node)?,
+            "background" | "environment" | "sky" => {
+                if background.is_some() {
+                    return Err(node.error("Only one background, environment or sky is allowed"));
+                }
+                background = Some(environment(node, directory)?);
+            }
+            "texture" => loader.define_texture(node)?,
+            "material" => loader.define_material(node)?,
+            "light" => {
+                node.arg_count(1)?;
+                if node.ident_at(0)? == "area" {
+                    let light = area_light(node)?;
+                    lights.add(light.clone());
+                    world.add(light);
+                } else {
+                    punctual_lights.push(punctual_light(node)?);
+                }
+            }
+            _ => {
+                let hittable = loader.hittable(node)?;
+                if loader.is_light(node) {
+                    lights.add(hittable.clone());
+                }
+                world.add(hittable);
+            }
+        }
+    }
+
+    let camera = match camera_node {
+        Some(node) => camera(node, settings.aspect_ratio)?,
+        None => return Err(Error::parse(1, 1, "Scene has no camera")),
+    };
+
+    Ok(Scene {
+        world,
+        lights,
+        punctual_lights,
+        camera,
+        background: background
+            .unwrap_or_else(|| Arc::new(SolidBackground::new(Vec3::new(0.0, 0.0, 0.0)))),
+        settings,
+    })
+}
+
+/// One of `background R G B`, `environment { file "sky.hdr"; rotation DEGREES; intensity X }`
+/// or `sky { sun X Y Z; turbidity T; ground_albedo R G B; sun_size DEGREES; intensity X }`.
+fn environment(node: &Node, directory: &Path) -> Result<Arc<dyn Environment>, Error> {
+    match node.name.as_str() {
+        "background" => return Ok(Arc::new(SolidBackground::new(node.vec3()?))),
+        "sky" => {
+            node.allow_only(&["sun", "turbidity", "ground_albedo", "sun_size", "intensity"])?;
+            let sun = node.require("sun")?;
+            let sun_direction = sun.vec3()?;
+            if sun_direction.near_zero() {
+                return Err(sun.error("The direction towards the sun can't be zero"));
+            }
+            let sky = Sky::new(
+                sun_direction,
+                optional(node, "turbidity", Node::number)?.unwrap_or(3.0),
+                optional(node, "ground_albedo", Node::vec3)?.unwrap_or(Vec3::new(0.3, 0.3, 0.3)),
+            )
+            .sun_size(optional(node, "sun_size", Node::number)?.unwrap_or(sky::SUN_RADIUS))
+            .intensity(optional(node, "intensity", Node::number)?.unwrap_or(1.0));
+            return Ok(Arc::new(sky));
+        }
+        _ => {}
+    }
+    node.allow_only(&["file", "rotation", "intensity"])?;
+    let file = node.require("file")?;
+    file.arg_count(1)?;
+    let map = EnvironmentLight::load_file(directory.join(file.string_at(0)?))
+        .map_err(|e| moved_to(file, e))?
+        .rotation(optional(node, "rotation", Node::number)?.unwrap_or(0.0))
+        .intensity(optional(node, "intensity", Node::number)?.unwrap_or(1.0));
+    Ok(Arc::new(map))
+}
+
+/// `error`, loading the file named by `node`, reported at the node.
+fn moved_to(node: &Node, error: Error) -> Error {
+    node.error(
+        error
+            .explanation
+            .as_deref()
+            .unwrap_or("Unable to load file"),
+    )
+}
+
+/// The tint `color R G B`, white if left out, times exactly one of the quantities `names`.
+/// Also returns which one was given.
+fn emission(node: &Node, names: &[&str]) -> Result<(usize, Vec3), Error> {
+    let color = optional(node, "color", Node::vec3)?.unwrap_or(Vec3::new(1.0, 1.0, 1.0));
+    let mut given = names
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| node.child(name).map(|n| (i, n)));
+    match (given.next(), given.next()) {
+        (Some((i, n)), None) => Ok((i, color * n.number()?)),
+        _ => Err(node.error(&format!(
+            "'{} {}' needs exactly one of '{}'",
+            node.name,
+            node.ident_at(0)?,
+            names.join("' or '")
+        ))),
+    }
+}
+
+/// `light point { position X Y Z; power W }`, with `intensity` instead of `power` in watts
+/// per steradian, `light spot { ...; look_at X Y Z; cone_angle DEGREES; cone_falloff
+/// DEGREES }` or `light directional { direction X Y Z; irradiance E }`. All take a `color`.
+fn punctual_light(node: &Node) -> Result<Arc<dyn Light>, Error> {
+    let light: Arc<dyn Light> = match node.ident_at(0)? {
+        "point" => {
+            node.allow_only(&["position", "color", "power", "intensity"])?;
+            let position = node.require("position")?.vec3()?;
+            match emission(node, &["power", "intensity"])? {
+                (0, power) => Arc::new(PointLight::from_power(position, power)),
+                (_, intensity) => Arc::new(PointLight::new(position, intensity)),
+            }
+        }
+        "spot" => {
+            node.allow_only(&[
+                "position",
+                "look_at",
+                "cone_angle",
+                "cone_falloff",
+                "color",
+                "power",
+                "intensity",
+            ])?;
+            let position = node.require("position")?.vec3()?;
+            let look_at = node.require("look_at")?;
+            let direction = look_at.vec3()? - position;
+            if direction.near_zero() {
+                return Err(look_at.error("A spot light can't look at its own position"));
+            }
+            let cone_angle = optional(node, "cone_angle", Node::number)?.unwrap_or(30.0);
+            let falloff = optional(node, "cone_falloff", Node::number)?.unwrap_or(5.0);
+            match emission(node, &["power", "intensity"])? {
+                (0, power) => Arc::new(SpotLight::from_power(
+                    position, direction, cone_angle, falloff, power,
+                )),
+                (_, intensity) => Arc::new(SpotLight::new(
+                    position, direction, cone_angle, falloff, intensity,
+                )),
+            }
+        }
+        "directional" => {
+            node.allow_only(&["direction", "color", "irradiance"])?;
+            let direction = node.require("direction")?;
+            if direction.vec3()?.near_zero() {
+                return Err(direction.error("The direction of a light can't be zero"));
+            }
+            let (_, irradiance) = emission(node, &["irradiance"])?;
+            Arc::new(DirectionalLight::new(direction.vec3()?, irradiance))
+        }
+        kind => return Err(node.error(&format!("Unknown light type '{}'", kind))),
+    };
+    Ok(light)
+}
+
+/// `light area { xz_rect { x X0 X1; z Z0 Z1; k Y }; power W }`, or a `sphere { center X Y Z;
+/// radius R }`, with `radiance` instead of `power`, a `color` and `sides front|back|both`.
+fn area_light(node: &Node) -> Result<Arc<dyn Hittable>, Error> {
+    node.allow_only(&["xz_rect", "sphere", "color", "power", "radiance", "sides"])?;
+    let sides = match node.child("sides") {
+        None => Sides::Both,
+        Some(n) => match n.ident()? {
+            "front" => Sides::Front,
+            "back" => Sides::Back,
+            "both" => Sides::Both,
+            other => {
+                return Err(n.error(&format!(
+                    "Unknown side '{}', expected front, back or both",
+                    other
+                )))
+            }
+        },
+    };
+    let (kind, emission) = emission(node, &["power", "radiance"])?;
+    let material = |area: f64| -> Arc<dyn Material> {
+        let radiance = match kind {
+            0 => light::area_radiance(emission, area, sides),
+            _ => emission,
+        };
+        Arc::new(DiffuseLight::new_color(radiance).sides(sides))
+    };
+    match (node.child("xz_rect"), node.child("sphere")) {
+        (Some(rect), None) => {
+            rect.arg_count(0)?;
+            rect.allow_only(&["x", "z", "k"])?;
+            let (x0, x1) = rect.require("x")?.pair()?;
+            let (z0, z1) = rect.require("z")?.pair()?;
+            if x0 >= x1 || z0 >= z1 {
+                return Err(rect.error("The rectangle of an area light can't be empty"));
+            }
+            let k = rect.require("k")?.number()?;
+            let area = (x1 - x0) * (z1 - z0);
+            Ok(Arc::new(XZRectangle::new(
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material(area),
+            )))
+        }
+        (None, Some(sphere)) => {
+            sphere.arg_count(0)?;
+            sphere.allow_only(&["center", "radius"])?;
+            let radius = sphere.require("radius")?.number()?;
+            if radius <= 0.0 {
+                return Err(sphere.error("The radius of an area light must be positive"));
+            }
+            let area = 4.0 * std::f64::consts::PI * radius * radius;
+            Ok(Arc::new(Sphere::new(
+                sphere.require("center")?.vec3()?,
+                radius,
+                material(area),
+            )))
+        }
+        _ => Err(node.error("An area light needs exactly one 'xz_rect' or 'sphere'")),
+    }
+}
+
+fn render_settings(node: &Node) -> Result<RenderSettings, Error> {
+    node.allow_only(&[
+        "image_width",
+        "aspect_ratio",
+        "samples_per_pixel",
+        "max_depth",
+        "spectral",
+        "adaptive",
+        "min_samples",
+    ])?;
+    let mut settings = RenderSettings::default();
+    if let Some(n) = node.child("image_width") {
+        settings.image_width = n.integer()?;
+    }
+    if let Some(n) = node.child("aspect_ratio") {
+        // either `aspect_ratio 1.5` or `aspect_ratio 16 9`
+        settings.aspect_ratio = if n.args.len() == 2 {
+            n.number_at(0)? / n.number_at(1)?
+        } else {
+            n.number()?
+        };
+        if !(settings.aspect_ratio > 0.0 && settings.aspect_ratio.is_finite()) {
+            return Err(n.error("aspect_ratio must be positive"));
+        }
+    }
+    if let Some(n) = node.child("samples_per_pixel") {
+        settings.samples_per_pixel = n.integer()? as i32;
+    }
+    if let Some(n) = node.child("max_depth") {
+        settings.max_depth = n.integer()? as i32;
+    }
+    if let Some(n) = node.child("spectral") {
+        n.arg_count(0)?;
+        settings.spectral = true;
+    }
+    if let Some(n) = node.child("adaptive") {
+        let threshold = n.number()?;
+        if threshold <= 0.0 {
+            return Err(n.error("The adaptive sampling threshold must be positive"));
+        }
+        settings.adaptive = Some(AdaptiveSampling::new(threshold));
+    }
+    if let Some(n) = node.child("min_samples") {
+        match settings.adaptive.as_mut() {
+            Some(adaptive) => adaptive.min_samples = n.integer()? as i32,
+            None => return Err(n.error("'min_samples' needs 'adaptive'")),
+        }
+    }
+    if settings.image_width < 2 || settings.image_height() < 2 {
+        return Err(node.error("Image must be at least two pixels wide and high"));
+    }
+    Ok(settings)
+}
+
+/// The index of refraction of a material, from exactly one of `ir`, `cauchy` or `sellmeier`.
+fn ior(node: &Node) -> Result<Ior, Error> {
+    match (
+        node.child("ir"),
+        node.child("cauchy"),
+        node.child("sellmeier"),
+    ) {
+        (Some(n), None, None) => Ok(Ior::Constant(n.number()?)),
+        (None, Some(n), None) => {
+            n.arg_count(2)?;
+            Ok(Ior::Cauchy {
+                a: n.number_at(0)?,
+                b: n.number_at(1)?,
+            })
+        }
+        (None, None, Some(n)) => {
+            n.arg_count(6)?;
+            Ok(Ior::Sellmeier {
+                b: [n.number_at(0)?, n.number_at(1)?, n.number_at(2)?],
+                c: [n.number_at(3)?, n.number_at(4)?, n.number_at(5)?],
+            })
+        }
+        _ => Err(node.error(&format!(
+            "'{}' needs exactly one of 'ir', 'cauchy' or 'sellmeier'",
+            node.ident_at(1).unwrap_or(&node.name)
+        ))),
+    }
+}
+
+/// The axis and angle of `rotate y 30` or `rotate 1 1 0 30`.
+fn rotation(node: &Node) -> Result<(Vec3, f64), Error> {
+    match node.args.len() {
+        2 => {
+            let axis = match node.ident_at(0)? {
+                "x" => Vec3::new(1.0, 0.0, 0.0),
+                "y" => Vec3::new(0.0, 1.0, 0.0),
+                "z" => Vec3::new(0.0, 0.0, 1.0),
+                axis => return Err(node.error(&format!("Unknown axis '{}'", axis))),
+            };
+            Ok((axis, node.number_at(1)?))
+        }
+        4 => {
+            let axis = node.vec3_at(0)?;
+            if axis.length_squared() == 0.0 {
+                return Err(node.error("Rotation axis can't be zero"));
+            }
+            Ok((axis, node.number_at(3)?))
+        }
+        _ => Err(node.error("'rotate' takes an axis and an angle")),
+    }
+}
+
+/// The factors of `scale S` or `scale X Y Z`.
+fn scale(node: &Node) -> Result<Vec3, Error> {
+    match node.args.len() {
+        1 => {
+            let s = node.number_at(0)?;
+            Ok(Vec3::new(s, s, s))
+        }
+        _ => node.vec3(),
+    }
+}
+
+/// `keyframe TIME { scale ...; rotate ...; offset X Y Z }`, where the object is at `TIME`.
+/// The steps are always applied in that order.
+fn keyframe(node: &Node) -> Result<Keyframe, Error> {
+    node.arg_count(1)?;
+    node.allow_only(&["offset", "rotate", "scale"])?;
+    let mut keyframe = Keyframe::new(node.number_at(0)?);
+    if let Some(offset) = optional(node, "offset", Node::vec3)? {
+        keyframe = keyframe.offset(offset);
+    }
+    if let Some((axis, angle)) = optional(node, "rotate", rotation)? {
+        keyframe = keyframe.rotate(axis, angle);
+    }
+    if let Some(factors) = optional(node, "scale", scale)? {
+        keyframe = keyframe.scale(factors);
+    }
+    Ok(keyframe)
+}
+
+/// The operations of a `transform` node, applied in the order they're listed.
+fn transform(node: &Node) -> Result<Transform, Error> {
+    let mut transform = Transform::identity();
+    for child in &node.children {
+        let step = match child.name.as_str() {
+            "offset" => Transform::translation(child.vec3()?),
+            "rotate" => {
+                let (axis, angle) = rotation(child)?;
+                Transform::rotation(axis, angle)
+            }
+            "scale" => Transform::scaling(scale(child)?)
+                .ok_or_else(|| child.error("Scale factors can't be zero"))?,
+            "matrix" => {
+                // twelve numbers, the first three rows of an affine matrix
+                child.arg_count(12)?;
+                let mut matrix = Matrix::identity();
+                for (i, value) in matrix.m.iter_mut().flatten().take(12).enumerate() {
+                    *value = child.number_at(i)?;
+                }
+                Transform::new(matrix).ok_or_else(|| child.error("Matrix can't be inverted"))?
+            }
+            // the objects being transformed
+            name if HITTABLES.contains(&name) => continue,
+            name => {
+                return Err(child.error(&format!("Unknown property '{}' in '{}'", name, node.name)))
+            }
+        };
+        transform = transform.then(&step);
+    }
+    Ok(transform)
+}
+
+fn camera(node: &Node, aspect_ratio: f64) -> Result<Camera, Error> {
+    node.allow_only(&[
+        "look_from",
+        "look_at",
+        "vup",
+        "vfov",
+        "aperture",
+        "focus_distance",
+        "time",
+    ])?;
+    let look_from = node.require("look_from")?.vec3()?;
+    let look_at = node.require("look_at")?.vec3()?;
+    let vup = optional(node, "vup", Node::vec3)?.unwrap_or_else(|| Vec3::new(0.0, 1.0, 0.0));
+    let vfov = optional(node, "vfov", Node::number)?.unwrap_or(40.0);
+    let aperture = optional(node, "aperture", Node::number)?.unwrap_or(0.0);
+    let focus_distance = optional(node, "focus_distance", Node::number)?.unwrap_or(10.0);
+    let (time_start, time_end) = shutter(node)?;
+    Ok(Camera::new(
+        look_from,
+        look_at,
+        vup,
+        vfov,
+        aspect_ratio,
+        aperture,
+        focus_distance,
+        time_start,
+        time_end,
+    ))
+}
+
+/// When the camera's shutter opens and closes, `time T0 T1`.
+fn shutter(camera: &Node) -> Result<(f64, f64), Error> {
+    Ok(optional(camera, "time", Node::pair)?.unwrap_or((0.0, 1.0)))
+}
+
+fn optional<T>(
+    node: &Node,
+    name: &str,
+    f: fn(&Node) -> Result<T, Error>,
+) -> Result<Option<T>, Error> {
+    node.child(name).map(f).transpose()
+}
+
+/// Whether a flag without arguments, such as `open`, is given.
+fn flag(node: &Node, name: &str) -> Result<bool, Error> {
+    match node.child(name) {
+        Some(n) => {
+            n.arg_count(0)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// A required number above zero, such as a radius.
+fn positive(node: &Node, name: &str) -> Result<f64, Error> {
+    let n = node.require(name)?;
+    let value = n.number()?;
+    if value <= 0.0 {
+        return Err(n.error(&format!("'{}' must be positive", name)));
+    }
+    Ok(value)
+}
+
+/// The two ends of an axis, such as the base and top of a cylinder, which can't coincide.
+fn axis(node: &Node, start: &str, end: &str) -> Result<(Vec3, Vec3), Error> {
+    let (a, b) = (node.require(start)?.vec3()?, node.require(end)?.vec3()?);
+    if (b - a).near_zero() {
+        return Err(node.error(&format!(
+            "'{}' and '{}' can't be the same point",
+            start, end
+        )));
+    }
+    Ok((a, b))
+}
+
+/// `motion X Y Z`, how far an object moves between the two times of `time T0 T1`, which
+/// default to 0 and 1.
+fn motion(node: &Node) -> Result<Motion, Error> {
+    let (time_start, time_end) = optional(node, "time", Node::pair)?.unwrap_or((0.0, 1.0));
+    match node.child("motion") {
+        Some(offset) => {
+            if time_end <= time_start {
+                return Err(node.error("The end of 'time' must come after its start"));
+            }
+            Ok(Motion::new(offset.vec3()?, time_start, time_end))
+        }
+        None => Ok(Motion::none()),
+    }
+}
+
+/// Like `Node::allow_only`, but also accepts any child named in `nested`.
+fn allow_only_with(node: &Node, allowed: &[&str], nested: &[&str]) -> Result<(), Error> {
+    let properties = Node {
+        children: node
+            .children
+            .iter()
+            .filter(|c| !nested.contains(&c.name.as_str()))
+            .cloned()
+            .collect(),
+        ..node.clone()
+    };
+    properties.allow_only(allowed)
+}
+
+/// The shapes nested in `node`, which must hold at least `min` and at most `max`.
+fn sdf_children(node: &Node, min: usize, max: usize) -> Result<Vec<Arc<dyn Sdf>>, Error> {
+    let shapes = node
+        .children
+        .iter()
+        .filter(|c| SDF_SHAPES.contains(&c.name.as_str()))
+        .map(sdf_shape)
+        .collect::<Result<Vec<_>, _>>()?;
+    if shapes.len() < min || shapes.len() > max {
+        let expected = match (min, max) {
+            (1, 1) => "one shape".to_string(),
+            (min, usize::MAX) => format!("at least {} shapes", min),
+            (min, max) => format!("{} to {} shapes", min, max),
+        };
+        return Err(node.error(&format!("'{}' needs {}", node.name, expected)));
+    }
+    Ok(shapes)
+}
+
+/// A shape inside an `sdf`, which has no material of its own.
+fn sdf_shape(node: &Node) -> Result<Arc<dyn Sdf>, Error> {
+    node.arg_count(0)?;
+    let shape: Arc<dyn Sdf> = match node.name.as_str() {
+        "sphere" => {
+            node.allow_only(&["center", "radius"])?;
+            Arc::new(SdfSphere::new(
+                node.require("center")?.vec3()?,
+                positive(node, "radius")?,
+            ))
+        }
+        "rounded_box" => {
+            node.allow_only(&["center", "size", "radius"])?;
+            let size = node.require("size")?.vec3()?;
+            if size.x <= 0.0 || size.y <= 0.0 || size.z <= 0.0 {
+                return Err(node.error("'size' must be positive"));
+            }
+            let radius = optional(node, "radius", Node::number)?.unwrap_or(0.0);
+            if radius < 0.0 || 2.0 * radius > size.x.min(size.y).min(size.z) {
+                return Err(
+                    node.error("'radius' must be between 0 and half the shortest side of the box")
+                );
+            }
+            Arc::new(RoundedBox::new(
+                node.require("center")?.vec3()?,
+                size,
+                radius,
+            ))
+        }
+        "torus" => {
+            node.allow_only(&["center", "major_radius", "minor_radius"])?;
+            Arc::new(SdfTorus::new(
+                node.require("center")?.vec3()?,
+                positive(node, "major_radius")?,
+                positive(node, "minor_radius")?,
+            ))
+        }
+        "mandelbulb" => {
+            node.allow_only(&["center", "scale", "power", "iterations"])?;
+            let scale = match node.child("scale") {
+                Some(_) => positive(node, "scale")?,
+                None => 1.0,
+            };
+            let power = optional(node, "power", Node::number)?.unwrap_or(8.0);
+            if power < 2.0 {
+                return Err(node.error("'power' must be at least 2"));
+            }
+            Arc::new(Mandelbulb::new(
+                node.require("center")?.vec3()?,
+                scale,
+                power,
+                optional(node, "iterations", Node::integer)?.unwrap_or(10),
+            ))
+        }
+        "smooth_union" => {
+            allow_only_with(node, &["smoothness"], &SDF_SHAPES)?;
+            let smoothness = optional(node, "smoothness", Node::number)?.unwrap_or(0.0);
+            if smoothness < 0.0 {
+                return Err(node.error("'smoothness' can't be negative"));
+            }
+            let mut shapes = sdf_children(node, 2, usize::MAX)?.into_iter();
+            let first = shapes.next().unwrap();
+            shapes.fold(first, |left, right| {
+                Arc::new(SmoothUnion::new(left, right, smoothness))
+            })
+        }
+        "repeat" => {
+            allow_only_with(node, &["spacing", "count"], &SDF_SHAPES)?;
+            let spacing = node.require("spacing")?.vec3()?;
+            let count_node = node.require("count")?;
+            count_node.arg_count(3)?;
+            let mut count = [0; 3];
+            for (axis, n) in count.iter_mut().enumerate() {
+                let value = count_node.number_at(axis)?;
+                if value < 1.0 || value.fract() != 0.0 {
+                    return Err(count_node.error("'count' must be whole numbers of at least 1"));
+                }
+                *n = value as usize;
+            }
+            let spacings = [spacing.x, spacing.y, spacing.z];
+            if (0..3).any(|axis| count[axis] > 1 && spacings[axis] <= 0.0) {
+                return Err(node
+                    .error("'spacing' must be positive along every axis with more than one copy"));
+            }
+            let shape = sdf_children(node, 1, 1)?.pop().unwrap();
+            Arc::new(Repeat::new(shape, spacing, count))
+        }
+        "twist" => {
+            allow_only_with(node, &["rate"], &SDF_SHAPES)?;
+            let rate = node.require("rate")?.number()?.to_radians();
+            let shape = sdf_children(node, 1, 1)?.pop().unwrap();
+            Arc::new(Twist::new(shape, rate))
+        }
+        name => return Err(node.error(&format!("Unknown shape '{}'", name))),
+    };
+    Ok(shape)
+}
+
+struct Loader<'a> {
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    /// Names of the materials that emit light
+    emissive: HashSet<String>,
+    /// Where files named in the scene are read from
+    directory: &'a Path,
+    /// The camera's shutter interval, which nested hierarchies bound moving objects over
+    shutter: (f64, f64),
+    sampler: &'a mut Sampler,
+}
+
+impl<'a> Loader<'a> {
+    fn texture(&self, node: &Node) -> Result<Arc<dyn Texture>, Error> {
+        let name = node.ident()?;
+        self.textures
+            .get(name)
+            .cloned()
+            .ok_or_else(|| node.error(&format!("Unknown texture '{}'", name)))
+    }
+
+    fn material(&self, node: &Node) -> Result<Arc<dyn Material>, Error> {
+        let name = node.ident()?;
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| node.error(&format!("Unknown material '{}'", name)))
+    }
+
+    /// Either a `color r g b` or a `texture name` property.
+    fn color_or_texture(&self, node: &Node, color: &str) -> Result<Arc<dyn Texture>, Error> {
+        match (node.child(color), node.child("texture")) {
+            (Some(c), None) => Ok(Arc::new(SolidColor::new_color(c.vec3()?))),
+            (None, Some(t)) => self.texture(t),
+            _ => Err(node.error(&format!(
+                "'{}' needs exactly one of '{}' or 'texture'",
+                node.name, color
+            ))),
+        }
+    }
+
+    /// A material parameter given as `name` followed by one or three numbers, or as
+    /// `name_texture` and a texture. Parameters with a `default` may be left out.
+    fn parameter(
+        &self,
+        node: &Node,
+        name: &str,
+        default: Option<f64>,
+    ) -> Result<Arc<dyn Texture>, Error> {
+        let texture = format!("{}_texture", name);
+        let constant = |value: f64| -> Arc<dyn Texture> {
+            Arc::new(SolidColor::new_color(Vec3::new(value, value, value)))
+        };
+        match (node.child(name), node.child(&texture), default) {
+            (Some(n), None, _) if n.args.len() == 3 => {
+                Ok(Arc::new(SolidColor::new_color(n.vec3()?)))
+            }
+            (Some(n), None, _) => Ok(constant(n.number()?)),
+            (None, Some(t), _) => self.texture(t),
+            (None, None, Some(value)) => Ok(constant(value)),
+            (None, None, None) => Err(node.error(&format!(
+                "'{}' needs either '{}' or '{}'",
+                node.ident_at(1).unwrap_or(&node.name),
+                name,
+                texture
+            ))),
+            (Some(_), Some(_), _) => {
+                Err(node.error(&format!("'{}' and '{}' can't both be given", name, texture)))
+            }
+        }
+    }
+
+    fn define_texture(&mut self, node: &Node) -> Result<(), Error> {
+        node.arg_count(2)?;
+        let name = node.ident_at(0)?;
+        let texture: Arc<dyn Texture> = match node.ident_at(1)? {
+            "solid" => {
+                node.allow_only(&["color"])?;
+                Arc::new(SolidColor::new_color(node.require("color")?.vec3()?))
+            }
+            "checker" => {
+                node.allow_only(&["even", "odd", "even_texture", "odd_texture"])?;
+                let side = |color: &str, texture: &str| -> Result<Arc<dyn Texture>, Error> {
+                    match (node.child(color), node.child(texture)) {
+                        (Some(c), None) => Ok(Arc::new(SolidColor::new_color(c.vec3()?))),
+                        (None, Some(t)) => self.texture(t),
+                        _ => Err(node.error(&format!(
+                            "checker needs exactly one of '{}' or '{}'",
+                            color, texture
+                        ))),
+                    }
+                };
+                Arc::new(CheckerTexture::new_texture(
+                    side("even", "even_texture")?,
+                    side("odd", "odd_texture")?,
+                ))
+            }
+            "noise" => {
+                node.allow_only(&["scale"])?;
+                let scale = optional(node, "scale", Node::number)?.unwrap_or(1.0);
+                Arc::new(NoiseTexture::new_scaled(scale, self.sampler))
+            }
+            "image" => {
+                node.allow_only(&["file"])?;
+                let file = node.require("file")?;
+                file.arg_count(1)?;
+                let path = self.directory.join(file.string_at(0)?);
+                Arc::new(ImageTexture::load_file(path).map_err(|e| moved_to(file, e))?)
+            }
+            kind => return Err(node.error(&format!("Unknown texture type '{}'", kind))),
+        };
+        self.textures.insert(name.to_owned(), texture);
+        Ok(())
+    }
+
+    fn define_material(&mut self, node: &Node) -> Result<(), Error> {
+        node.arg_count(2)?;
+        let name = node.ident_at(0)?;
+        let material: Arc<dyn Material> = match node.ident_at(1)? {
+            "lambertian" => {
+                node.allow_only(&["color", "texture"])?;
+                Arc::new(Lambertian::new_texture(
+                    self.color_or_texture(node, "color")?,
+                ))
+            }
+            "metal" => {
+                node.allow_only(&["albedo", "fuzz"])?;
+                Arc::new(Metal::new(
+                    node.require("albedo")?.vec3()?,
+                    optional(node, "fuzz", Node::number)?.unwrap_or(0.0),
+                ))
+            }
+            "dielectric" => {
+                node.allow_only(&["ir", "cauchy", "sellmeier"])?;
+                Arc::new(Dielectric::with_ior(ior(node)?))
+            }
+            "conductor" => {
+                node.allow_only(&[
+                    "metal",
+                    "eta",
+                    "eta_texture",
+                    "k",
+                    "k_texture",
+                    "roughness",
+                    "roughness_texture",
+                ])?;
+                let roughness = self.parameter(node, "roughness", Some(0.0))?;
+                match node.child("metal") {
+                    Some(metal) => {
+                        if node.child("eta").is_some() || node.child("k").is_some() {
+                            return Err(node.error("'metal' replaces 'eta' and 'k'"));
+                        }
+                        let name = metal.ident()?;
+                        Arc::new(Conductor::preset(name, roughness).ok_or_else(|| {
+                            metal.error(&format!(
+                                "Unknown metal '{}', expected gold, silver, copper or aluminium",
+                                name
+                            ))
+                        })?)
+                    }
+                    None => Arc::new(Conductor::new(
+                        self.parameter(node, "eta", None)?,
+                        self.parameter(node, "k", None)?,
+                        roughness,
+                    )),
+                }
+            }
+            "rough_dielectric" => {
+                node.allow_only(&[
+                    "ir",
+                    "cauchy",
+                    "sellmeier",
+                    "roughness",
+                    "roughness_texture",
+                ])?;
+                Arc::new(RoughDielectric::new(
+                    ior(node)?,
+                    self.parameter(node, "roughness", Some(0.0))?,
+                ))
+            }
+            "principled" => {
+                let scalars = [
+                    ("metallic", 0.0),
+                    ("roughness", 0.5),
+                    ("specular", 0.5),
+                    ("clearcoat", 0.0),
+                    ("sheen", 0.0),
+                    ("transmission", 0.0),
+                ];
+                let mut allowed = vec!["color", "texture", "ir", "cauchy", "sellmeier"];
+                for (name, _) in &scalars {
+                    allowed.push(name);
+                }
+                let textures = scalars
+                    .iter()
+                    .map(|(name, _)| format!("{}_texture", name))
+                    .collect::<Vec<_>>();
+                allowed.extend(textures.iter().map(String::as_str));
+                node.allow_only(&allowed)?;
+
+                let mut principled = Principled::new(self.color_or_texture(node, "color")?);
+                let mut values = scalars
+                    .iter()
+                    .map(|(name, default)| self.parameter(node, name, Some(*default)))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter();
+                principled.metallic = values.next().unwrap();
+                principled.roughness = values.next().unwrap();
+                principled.specular = values.next().unwrap();
+                principled.clearcoat = values.next().unwrap();
+                principled.sheen = values.next().unwrap();
+                principled.transmission = values.next().unwrap();
+                if node.child("ir").is_some()
+                    || node.child("cauchy").is_some()
+                    || node.child("sellmeier").is_some()
+                {
+                    principled.ior = ior(node)?;
+                }
+                Arc::new(principled)
+            }
+            "diffuse_light" => {
+                node.allow_only(&["color", "texture"])?;
+                self.emissive.insert(name.to_owned());
+                Arc::new(DiffuseLight::new(self.color_or_texture(node, "color")?))
+            }
+            "isotropic" => {
+                node.allow_only(&["color", "texture"])?;
+                Arc::new(Isotropic::new(self.color_or_texture(node, "color")?))
+            }
+            kind => return Err(node.error(&format!("Unknown material type '{}'", kind))),
+        };
+        self.materials.insert(name.to_owned(), material);
+        Ok(())
+    }
+
+    /// Whether a top-level object can be sampled as a light: a sphere or flat shape with an
+    /// emissive material, possibly moved by `translate`, `rotate_y` and `transform`.
+    fn is_light(&self, node: &Node) -> bool {
+        match node.name.as_str() {
+            "sphere" | "triangle" | "quad" | "disk" | "xy_rect" | "xz_rect" | "yz_rect" => node
+                .child("material")
+                .and_then(|m| m.ident().ok())
+                .is_some_and(|name| self.emissive.contains(name)),
+            "translate" | "rotate_y" | "transform" => {
+                let mut children = node
+                    .children
+                    .iter()
+                    .filter(|c| HITTABLES.contains(&c.name.as_str()));
+                match (children.next(), children.next()) {
+                    (Some(child), None) => self.is_light(child),
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// The transform of a `translate`, `rotate_y` or `transform` node and the object it
+    /// moves. Directly nested transforms are combined into one.
+    fn instance(&mut self, node: &Node) -> Result<(Transform, Arc<dyn Hittable>), Error> {
+        let transform = match node.name.as_str() {
+            "translate" => {
+                self.allow_only_with_children(node, &["offset"])?;
+                Transform::translation(node.require("offset")?.vec3()?)
+            }
+            "rotate_y" => {
+                self.allow_only_with_children(node, &["angle"])?;
+                Transform::rotation_y(node.require("angle")?.number()?)
+            }
+            _ => {
+                self.allow_only_with_children(node, &["offset", "rotate", "scale", "matrix"])?;
+                transform(node)?
+            }
+        };
+        let mut children = node
+            .children
+            .iter()
+            .filter(|c| HITTABLES.contains(&c.name.as_str()));
+        if let (Some(child), None) = (children.next(), children.next()) {
+            if ["translate", "rotate_y", "transform"].contains(&child.name.as_str()) {
+                let (inner, hittable) = self.instance(child)?;
+                return Ok((inner.then(&transform), hittable));
+            }
+        }
+        Ok((transform, self.contents(node)?))
+    }
+
+    /// The hittable children of a node, wrapped in a `HittableList` when there is more than one.
+    fn contents(&mut self, node: &Node) -> Result<Arc<dyn Hittable>, Error> {
+        let mut list = self.children(node)?;
+        match list.objects.len() {
+            0 => Err(node.error(&format!("'{}' contains no objects", node.name))),
+            1 => Ok(list.objects.pop().unwrap()),
+            _ => Ok(Arc::new(list)),
+        }
+    }
+
+    fn children(&mut self, node: &Node) -> Result<HittableList, Error> {
+        let mut list = HittableList::new();
+        for child in node
+            .children
+            .iter()
+            .filter(|c| HITTABLES.contains(&c.name.as_str()))
+        {
+            list.add(self.hittable(child)?);
+        }
+        Ok(list)
+    }
+
+    /// Like `Node::allow_only`, but also accepts any nested hittable.
+    fn allow_only_with_children(&self, node: &Node, allowed: &[&str]) -> Result<(), Error> {
+        allow_only_with(node, allowed, &HITTABLES)
+    }
+
+    fn hittable(&mut self, node: &Node) -> Result<Arc<dyn Hittable>, Error> {
+        node.arg_count(0)?;
+        let hittable: Arc<dyn Hittable> = match node.name.as_str() {
+            "sphere" => {
+                node.allow_only(&["center", "center_end", "time", "radius", "material"])?;
+                let center = node.require("center")?.vec3()?;
+                let radius = node.require("radius")?.number()?;
+                let material = self.material(node.require("material")?)?;
+                match node.child("center_end") {
+                    Some(end) => {
+                        let (time_start, time_end) =
+                            optional(node, "time", Node::pair)?.unwrap_or((0.0, 1.0));
+                        Arc::new(Sphere::new_moving(
+                            radius,
+                            center,
+                            end.vec3()?,
+                            time_start,
+                            time_end,
+                            material,
+                        ))
+                    }
+                    None => Arc::new(Sphere::new(center, radius, material)),
+                }
+            }
+            "triangle" => {
+                node.allow_only(&["vertices", "normals", "uvs", "material"])?;
+                let vertices = node.require("vertices")?;
+                vertices.arg_count(9)?;
+                let mut triangle = Triangle::new(
+                    vertices.vec3_at(0)?,
+                    vertices.vec3_at(3)?,
+                    vertices.vec3_at(6)?,
+                    self.material(node.require("material")?)?,
+                );
+                if let Some(normals) = node.child("normals") {
+                    normals.arg_count(9)?;
+                    triangle = triangle.with_normals([
+                        normals.vec3_at(0)?,
+                        normals.vec3_at(3)?,
+                        normals.vec3_at(6)?,
+                    ]);
+                }
+                if let Some(uvs) = node.child("uvs") {
+                    uvs.arg_count(6)?;
+                    let uv = |i: usize| -> Result<(f64, f64), Error> {
+                        Ok((uvs.number_at(2 * i)?, uvs.number_at(2 * i + 1)?))
+                    };
+                    triangle = triangle.with_uvs([uv(0)?, uv(1)?, uv(2)?]);
+                }
+                Arc::new(triangle)
+            }
+            "mesh" => {
+                node.allow_only(&["file", "material"])?;
+                let file = node.require("file")?;
+                file.arg_count(1)?;
+                // faces without an MTL material
+                let material = match node.child("material") {
+                    Some(m) => self.material(m)?,
+                    None => Arc::new(Lambertian::new_color(Vec3::new(0.73, 0.73, 0.73))),
+                };
+                let meshes = obj::load_file(self.directory.join(file.string_at(0)?), material)?;
+                Arc::new(Bvh::from_list(&meshes, 0.0, 1.0))
+            }
+            "quad" => {
+                node.allow_only(&["origin", "u", "v", "material"])?;
+                let u = node.require("u")?.vec3()?;
+                let v = node.require("v")?.vec3()?;
+                if Vec3::cross(u, v).near_zero() {
+                    return Err(node.error("The edges of a quad can't be parallel"));
+                }
+                Arc::new(Quad::new(
+                    node.require("origin")?.vec3()?,
+                    u,
+                    v,
+                    self.material(node.require("material")?)?,
+                ))
+            }
+            "disk" => {
+                node.allow_only(&["center", "normal", "radius", "material"])?;
+                let normal = node.require("normal")?;
+                if normal.vec3()?.near_zero() {
+                    return Err(normal.error("The normal of a disk can't be zero"));
+                }
+                let radius = node.require("radius")?;
+                if radius.number()? <= 0.0 {
+                    return Err(radius.error("The radius of a disk must be positive"));
+                }
+                Arc::new(Disk::new(
+                    node.require("center")?.vec3()?,
+                    normal.vec3()?,
+                    radius.number()?,
+                    self.material(node.require("material")?)?,
+                ))
+            }
+            "plane" => {
+                node.allow_only(&["point", "normal", "motion", "time", "material"])?;
+                let normal = node.require("normal")?;
+                if normal.vec3()?.near_zero() {
+                    return Err(normal.error("The normal of a plane can't be zero"));
+                }
+                Arc::new(Plane::new_moving(
+                    node.require("point")?.vec3()?,
+                    normal.vec3()?,
+                    motion(node)?,
+                    self.material(node.require("material")?)?,
+                ))
+            }
+            "cylinder" => {
+                node.allow_only(&[
+                    "base", "top", "radius", "open", "motion", "time", "material",
+                ])?;
+                let (base, top) = axis(node, "base", "top")?;
+                let cylinder = Cylinder::new_moving(
+                    base,
+                    top,
+                    positive(node, "radius")?,
+                    motion(node)?,
+                    self.material(node.require("material")?)?,
+                );
+                match flag(node, "open")? {
+                    true => Arc::new(cylinder.open()),
+                    false => Arc::new(cylinder),
+                }
+            }
+            "cone" => {
+                node.allow_only(&[
+                    "base", "apex", "radius", "open", "motion", "time", "material",
+                ])?;
+                let (base, apex) = axis(node, "base", "apex")?;
+                let cone = Cone::new_moving(
+                    base,
+                    apex,
+                    positive(node, "radius")?,
+                    motion(node)?,
+                    self.material(node.require("material")?)?,
+                );
+                match flag(node, "open")? {
+                    true => Arc::new(cone.open()),
+                    false => Arc::new(cone),
+                }
+            }
+            "torus" => {
+                node.allow_only(&[
+                    "center",
+                    "axis",
+                    "major_radius",
+                    "minor_radius",
+                    "motion",
+                    "time",
+                    "material",
+                ])?;
+                let axis = optional(node, "axis", Node::vec3)?.unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+                if axis.near_zero() {
+                    return Err(node.error("The axis of a torus can't be zero"));
+                }
+                Arc::new(Torus::new_moving(
+                    node.require("center")?.vec3()?,
+                    axis,
+                    positive(node, "major_radius")?,
+                    positive(node, "minor_radius")?,
+                    motion(node)?,
+                    self.material(node.require("material")?)?,
+                ))
+            }
+            "capsule" => {
+                node.allow_only(&["start", "end", "radius", "motion", "time", "material"])?;
+                Arc::new(Capsule::new_moving(
+                    node.require("start")?.vec3()?,
+                    node.require("end")?.vec3()?,
+                    positive(node, "radius")?,
+                    motion(node)?,
+                    self.material(node.require("material")?)?,
+                ))
+            }
+            "xy_rect" => {
+                node.allow_only(&["x", "y", "k", "material"])?;
+                let (x0, x1) = node.require("x")?.pair()?;
+                let (y0, y1) = node.require("y")?.pair()?;
+                let k = node.require("k")?.number()?;
+                let material = self.material(node.require("material")?)?;
+                Arc::new(XYRectangle::new(x0, x1, y0, y1, k, material))
+            }
+            "xz_rect" => {
+                node.allow_only(&["x", "z", "k", "material"])?;
+                let (x0, x1) = node.require("x")?.pair()?;
+                let (z0, z1) = node.require("z")?.pair()?;
+                let k = node.require("k")?.number()?;
+                let material = self.material(node.require("material")?)?;
+                Arc::new(XZRectangle::new(x0, x1, z0, z1, k, material))
+            }
+            "yz_rect" => {
+                node.allow_only(&["y", "z", "k", "material"])?;
+                let (y0, y1) = node.require("y")?.pair()?;
+                let (z0, z1) = node.require("z")?.pair()?;
+                let k = node.require("k")?.number()?;
+                let material = self.material(node.require("material")?)?;
+                Arc::new(YZRectangle::new(y0, y1, z0, z1, k, material))
+            }
+            "box" => {
+                node.allow_only(&["min", "max", "material"])?;
+                Arc::new(MyBox::new(
+                    node.require("min")?.vec3()?,
+                    node.require("max")?.vec3()?,
+                    self.material(node.require("material")?)?,
+                ))
+            }
+            "constant_medium" => {
+                self.allow_only_with_children(
+                    node,
+                    &["density", "color", "texture", "density_texture"],
+                )?;
+                let boundary = self.contents(node)?;
+                let density = positive(node, "density")?;
+                let albedo = self.color_or_texture(node, "color")?;
+                match node.child("density_texture") {
+                    Some(t) => Arc::new(ConstantMedium::new_varying(
+                        boundary,
+                        density,
+                        self.texture(t)?,
+                        albedo,
+                    )),
+                    None => Arc::new(ConstantMedium::new(boundary, density, albedo)),
+                }
+            }
+            "translate" | "rotate_y" | "transform" => {
+                let (transform, hittable) = self.instance(node)?;
+                Arc::new(Instance::new(hittable, transform))
+            }
+            "animate" => {
+                let nested: Vec<&str> = HITTABLES.iter().copied().chain(["keyframe"]).collect();
+                allow_only_with(node, &[], &nested)?;
+                let keyframes = node
+                    .children
+                    .iter()
+                    .filter(|c| c.name == "keyframe")
+                    .map(keyframe)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let animation = Animation::new(keyframes).ok_or_else(|| {
+                    node.error(
+                        "'animate' needs keyframes at different times, with scale factors \
+                         that are never zero and keep their sign",
+                    )
+                })?;
+                Arc::new(AnimatedInstance::new(self.contents(node)?, animation))
+            }
+            "bvh" => {
+                self.allow_only_with_children(node, &["time"])?;
+                let (time_start, time_end) =
+                    optional(node, "time", Node::pair)?.unwrap_or(self.shutter);
+                let list = self.children(node)?;
+                if list.objects.is_empty() {
+                    return Err(node.error("'bvh' contains no objects"));
+                }
+                Arc::new(Bvh::from_list(&list, time_start, time_end))
+            }
+            "list" => {
+                self.allow_only_with_children(node, &[])?;
+                Arc::new(self.children(node)?)
+            }
+            "union" | "intersection" | "difference" => {
+                self.allow_only_with_children(node, &[])?;
+                let mut children = self.children(node)?.objects.into_iter();
+                let (Some(first), Some(second)) = (children.next(), children.next()) else {
+                    return Err(node.error(&format!("'{}' needs at least two objects", node.name)));
+                };
+                // further objects are combined with the result so far, left to right
+                let combine = |left: Arc<dyn Hittable>, right| -> Arc<dyn Hittable> {
+                    match node.name.as_str() {
+                        "union" => Arc::new(CsgUnion::new(left, right)),
+                        "intersection" => Arc::new(CsgIntersection::new(left, right)),
+                        _ => Arc::new(CsgDifference::new(left, right)),
+                    }
+                };
+                children.fold(combine(first, second), combine)
+            }
+            "sdf" => {
+                allow_only_with(node, &["material"], &SDF_SHAPES)?;
+                let shape = sdf_children(node, 1, 1)?.pop().unwrap();
+                Arc::new(SdfHittable::new(
+                    shape,
+                    self.material(node.require("material")?)?,
+                ))
+            }
+            name => return Err(node.error(&format!("Unknown object '{}'", name))),
+        };
+        Ok(hittable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Kind;
+    use crate::ray::Ray;
+    use std::path::PathBuf;
+
+    const CORNELL_BOX: &str = include_str!("../../scenes/cornell_box.scene");
+
+    /// The bundled scenes, wherever the tests run from
+    fn scenes() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes")
+    }
+
+    /// Parses `source` as if it were a scene file next to the bundled ones.
+    fn parse_source(source: &str) -> Result<Scene, Error> {
+        parse(source, &scenes(), &mut Sampler::new(0))
+    }
+
+    #[test]
+    fn load_cornell_box() {
+        let scene = parse_source(CORNELL_BOX).unwrap();
+        assert_eq!(scene.world.objects.len(), 8);
+        assert_eq!(scene.lights.objects.len(), 1);
+        assert_eq!(scene.settings.image_width, 600);
+        assert_eq!(scene.settings.image_height(), 600);
+        assert_eq!(scene.settings.samples_per_pixel, 200);
+
+        let ray = Ray::new_at_time(
+            Vec3::new(278.0, 278.0, -800.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+        assert!(scene.world.hit(&ray, 0.001, f64::INFINITY).is_some());
+    }
+
+    #[test]
+    fn every_bundled_scene_loads() {
+        for entry in std::fs::read_dir(scenes()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "scene") {
+                continue;
+            }
+            if let Err(e) = load_file(&path, &mut Sampler::new(0)) {
+                panic!("{}: {}", path.display(), e);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_material_reports_position() {
+        let error = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }\nsphere {\n    center 0 0 -1\n    radius 0.5\n    material missing\n}\n",
+        )
+        .err()
+        .unwrap();
+        match error.kind {
+            Kind::ParseError { line, column } => assert_eq!((line, column), (5, 5)),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn media_need_a_positive_density() {
+        for density in ["0", "-0.5"] {
+            let source = format!(
+                "camera {{ look_from 0 0 0; look_at 0 0 -1 }}
+                 texture fog solid {{ color 0 0 0 }}
+                 material white lambertian {{ color 1 1 1 }}
+                 constant_medium {{
+                     density {}
+                     density_texture fog
+                     color 1 1 1
+                     sphere {{ center 0 0 -5; radius 1; material white }}
+                 }}",
+                density
+            );
+            let error = parse_source(&source).err().unwrap();
+            assert!(
+                matches!(
+                    error.kind,
+                    Kind::ParseError {
+                        line: 5,
+                        column: 22
+                    }
+                ),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_property_is_rejected() {
+        let error = parse_source("camera { look_from 0 0 0; look_at 0 0 -1; fov 20 }")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.kind,
+            Kind::ParseError {
+                line: 1,
+                column: 43
+            }
+        ));
+    }
+
+    #[test]
+    fn triangle_lights_are_sampled() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+            material lamp diffuse_light { color 4 4 4 }
+            triangle { vertices -1 1 -2  1 1 -2  0 1 -3; uvs 0 0 1 0 0 1; material lamp }",
+        )
+        .unwrap();
+        assert_eq!(scene.lights.objects.len(), 1);
+        assert!(parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+            material lamp diffuse_light { color 4 4 4 }
+            triangle { vertices -1 1 -2  1 1 -2; material lamp }",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn nested_transforms_apply_inside_out() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+            material white lambertian { color 1 1 1 }
+            translate {
+                offset 0 0 -10
+                transform {
+                    scale 2
+                    rotate z 90
+                    sphere { center 1 0 0; radius 0.5; material white }
+                }
+            }",
+        )
+        .unwrap();
+        // the sphere ends up centered at (0, 2, -10) with radius 1
+        let ray = Ray::new_at_time(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 9.0).abs() < 1e-9);
+        assert!(parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+            material white lambertian { color 1 1 1 }
+            transform { scale 0; sphere { center 1 0 0; radius 0.5; material white } }",
+        )
+        .is_err());
+        let error = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+            material white lambertian { color 1 1 1 }
+            transform {
+                rotate_x 90
+                sphere { center 1 0 0; radius 0.5; material white }
+            }",
+        )
+        .err()
+        .unwrap();
+        assert!(
+            matches!(
+                error.kind,
+                Kind::ParseError {
+                    line: 4,
+                    column: 17
+                }
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn lights_are_listed_apart_from_geometry() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             light point { position 0 5 0; power 100 }
+             light spot { position 0 5 0; look_at 0 0 0; cone_angle 20; intensity 3; color 1 0.5 0.5 }
+             light directional { direction 1 -1 0; irradiance 2 }
+             light area { xz_rect { x -1 1; z -1 1; k 3 }; power 100; sides front }",
+        )
+        .unwrap();
+        assert_eq!(scene.punctual_lights.len(), 3);
+        assert_eq!(scene.world.objects.len(), 1);
+        assert_eq!(scene.lights.objects.len(), 1);
+
+        // the 100 W leave the top of the 4 m² rectangle only
+        let radiance = |origin: Vec3, direction: Vec3| {
+            let hit = scene
+                .world
+                .hit(
+                    &Ray::new_at_time(origin, direction, 0.0),
+                    0.001,
+                    f64::INFINITY,
+                )
+                .unwrap();
+            hit.material.emitted(&hit)
+        };
+        let above = radiance(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!((above.x - 100.0 / (4.0 * std::f64::consts::PI)).abs() < 1e-9);
+        let below = radiance(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(below, Vec3::new(0.0, 0.0, 0.0));
+
+        for light in [
+            "light point { position 0 5 0; power 100; intensity 8 }",
+            "light spot { position 0 5 0; look_at 0 5 0; power 100 }",
+            "light area { sphere { center 0 0 0; radius 1 }; xz_rect { x 0 1; z 0 1; k 0 }; power 1 }",
+            "light torch { power 1 }",
+        ] {
+            let source = format!("camera {{ look_from 0 0 0; look_at 0 0 -1 }}\n{}", light);
+            assert!(parse_source(&source).is_err(), "{}", light);
+        }
+    }
+
+    #[test]
+    fn emissive_quads_and_disks_are_lights() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             material lamp diffuse_light { color 4 4 4 }
+             material white lambertian { color 1 1 1 }
+             quad { origin -1 2 -1; u 2 0 0; v 0 0 2; material lamp }
+             disk { center 0 -1 0; normal 0 1 0; radius 3; material white }",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(scene.lights.objects.len(), 1);
+        let ray = Ray::new_at_time(Vec3::new(2.5, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert!((scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap().t - 1.0).abs() < 1e-9);
+
+        for object in [
+            "quad { origin 0 0 0; u 1 0 0; v 2 0 0; material white }",
+            "disk { center 0 0 0; normal 0 0 0; radius 1; material white }",
+            "disk { center 0 0 0; normal 0 1 0; radius 0; material white }",
+        ] {
+            let source = format!(
+                "camera {{ look_from 0 0 0; look_at 0 0 -1 }}
+                 material white lambertian {{ color 1 1 1 }}\n{}",
+                object
+            );
+            assert!(parse_source(&source).is_err(), "{}", object);
+        }
+    }
+
+    #[test]
+    fn analytic_shapes() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             material white lambertian { color 1 1 1 }
+             plane { point 0 -1 0; normal 0 1 0; material white }
+             cylinder { base 0 0 0; top 0 2 0; radius 0.5; open; motion 4 0 0; material white }
+             cone { base 2 0 0; apex 2 1 0; radius 0.5; material white }
+             torus { center 0 0 5; major_radius 1; minor_radius 0.25; material white }
+             capsule { start 0 0 -5; end 0 0 -5; radius 1; material white }",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 5);
+        assert!(scene.world.objects[0].bounding_box(0.0, 1.0).is_none());
+        // the cylinder moves from x = 0 to x = 4, above the cone
+        let across = |time: f64| {
+            Ray::new_at_time(Vec3::new(-10.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0), time)
+        };
+        let t = |time: f64| {
+            scene
+                .world
+                .hit(&across(time), 0.001, f64::INFINITY)
+                .unwrap()
+                .t
+        };
+        assert!((t(0.0) - 9.5).abs() < 1e-9);
+        assert!((t(0.5) - 11.5).abs() < 1e-9);
+
+        for object in [
+            "cylinder { base 0 0 0; top 0 0 0; radius 1; material white }",
+            "cone { base 0 0 0; apex 0 1 0; radius -1; material white }",
+            "torus { center 0 0 0; axis 0 0 0; major_radius 1; minor_radius 0.1; material white }",
+            "plane { point 0 0 0; normal 0 1 0; motion 1 0 0; time 1 1; material white }",
+            "capsule { start 0 0 0; end 1 0 0; radius 1; open; material white }",
+        ] {
+            let source = format!(
+                "camera {{ look_from 0 0 0; look_at 0 0 -1 }}
+                 material white lambertian {{ color 1 1 1 }}\n{}",
+                object
+            );
+            assert!(parse_source(&source).is_err(), "{}", object);
+        }
+    }
+
+    #[test]
+    fn solids_are_combined_left_to_right() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             material white lambertian { color 1 1 1 }
+             difference {
+                 sphere { center 0 0 0; radius 2; material white }
+                 box { min -3 -0.5 -0.5; max 3 0.5 0.5; material white }
+                 translate { offset 0 0 -2; sphere { center 0 0 0; radius 1; material white } }
+             }",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 1);
+        let hit = |origin: Vec3, direction: Vec3| {
+            scene
+                .world
+                .hit(
+                    &Ray::new_at_time(origin, direction, 0.0),
+                    0.001,
+                    f64::INFINITY,
+                )
+                .map(|h| h.t)
+        };
+        // through the tunnel the box bored, and into the dent the last sphere left
+        assert_eq!(
+            hit(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            None
+        );
+        let dent = hit(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0)).unwrap();
+        assert!((dent - 4.0).abs() < 1e-9);
+
+        let source = "camera { look_from 0 0 0; look_at 0 0 -1 }
+             material white lambertian { color 1 1 1 }
+             union { sphere { center 0 0 0; radius 2; material white } }";
+        assert!(parse_source(source).is_err());
+    }
+
+    #[test]
+    fn animated_objects_follow_their_keyframes() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             material white lambertian { color 1 1 1 }
+             animate {
+                 keyframe 0 { offset 0 0 -5 }
+                 keyframe 1 { offset 2 0 -5; rotate y 90; scale 2 1 1 }
+                 box { min -0.5 -0.5 -0.5; max 0.5 0.5 0.5; material white }
+             }",
+        )
+        .unwrap();
+        let object = &scene.world.objects[0];
+        let forward = |x: f64, time: f64| {
+            let ray = Ray::new_at_time(Vec3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+            object.hit(&ray, 0.001, f64::INFINITY).map(|h| h.t)
+        };
+        assert!(forward(0.0, 0.0).is_some_and(|t| (t - 4.5).abs() < 1e-9));
+        assert_eq!(forward(2.0, 0.0), None);
+        // stretched along x, then turned so the long side points along z
+        assert!(forward(2.0, 1.0).is_some_and(|t| (t - 4.0).abs() < 1e-9));
+        let swept = object.bounding_box(0.0, 1.0).unwrap();
+        assert!(swept.min.x <= -0.5 && swept.max.x >= 2.5 && swept.min.z <= -6.0);
+
+        for object in [
+            "animate { box { min 0 0 0; max 1 1 1; material white } }",
+            "animate { keyframe 0 {}; keyframe 0 { offset 1 0 0 }; sphere { center 0 0 0; radius 1; material white } }",
+            "animate { keyframe 0 {}; keyframe 1 { scale -1 1 1 }; sphere { center 0 0 0; radius 1; material white } }",
+            "animate { keyframe 0 { rotate w 5 }; sphere { center 0 0 0; radius 1; material white } }",
+            "animate { keyframe 0 { matrix 1 0 0 0 0 1 0 0 0 0 1 0 }; sphere { center 0 0 0; radius 1; material white } }",
+            "animate { keyframe 0 {} }",
+        ] {
+            let source = format!(
+                "camera {{ look_from 0 0 0; look_at 0 0 -1 }}
+                 material white lambertian {{ color 1 1 1 }}\n{}",
+                object
+            );
+            assert!(parse_source(&source).is_err(), "{}", object);
+        }
+    }
+
+    #[test]
+    fn nested_hierarchies_bound_motion_while_the_shutter_is_open() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1; time 2 3 }
+             material white lambertian { color 1 1 1 }
+             bvh {
+                 sphere { center -10 0 -5; radius 1; material white }
+                 animate {
+                     keyframe 2 { offset 0 0 -5 }
+                     keyframe 3 { offset 10 0 -5 }
+                     sphere { center 0 0 0; radius 1; material white }
+                 }
+             }",
+        )
+        .unwrap();
+        let ray = Ray::new_at_time(Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 3.0);
+        let hit = scene.world.hit(&ray, 0.001, f64::INFINITY);
+        assert!(hit.is_some_and(|h| (h.t - 4.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn signed_distance_shapes() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             material white lambertian { color 1 1 1 }
+             sdf {
+                 material white
+                 smooth_union {
+                     smoothness 0.5
+                     sphere { center -1.1 0 0; radius 1 }
+                     sphere { center 1.1 0 0; radius 1 }
+                     repeat {
+                         spacing 3 0 0; count 2 1 1
+                         twist { rate 45; rounded_box { center 0 0 -5; size 1 2 1; radius 0.1 } }
+                     }
+                 }
+             }",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 1);
+        // down into the blend between the spheres
+        let down = Ray::new_at_time(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert!(scene
+            .world
+            .hit(&down, 0.001, f64::INFINITY)
+            .is_some_and(|h| h.t > 4.5 && h.t < 5.0 && h.normal.y > 0.999));
+        let bounds = scene.world.objects[0].bounding_box(0.0, 1.0).unwrap();
+        assert!(bounds.min.z < -5.5 && bounds.max.x > 3.5);
+
+        for object in [
+            "sdf { material white }",
+            "sdf { material white; box { min 0 0 0; max 1 1 1 } }",
+            "sdf { sphere { center 0 0 0; radius 1 } }",
+            "sdf { material white; rounded_box { center 0 0 0; size 1 1 1; radius 0.6 } }",
+            "sdf { material white; smooth_union { sphere { center 0 0 0; radius 1 } } }",
+            "sdf { material white; repeat { spacing 0 1 0; count 2 1 1; sphere { center 0 0 0; radius 1 } } }",
+            "sdf { material white; repeat { spacing 1 1 1; count 2 1.5 1; sphere { center 0 0 0; radius 1 } } }",
+            "sdf { material white; mandelbulb { center 0 0 0; power 1 } }",
+        ] {
+            let source = format!(
+                "camera {{ look_from 0 0 0; look_at 0 0 -1 }}
+                 material white lambertian {{ color 1 1 1 }}\n{}",
+                object
+            );
+            assert!(parse_source(&source).is_err(), "{}", object);
+        }
+    }
+
+    #[test]
+    fn files_are_found_next_to_the_scene() {
+        let source = "camera { look_from 0 0 0; look_at 0 0 -1 }
+             environment { file \"sky.hdr\" }
+             texture earth image { file \"world.png\" }";
+        assert!(parse_source(source).is_ok());
+
+        let error = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             texture earth image {
+                 file \"missing.png\"
+             }",
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.kind,
+            Kind::ParseError {
+                line: 3,
+                column: 18
+            }
+        ));
+        assert!(error.to_string().contains("missing.png"), "{}", error);
+    }
+
+    #[test]
+    fn one_background_per_scene() {
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             environment { file \"sky.hdr\"; rotation 90; intensity 2 }",
+        )
+        .unwrap();
+        assert!(scene.background.is_sampled());
+        let scene = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             sky { sun 1 2 0; turbidity 4; ground_albedo 0.2 0.3 0.2 }",
+        )
+        .unwrap();
+        let sun = Vec3::unit_vector(Vec3::new(1.0, 2.0, 0.0));
+        assert!(scene.background.radiance(&sun).y > 1000.0);
+        let error = parse_source(
+            "camera { look_from 0 0 0; look_at 0 0 -1 }
+             background 1 1 1
+             environment { file \"sky.hdr\" }",
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error.kind,
+            Kind::ParseError {
+                line: 3,
+                column: 14
+            }
+        ));
+    }
+
+    #[test]
+    fn material_parameters_take_numbers_or_textures() {
+        let source = |material: &str| {
+            format!(
+                "camera {{ look_from 0 0 0; look_at 0 0 -1 }}
+                texture rough noise {{ scale 2 }}
+                material m {}
+                sphere {{ center 0 0 -1; radius 0.5; material m }}",
+                material
+            )
+        };
+        for material in [
+            "conductor { eta 0.2 0.9 1.1; k 3.9 2.4 2.1; roughness_texture rough }",
+            "principled { color 1 1 1; metallic 1; sheen_texture rough; sellmeier 1 0 0 0 0 0 }",
+        ] {
+            parse_source(&source(material)).unwrap();
+        }
+        for material in [
+            "conductor { metal brass }",
+            "conductor { eta 0.2 0.9 1.1 }",
+            "rough_dielectric { roughness 0.2 }",
+            "principled { color 1 1 1; roughness 0.2; roughness_texture rough }",
+        ] {
+            assert!(parse_source(&source(material)).is_err(), "{}", material);
+        }
+    }
+}
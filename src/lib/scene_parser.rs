@@ -0,0 +1,440 @@
+use crate::error::Error;
+use crate::vec::Vec3;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A scene file is a list of nodes. Each node is a name followed by arguments on the same
+/// line and an optional block of child nodes in braces:
+///
+/// ``` text
+/// # comment
+/// material white lambertian {
+///     color 0.73 0.73 0.73
+/// }
+/// box { min 0 0 0; max 165 330 165; material white }
+/// ```
+///
+/// Nodes are separated by newlines or `;`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub name: String,
+    pub args: Vec<Value>,
+    pub children: Vec<Node>,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Ident(String),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    LeftBrace,
+    RightBrace,
+    Separator,
+    End,
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Self {
+        Lexer {
+            chars: source.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Returns the next token and the line and column it starts at.
+    fn next_token(&mut self) -> Result<(Token, usize, usize), Error> {
+        loop {
+            match self.chars.peek() {
+                Some('#') => {
+                    while !matches!(self.chars.peek(), Some('\n') | None) {
+                        self.bump();
+                    }
+                }
+                Some(c) if *c != '\n' && c.is_whitespace() => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+
+        let (line, column) = (self.line, self.column);
+        let c = match self.chars.peek() {
+            Some(c) => *c,
+            None => return Ok((Token::End, line, column)),
+        };
+        let token = match c {
+            '{' => {
+                self.bump();
+                Token::LeftBrace
+            }
+            '}' => {
+                self.bump();
+                Token::RightBrace
+            }
+            '\n' | ';' => {
+                self.bump();
+                Token::Separator
+            }
+            '"' => {
+                self.bump();
+                let mut s = String::new();
+                loop {
+                    match self.bump() {
+                        Some('"') => break,
+                        Some('\n') | None => {
+                            return Err(Error::parse(line, column, "Unterminated string"))
+                        }
+                        Some(c) => s.push(c),
+                    }
+                }
+                Token::Str(s)
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let mut s = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    // a sign is only part of the number at the start or after an exponent
+                    let sign = (c == '-' || c == '+')
+                        && (s.is_empty() || matches!(s.chars().last(), Some('e' | 'E')));
+                    if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || sign {
+                        s.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                let number = s
+                    .parse::<f64>()
+                    .map_err(|_| Error::parse(line, column, &format!("Invalid number '{}'", s)))?;
+                Token::Number(number)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut s = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        s.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                Token::Ident(s)
+            }
+            c => {
+                return Err(Error::parse(
+                    line,
+                    column,
+                    &format!("Unexpected character '{}'", c),
+                ))
+            }
+        };
+        Ok((token, line, column))
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<(Token, usize, usize)>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Result<&(Token, usize, usize), Error> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lexer.next_token()?);
+        }
+        Ok(self.peeked.as_ref().unwrap())
+    }
+
+    fn next(&mut self) -> Result<(Token, usize, usize), Error> {
+        self.peek()?;
+        Ok(self.peeked.take().unwrap())
+    }
+
+    fn parse_nodes(&mut self, in_block: bool) -> Result<Vec<Node>, Error> {
+        let mut nodes = Vec::new();
+        loop {
+            let (token, line, column) = self.next()?;
+            match token {
+                Token::Separator => continue,
+                Token::End if in_block => {
+                    return Err(Error::parse(
+                        line,
+                        column,
+                        "Expected '}' before end of file",
+                    ))
+                }
+                Token::End => return Ok(nodes),
+                Token::RightBrace if in_block => return Ok(nodes),
+                Token::Ident(name) => nodes.push(self.parse_node(name, line, column)?),
+                token => {
+                    return Err(Error::parse(
+                        line,
+                        column,
+                        &format!("Expected a name, found {}", describe(&token)),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn parse_node(&mut self, name: String, line: usize, column: usize) -> Result<Node, Error> {
+        let mut args = Vec::new();
+        let mut children = Vec::new();
+        loop {
+            let (token, _, _) = self.peek()?.clone();
+            match token {
+                Token::Number(n) => args.push(Value::Number(n)),
+                Token::Ident(s) => args.push(Value::Ident(s)),
+                Token::Str(s) => args.push(Value::Str(s)),
+                Token::LeftBrace => {
+                    self.next()?;
+                    children = self.parse_nodes(true)?;
+                    match self.peek()? {
+                        (Token::Separator | Token::RightBrace | Token::End, _, _) => break,
+                        (token, token_line, token_column) => {
+                            return Err(Error::parse(
+                                *token_line,
+                                *token_column,
+                                &format!(
+                                    "Expected a new line after '}}', found {}",
+                                    describe(token)
+                                ),
+                            ))
+                        }
+                    }
+                }
+                Token::Separator | Token::RightBrace | Token::End => break,
+            }
+            self.next()?;
+        }
+        Ok(Node {
+            name,
+            args,
+            children,
+            line,
+            column,
+        })
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(s) => format!("'{}'", s),
+        Token::Number(n) => format!("number {}", n),
+        Token::Str(s) => format!("string \"{}\"", s),
+        Token::LeftBrace => "'{'".to_owned(),
+        Token::RightBrace => "'}'".to_owned(),
+        Token::Separator => "new line".to_owned(),
+        Token::End => "end of file".to_owned(),
+    }
+}
+
+pub fn parse(source: &str) -> Result<Vec<Node>, Error> {
+    let mut parser = Parser {
+        lexer: Lexer::new(source),
+        peeked: None,
+    };
+    parser.parse_nodes(false)
+}
+
+impl Node {
+    pub fn error(&self, explanation: &str) -> Error {
+        Error::parse(self.line, self.column, explanation)
+    }
+
+    pub fn arg_count(&self, count: usize) -> Result<(), Error> {
+        if self.args.len() != count {
+            return Err(self.error(&format!(
+                "'{}' takes {} argument(s), found {}",
+                self.name,
+                count,
+                self.args.len()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn number_at(&self, index: usize) -> Result<f64, Error> {
+        match self.args.get(index) {
+            Some(Value::Number(n)) => Ok(*n),
+            _ => Err(self.error(&format!(
+                "Expected a number as argument {} of '{}'",
+                index + 1,
+                self.name
+            ))),
+        }
+    }
+
+    pub fn ident_at(&self, index: usize) -> Result<&str, Error> {
+        match self.args.get(index) {
+            Some(Value::Ident(s)) => Ok(s),
+            _ => Err(self.error(&format!(
+                "Expected a name as argument {} of '{}'",
+                index + 1,
+                self.name
+            ))),
+        }
+    }
+
+    pub fn string_at(&self, index: usize) -> Result<&str, Error> {
+        match self.args.get(index) {
+            Some(Value::Str(s)) => Ok(s),
+            _ => Err(self.error(&format!(
+                "Expected a quoted string as argument {} of '{}'",
+                index + 1,
+                self.name
+            ))),
+        }
+    }
+
+    pub fn number(&self) -> Result<f64, Error> {
+        self.arg_count(1)?;
+        self.number_at(0)
+    }
+
+    pub fn integer(&self) -> Result<usize, Error> {
+        let n = self.number()?;
+        if n < 0.0 || n.fract() != 0.0 {
+            return Err(self.error(&format!("'{}' must be a whole number", self.name)));
+        }
+        Ok(n as usize)
+    }
+
+    pub fn vec3(&self) -> Result<Vec3, Error> {
+        self.arg_count(3)?;
+        self.vec3_at(0)
+    }
+
+    /// Three numbers starting at argument `index`, for lists of points.
+    pub fn vec3_at(&self, index: usize) -> Result<Vec3, Error> {
+        Ok(Vec3::new(
+            self.number_at(index)?,
+            self.number_at(index + 1)?,
+            self.number_at(index + 2)?,
+        ))
+    }
+
+    /// Two numbers, for ranges such as `x 0 555` or `time 0 1`.
+    pub fn pair(&self) -> Result<(f64, f64), Error> {
+        self.arg_count(2)?;
+        Ok((self.number_at(0)?, self.number_at(1)?))
+    }
+
+    pub fn ident(&self) -> Result<&str, Error> {
+        self.arg_count(1)?;
+        self.ident_at(0)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn require(&self, name: &str) -> Result<&Node, Error> {
+        self.child(name)
+            .ok_or_else(|| self.error(&format!("'{}' is missing '{}'", self.name, name)))
+    }
+
+    /// Fails on the first child that isn't in `allowed` or appears more than once.
+    pub fn allow_only(&self, allowed: &[&str]) -> Result<(), Error> {
+        for (i, child) in self.children.iter().enumerate() {
+            if !allowed.contains(&child.name.as_str()) {
+                return Err(child.error(&format!(
+                    "Unknown property '{}' in '{}'",
+                    child.name, self.name
+                )));
+            }
+            if self.children[..i].iter().any(|c| c.name == child.name) {
+                return Err(child.error(&format!("Duplicate property '{}'", child.name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Kind;
+
+    fn position(error: Error) -> (usize, usize) {
+        match error.kind {
+            Kind::ParseError { line, column } => (line, column),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn parse_nested_nodes() {
+        let nodes = parse(
+            "# comment\nmaterial white lambertian {\n    color 0.73 0.73 .73\n}\nbox { min 0 0 -1e2; max 1 2 3 }\n",
+        )
+        .unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].name, "material");
+        assert_eq!(
+            nodes[0].args,
+            vec![
+                Value::Ident("white".to_owned()),
+                Value::Ident("lambertian".to_owned())
+            ]
+        );
+        assert_eq!(
+            nodes[0].children[0].vec3().unwrap(),
+            Vec3::new(0.73, 0.73, 0.73)
+        );
+        assert_eq!((nodes[1].line, nodes[1].column), (5, 1));
+        assert_eq!(
+            nodes[1].require("min").unwrap().vec3().unwrap(),
+            Vec3::new(0.0, 0.0, -100.0)
+        );
+    }
+
+    #[test]
+    fn unclosed_block_reports_end_of_file() {
+        let error = parse("sphere {\n  radius 1\n").unwrap_err();
+        assert_eq!(position(error), (3, 1));
+    }
+
+    #[test]
+    fn bad_character_reports_position() {
+        let error = parse("camera {\n  vfov 2@\n}").unwrap_err();
+        assert_eq!(position(error), (2, 9));
+    }
+
+    #[test]
+    fn node_errors_point_at_node() {
+        let nodes = parse("render {\n    max_depth fifty\n}").unwrap();
+        let error = nodes[0]
+            .require("max_depth")
+            .unwrap()
+            .integer()
+            .unwrap_err();
+        assert_eq!(position(error), (2, 5));
+    }
+}
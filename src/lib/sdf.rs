@@ -0,0 +1,604 @@
+//! Shapes given by signed distance functions, rendered by sphere tracing.
+//!
+//! A signed distance function tells, for any point, how far the nearest surface is, negative
+//! inside the shape. No point within that distance can be on the surface, so a ray steps
+//! forward by it again and again until it lands on the surface. This needs no closed form
+//! for the intersection, so smooth blends, repetitions and fractals are as easy as a sphere.
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::vec::Vec3;
+use std::sync::Arc;
+
+/// How close to the surface a ray has to get to hit it
+const HIT_DISTANCE: f64 = 1e-5;
+/// Steps a ray takes before it gives up, such as when it grazes the surface
+const MAX_STEPS: usize = 512;
+/// Distance between the samples that estimate the normal
+const NORMAL_STEP: f64 = 1e-5;
+
+/// A shape given by the distance to its surface.
+pub trait Sdf: Send + Sync {
+    /// Distance from `p` to the nearest surface, negative inside. It may be less than the
+    /// true distance, which only costs more steps, but never more, or rays step through
+    /// the surface.
+    fn distance(&self, p: &Vec3) -> f64;
+
+    /// A box the whole surface is inside.
+    fn bounds(&self) -> AABB;
+}
+
+pub struct SdfSphere {
+    center: Vec3,
+    radius: f64,
+}
+
+impl SdfSphere {
+    pub fn new(center: Vec3, radius: f64) -> Self {
+        SdfSphere { center, radius }
+    }
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p: &Vec3) -> f64 {
+        (*p - self.center).length() - self.radius
+    }
+
+    fn bounds(&self) -> AABB {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        AABB::new(self.center - r, self.center + r)
+    }
+}
+
+/// An axis-aligned box of `size` around `center`, with its edges and corners rounded off by
+/// `radius`, which can't be more than half the shortest side. A radius of 0 leaves them
+/// sharp.
+pub struct RoundedBox {
+    center: Vec3,
+    half_size: Vec3,
+    radius: f64,
+}
+
+impl RoundedBox {
+    pub fn new(center: Vec3, size: Vec3, radius: f64) -> Self {
+        RoundedBox {
+            center,
+            half_size: size * 0.5,
+            radius,
+        }
+    }
+}
+
+impl Sdf for RoundedBox {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let p = *p - self.center;
+        let r = self.radius;
+        // how far outside the box shrunk by the radius, along each axis
+        let q = Vec3::new(
+            p.x.abs() - self.half_size.x + r,
+            p.y.abs() - self.half_size.y + r,
+            p.z.abs() - self.half_size.z + r,
+        );
+        let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+        let inside = q.x.max(q.y).max(q.z).min(0.0);
+        outside + inside - r
+    }
+
+    fn bounds(&self) -> AABB {
+        AABB::new(self.center - self.half_size, self.center + self.half_size)
+    }
+}
+
+/// A ring around `center` lying flat in the xz plane: the circle of `major_radius` swept by
+/// a tube of `minor_radius`.
+pub struct SdfTorus {
+    center: Vec3,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl SdfTorus {
+    pub fn new(center: Vec3, major_radius: f64, minor_radius: f64) -> Self {
+        SdfTorus {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for SdfTorus {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let p = *p - self.center;
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - self.major_radius;
+        (ring * ring + p.y * p.y).sqrt() - self.minor_radius
+    }
+
+    fn bounds(&self) -> AABB {
+        let reach = self.major_radius + self.minor_radius;
+        let extent = Vec3::new(reach, self.minor_radius, reach);
+        AABB::new(self.center - extent, self.center + extent)
+    }
+}
+
+/// The Mandelbulb fractal of `power`, scaled from its usual size of about 1 by `scale`,
+/// with its poles along y.
+///
+/// The distance is an estimate, which is only right outside the bulb, so rays must not
+/// start inside it: give it an opaque material.
+pub struct Mandelbulb {
+    center: Vec3,
+    scale: f64,
+    power: f64,
+    iterations: usize,
+}
+
+impl Mandelbulb {
+    /// Points escaping beyond this radius are outside the set
+    const BAILOUT: f64 = 2.0;
+
+    pub fn new(center: Vec3, scale: f64, power: f64, iterations: usize) -> Self {
+        Mandelbulb {
+            center,
+            scale,
+            power,
+            iterations,
+        }
+    }
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let c = (*p - self.center) / self.scale;
+        let n = self.power;
+        let mut z = c;
+        let mut r = z.length();
+        // derivative of the iteration, which turns the escape speed into a distance
+        let mut dr = 1.0;
+        for _ in 0..self.iterations {
+            if r > Mandelbulb::BAILOUT || r == 0.0 {
+                break;
+            }
+            // z ↦ zⁿ + c, raising the length to n and multiplying the angles by n
+            let theta = (z.y / r).acos() * n;
+            let phi = z.z.atan2(z.x) * n;
+            let r_n1 = r.powf(n - 1.0);
+            dr = n * r_n1 * dr + 1.0;
+            let direction = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            z = r_n1 * r * direction + c;
+            r = z.length();
+        }
+        let r = r.max(1e-12);
+        0.5 * r.ln() * r / dr * self.scale
+    }
+
+    fn bounds(&self) -> AABB {
+        let reach = Mandelbulb::BAILOUT * self.scale;
+        let extent = Vec3::new(reach, reach, reach);
+        AABB::new(self.center - extent, self.center + extent)
+    }
+}
+
+/// Both shapes, melted together where they come within `smoothness` of each other. A
+/// smoothness of 0 is a plain union.
+pub struct SmoothUnion {
+    left: Arc<dyn Sdf>,
+    right: Arc<dyn Sdf>,
+    smoothness: f64,
+}
+
+impl SmoothUnion {
+    pub fn new(left: Arc<dyn Sdf>, right: Arc<dyn Sdf>, smoothness: f64) -> Self {
+        SmoothUnion {
+            left,
+            right,
+            smoothness,
+        }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: &Vec3) -> f64 {
+        smooth_min(
+            self.left.distance(p),
+            self.right.distance(p),
+            self.smoothness,
+        )
+    }
+
+    fn bounds(&self) -> AABB {
+        // the blend reaches out at most a quarter of the smoothness past either shape
+        let grow = 0.25 * self.smoothness;
+        let grow = Vec3::new(grow, grow, grow);
+        let both = AABB::surrounding_box(&self.left.bounds(), &self.right.bounds());
+        AABB::new(both.min - grow, both.max + grow)
+    }
+}
+
+/// The smaller of `a` and `b`, with the corner where they meet rounded over `k`.
+pub fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+/// `count` copies of a shape along each axis, each `spacing` further along than the last.
+///
+/// Only the copy in whose cell a point lies is asked for the distance. When the copies
+/// leave gaps between them, those gaps keep the distance from reaching past the cell; when
+/// they overlap, the distance is only approximate.
+pub struct Repeat {
+    shape: Arc<dyn Sdf>,
+    spacing: [f64; 3],
+    count: [usize; 3],
+    /// Middle of the first copy
+    center: Vec3,
+    /// Gap between neighbouring copies along each axis, negative where they overlap
+    gap: [f64; 3],
+}
+
+impl Repeat {
+    /// The spacing along any axis with more than one copy must be positive.
+    pub fn new(shape: Arc<dyn Sdf>, spacing: Vec3, count: [usize; 3]) -> Self {
+        let bounds = shape.bounds();
+        let spacing = [spacing.x, spacing.y, spacing.z];
+        let size = bounds.max - bounds.min;
+        let size = [size.x, size.y, size.z];
+        Repeat {
+            shape,
+            spacing,
+            count,
+            center: (bounds.min + bounds.max) * 0.5,
+            gap: [0, 1, 2].map(|axis| spacing[axis] - size[axis]),
+        }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let offset = *p - self.center;
+        let mut local = [offset.x, offset.y, offset.z];
+        // lower limit on the distance to the copies in other cells
+        let mut others = f64::INFINITY;
+        for (x, ((&s, &n), &gap)) in local
+            .iter_mut()
+            .zip(self.spacing.iter().zip(&self.count).zip(&self.gap))
+        {
+            if n <= 1 {
+                continue;
+            }
+            let cell = (*x / s).round().clamp(0.0, (n - 1) as f64);
+            *x -= cell * s;
+            if gap > 0.0 {
+                let beyond_wall = 0.5 * s + 0.5 * gap;
+                if cell > 0.0 {
+                    others = others.min(beyond_wall + *x);
+                }
+                if cell < (n - 1) as f64 {
+                    others = others.min(beyond_wall - *x);
+                }
+            }
+        }
+        let local = Vec3::new(local[0], local[1], local[2]) + self.center;
+        self.shape.distance(&local).min(others)
+    }
+
+    fn bounds(&self) -> AABB {
+        let bounds = self.shape.bounds();
+        let [sx, sy, sz] = self.spacing;
+        let [nx, ny, nz] = self.count.map(|n| n.max(1) as f64 - 1.0);
+        AABB::new(
+            bounds.min,
+            bounds.max + Vec3::new(sx * nx, sy * ny, sz * nz),
+        )
+    }
+}
+
+/// A shape twisted about the vertical line through the middle of its bounds, turning by
+/// `rate` radians for every unit up from there.
+pub struct Twist {
+    shape: Arc<dyn Sdf>,
+    rate: f64,
+    center: Vec3,
+    /// Furthest the shape reaches from the line
+    reach: f64,
+    half_height: f64,
+}
+
+impl Twist {
+    pub fn new(shape: Arc<dyn Sdf>, rate: f64) -> Self {
+        let bounds = shape.bounds();
+        let half = (bounds.max - bounds.min) * 0.5;
+        Twist {
+            shape,
+            rate,
+            center: bounds.min + half,
+            reach: (half.x * half.x + half.z * half.z).sqrt(),
+            half_height: half.y,
+        }
+    }
+}
+
+impl Sdf for Twist {
+    fn distance(&self, p: &Vec3) -> f64 {
+        let q = *p - self.center;
+        let radial = (q.x * q.x + q.z * q.z).sqrt();
+        if radial > self.reach {
+            return radial - self.reach;
+        }
+        let (sin, cos) = (-self.rate * q.y).sin_cos();
+        let untwisted = Vec3::new(cos * q.x + sin * q.z, q.y, -sin * q.x + cos * q.z);
+        // Untwisting shears by up to rate × reach, stretching distances by at most the
+        // largest singular value of that shear.
+        let shear = self.rate.abs() * self.reach;
+        let stretch = 0.5 * (shear + (shear * shear + 4.0).sqrt());
+        self.shape.distance(&(untwisted + self.center)) / stretch
+    }
+
+    fn bounds(&self) -> AABB {
+        let extent = Vec3::new(self.reach, self.half_height, self.reach);
+        AABB::new(self.center - extent, self.center + extent)
+    }
+}
+
+/// A signed distance shape that rays are sphere traced against.
+///
+/// The normal is the gradient of the distance, and u and v map it like a point on a
+/// `Sphere`.
+pub struct SdfHittable {
+    shape: Arc<dyn Sdf>,
+    bounds: AABB,
+    material: Arc<dyn Material>,
+}
+
+impl SdfHittable {
+    pub fn new(shape: Arc<dyn Sdf>, material: Arc<dyn Material>) -> Self {
+        let bounds = shape.bounds();
+        // room for the hit distance, so surfaces on the bounds are still found
+        let pad = Vec3::new(HIT_DISTANCE, HIT_DISTANCE, HIT_DISTANCE) * 2.0;
+        SdfHittable {
+            shape,
+            bounds: AABB::new(bounds.min - pad, bounds.max + pad),
+            material,
+        }
+    }
+
+    /// 1 outside the shape and -1 inside.
+    fn side(&self, p: &Vec3) -> f64 {
+        if self.shape.distance(p) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Steps along `ray` from `t` until it reaches the surface from `side`, unless it first
+    /// passes `end`.
+    fn march(&self, ray: &Ray, mut t: f64, end: f64, side: f64) -> Option<f64> {
+        let length = ray.direction.length();
+        for _ in 0..MAX_STEPS {
+            let distance = side * self.shape.distance(&ray.at(t));
+            if distance < HIT_DISTANCE {
+                return Some(t);
+            }
+            t += distance / length;
+            if t > end {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Steps along `ray` from the surface at `t` until it is clear of it on `side`.
+    fn leave(&self, ray: &Ray, mut t: f64, end: f64, side: f64) -> Option<f64> {
+        let length = ray.direction.length();
+        for _ in 0..MAX_STEPS {
+            let distance = side * self.shape.distance(&ray.at(t));
+            if distance >= HIT_DISTANCE {
+                return Some(t);
+            }
+            t += distance.abs().max(HIT_DISTANCE) / length;
+            if t > end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn normal(&self, p: &Vec3) -> Vec3 {
+        // central differences on the corners of a tetrahedron, four samples instead of six
+        let h = NORMAL_STEP;
+        let gradient = [
+            (1.0, -1.0, -1.0),
+            (-1.0, -1.0, 1.0),
+            (-1.0, 1.0, -1.0),
+            (1.0, 1.0, 1.0),
+        ]
+        .iter()
+        .map(|&(x, y, z)| {
+            let k = Vec3::new(x, y, z);
+            k * self.shape.distance(&(*p + k * h))
+        })
+        .fold(Vec3::new(0.0, 0.0, 0.0), |sum, v| sum + v);
+        Vec3::unit_vector(gradient)
+    }
+
+    fn hit_record(&self, ray: &Ray, t: f64) -> HitRecord {
+        let mut hit_record = HitRecord::new(self.material.clone());
+        hit_record.t = t;
+        hit_record.point = ray.at(t);
+        let normal = self.normal(&hit_record.point);
+        // a flat spot in the distance, which only a degenerate shape has
+        let normal = if normal.x.is_nan() {
+            -Vec3::unit_vector(ray.direction)
+        } else {
+            normal
+        };
+        hit_record.set_face_normal(ray, &normal);
+        let (u, v) = Sphere::get_sphere_uv(&normal);
+        hit_record.u = u;
+        hit_record.v = v;
+        hit_record
+    }
+}
+
+impl Hittable for SdfHittable {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (start, end) = self.bounds.clip(ray, t_min, t_max)?;
+        // a ray that starts inside the shape looks for where it leaves
+        let side = self.side(&ray.at(start));
+        let t = self.march(ray, start, end, side)?;
+        Some(self.hit_record(ray, t))
+    }
+
+    fn hit_all(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord> {
+        let mut hits = Vec::new();
+        let Some((mut t, end)) = self.bounds.clip(ray, t_min, t_max) else {
+            return hits;
+        };
+        let mut side = self.side(&ray.at(t));
+        while let Some(hit) = self.march(ray, t, end, side) {
+            hits.push(self.hit_record(ray, hit));
+            side = -side;
+            match self.leave(ray, hit, end, side) {
+                Some(clear) => t = clear,
+                None => break,
+            }
+        }
+        hits
+    }
+
+    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn hittable(shape: impl Sdf + 'static) -> SdfHittable {
+        SdfHittable::new(
+            Arc::new(shape),
+            Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray::new_at_time(origin, direction, 0.0)
+    }
+
+    #[test]
+    fn traced_sphere_matches_the_analytic_one() {
+        let sphere = hittable(SdfSphere::new(Vec3::new(0.0, 1.0, 0.0), 0.5));
+        let across = ray(Vec3::new(-3.0, 1.2, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        let hit = sphere.hit(&across, 0.001, 100.0).unwrap();
+        let expected = (3.0 - (0.25f64 - 0.04).sqrt()) / 2.0;
+        assert!((hit.t - expected).abs() < 1e-4, "{}", hit.t);
+        let normal = Vec3::unit_vector(hit.point - Vec3::new(0.0, 1.0, 0.0));
+        assert!((hit.normal - normal).length() < 1e-3 && hit.front_face);
+
+        // from inside, and both sides for CSG
+        let inside = sphere.hit(&across, expected + 0.1, 100.0).unwrap();
+        assert!(!inside.front_face && inside.normal.x < 0.0);
+        let all = sphere.hit_all(&across, 0.001, 100.0);
+        assert_eq!(all.len(), 2);
+        assert!(all[0].front_face && !all[1].front_face);
+        assert!(sphere
+            .hit(
+                &ray(Vec3::new(-3.0, 1.6, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+                0.001,
+                100.0
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn smooth_union_fills_the_gap() {
+        let sphere = |x| Arc::new(SdfSphere::new(Vec3::new(x, 0.0, 0.0), 1.0)) as Arc<dyn Sdf>;
+        let between = ray(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let sharp = hittable(SmoothUnion::new(sphere(-1.1), sphere(1.1), 0.0));
+        assert!(sharp.hit(&between, 0.001, 100.0).is_none());
+        let smooth = hittable(SmoothUnion::new(sphere(-1.1), sphere(1.1), 1.0));
+        assert!(smooth
+            .hit(&between, 0.001, 100.0)
+            .is_some_and(|h| h.normal.y > 0.999));
+        assert!((smooth_min(0.3, 2.0, 1.0) - 0.3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn repeated_copies_in_a_row() {
+        let ball = Arc::new(SdfSphere::new(Vec3::new(0.0, 0.0, 0.0), 0.5));
+        let row = Repeat::new(ball, Vec3::new(2.0, 0.0, 0.0), [3, 1, 1]);
+        let bounds = row.bounds();
+        assert!((bounds.max.x - 4.5).abs() < 1e-12 && (bounds.min.x + 0.5).abs() < 1e-12);
+        for x in [0.0, 2.0, 4.0] {
+            assert!(row.distance(&Vec3::new(x, 0.0, 0.0)) < -0.49);
+        }
+        // between copies, and past the ends
+        assert!((row.distance(&Vec3::new(1.0, 0.0, 0.0)) - 0.5).abs() < 1e-12);
+        assert!((row.distance(&Vec3::new(8.0, 0.0, 0.0)) - 3.5).abs() < 1e-12);
+        let along = hittable(row);
+        let hits = along.hit_all(
+            &ray(Vec3::new(-2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            0.001,
+            100.0,
+        );
+        let t: Vec<f64> = hits.iter().map(|h| h.t).collect();
+        assert_eq!(t.len(), 6, "{:?}", t);
+        assert!(
+            (t[2] - 3.5).abs() < 1e-4 && (t[5] - 6.5).abs() < 1e-4,
+            "{:?}",
+            t
+        );
+    }
+
+    #[test]
+    fn twist_never_overestimates() {
+        let bar = Arc::new(RoundedBox::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 4.0, 0.4),
+            0.1,
+        ));
+        let straight = Twist::new(bar.clone(), 0.0);
+        let p = Vec3::new(0.5, 2.3, 0.1);
+        assert!((straight.distance(&p) - bar.distance(&p)).abs() < 1e-12);
+
+        // a quarter turn by the top, so near there the bar lies along z
+        let twisted = Twist::new(bar, std::f64::consts::PI / 4.0);
+        assert!(twisted.distance(&Vec3::new(0.0, 2.0 - 0.2, 0.9)) < 0.0);
+        assert!(twisted.distance(&Vec3::new(0.0, 0.0, 0.9)) > 0.0);
+        let hit = hittable(twisted)
+            .hit(
+                &ray(Vec3::new(0.0, 1.8, 5.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                100.0,
+            )
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < 0.02, "{}", hit.t);
+    }
+
+    #[test]
+    fn mandelbulb_is_inside_its_bounds() {
+        let bulb = hittable(Mandelbulb::new(Vec3::new(0.0, 0.0, 0.0), 1.0, 8.0, 12));
+        let hit = bulb
+            .hit(
+                &ray(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                100.0,
+            )
+            .unwrap();
+        assert!(hit.t > 3.5 && hit.t < 4.5, "{}", hit.t);
+        assert!(hit.normal.z > 0.0);
+    }
+}
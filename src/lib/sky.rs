@@ -0,0 +1,345 @@
+//! An analytic daylight sky, after Preetham, Shirley and Smits, "A Practical Analytic Model
+//! for Daylight" (1999).
+//!
+//! The model gives the luminance and chromaticity of a clear or hazy sky from the position of
+//! the sun and the turbidity of the air. Luminances are in kcd/m² and are scaled to radiance
+//! by `SKY_SCALE`.
+
+use crate::environment::Environment;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::spectrum;
+use crate::vec::Vec3;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Radiance per kcd/m² of luminance, so that a white surface facing a high sun is about 1.
+pub const SKY_SCALE: f64 = 0.025;
+
+/// Luminance of the sun's disk outside the atmosphere, in kcd/m².
+const SUN_LUMINANCE: f64 = 1.96e6;
+
+/// Angular radius of the sun seen from the earth, in degrees.
+pub const SUN_RADIUS: f64 = 0.2666;
+
+/// Perez et al.'s distribution of luminance over the sky, relative to the zenith.
+#[derive(Clone, Copy, Debug)]
+struct Perez {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    e: f64,
+}
+
+impl Perez {
+    /// `theta` is the angle from the zenith and `gamma` the angle to the sun.
+    fn f(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta.max(1e-3)).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Preetham's sky with a sun disk, above a uniformly lit ground.
+///
+/// Below the horizon the sky shows the ground: a diffuse surface with `ground_albedo` lit by
+/// the sun and the sky. The environment is sampled by picking the sun in proportion to its
+/// share of the light and otherwise a uniform direction, since the sky itself is smooth.
+pub struct Sky {
+    /// Unit direction towards the sun
+    sun_direction: Vec3,
+    turbidity: f64,
+    ground_albedo: Vec3,
+    /// Angular radius of the sun disk in degrees
+    sun_size: f64,
+    intensity: f64,
+    /// Perez distributions for the luminance Y and the chromaticities x and y
+    perez: [Perez; 3],
+    /// Y, x and y at the zenith, divided by the Perez function there
+    zenith: [f64; 3],
+    /// Linear RGB radiance of the sun disk and the ground, before `intensity`
+    sun_radiance: Vec3,
+    ground_radiance: Vec3,
+    /// Cosine of the angular radius of the sun
+    cos_sun_radius: f64,
+    /// Probability of sampling the sun rather than a uniform direction
+    sun_probability: f64,
+}
+
+impl Sky {
+    /// A sky with the sun towards `sun_direction`. Turbidity is the haziness of the air, from
+    /// 2 for a very clear sky to around 10 for a hazy one.
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Vec3) -> Self {
+        let mut sky = Sky {
+            sun_direction: Vec3::unit_vector(sun_direction),
+            turbidity: turbidity.clamp(1.7, 10.0),
+            ground_albedo,
+            sun_size: SUN_RADIUS,
+            intensity: 1.0,
+            perez: [Perez {
+                a: 0.0,
+                b: 0.0,
+                c: 0.0,
+                d: 0.0,
+                e: 0.0,
+            }; 3],
+            zenith: [0.0; 3],
+            sun_radiance: Vec3::new(0.0, 0.0, 0.0),
+            ground_radiance: Vec3::new(0.0, 0.0, 0.0),
+            cos_sun_radius: 1.0,
+            sun_probability: 0.0,
+        };
+        sky.precompute();
+        sky
+    }
+
+    /// Sets the angular radius of the sun disk in degrees. Its radiance stays the same, so a
+    /// bigger sun gives more light and softer shadows.
+    pub fn sun_size(mut self, degrees: f64) -> Self {
+        self.sun_size = degrees.clamp(0.01, 45.0);
+        self.precompute();
+        self
+    }
+
+    /// Scales the radiance of the sky, sun and ground.
+    pub fn intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    fn precompute(&mut self) {
+        let t = self.turbidity;
+        let perez = |a: [f64; 2], b: [f64; 2], c: [f64; 2], d: [f64; 2], e: [f64; 2]| Perez {
+            a: a[0] * t + a[1],
+            b: b[0] * t + b[1],
+            c: c[0] * t + c[1],
+            d: d[0] * t + d[1],
+            e: e[0] * t + e[1],
+        };
+        self.perez = [
+            perez(
+                [0.1787, -1.4630],
+                [-0.3554, 0.4275],
+                [-0.0227, 5.3251],
+                [0.1206, -2.5771],
+                [-0.0670, 0.3703],
+            ),
+            perez(
+                [-0.0193, -0.2592],
+                [-0.0665, 0.0008],
+                [-0.0004, 0.2125],
+                [-0.0641, -0.8989],
+                [-0.0033, 0.0452],
+            ),
+            perez(
+                [-0.0167, -0.2608],
+                [-0.0950, 0.0092],
+                [-0.0079, 0.2102],
+                [-0.0441, -1.6537],
+                [-0.0109, 0.0529],
+            ),
+        ];
+
+        // the model only holds for a sun above the horizon
+        let theta_sun = self.sun_direction.y.clamp(0.0, 1.0).acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let polynomial = |c: [[f64; 4]; 3]| {
+            let powers = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+            let row = |r: [f64; 4]| (0..4).map(|i| r[i] * powers[i]).sum::<f64>();
+            t * t * row(c[0]) + t * row(c[1]) + row(c[2])
+        };
+        let x = polynomial([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = polynomial([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let zenith = [luminance.max(0.0), x, y];
+        for (i, perez) in self.perez.iter().enumerate() {
+            self.zenith[i] = zenith[i] / perez.f(1.0, theta_sun);
+        }
+
+        self.sun_radiance = self.sun_transmittance(theta_sun) * (SUN_LUMINANCE * SKY_SCALE);
+        if self.sun_direction.y <= 0.0 {
+            self.sun_radiance = Vec3::new(0.0, 0.0, 0.0);
+        }
+        self.cos_sun_radius = self.sun_size.to_radians().cos();
+        let sun_solid_angle = 2.0 * PI * (1.0 - self.cos_sun_radius);
+
+        // irradiance on the ground from the sky, by a midpoint sum over the upper hemisphere,
+        // and from the sun
+        let (n_theta, n_phi) = (32, 64);
+        let (d_theta, d_phi) = (PI / 2.0 / n_theta as f64, 2.0 * PI / n_phi as f64);
+        let mut sky_irradiance = Vec3::new(0.0, 0.0, 0.0);
+        let mut sky_power = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let w = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let radiance = self.sky_radiance(&w);
+                let solid_angle = theta.sin() * d_theta * d_phi;
+                sky_irradiance += radiance * (theta.cos() * solid_angle);
+                sky_power += spectrum::luminance(&radiance) * solid_angle;
+            }
+        }
+        let sun_irradiance = self.sun_radiance * (sun_solid_angle * self.sun_direction.y.max(0.0));
+        self.ground_radiance = self.ground_albedo * (sky_irradiance + sun_irradiance) / PI;
+
+        // share of the light from the sun, counting the ground as half the sphere
+        let sun_power = spectrum::luminance(&self.sun_radiance) * sun_solid_angle;
+        let ground_power = spectrum::luminance(&self.ground_radiance) * 2.0 * PI;
+        let total = sun_power + sky_power + ground_power;
+        self.sun_probability = if total > 0.0 { sun_power / total } else { 0.0 };
+    }
+
+    /// Fraction of sunlight reaching the ground through air and haze, per color channel,
+    /// for a sun `theta_sun` from the zenith.
+    fn sun_transmittance(&self, theta_sun: f64) -> Vec3 {
+        // relative optical mass of the air along the sun's rays
+        let degrees = theta_sun.to_degrees();
+        let mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - degrees).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        // representative red, green and blue wavelengths in micrometres
+        let channel = |lambda: f64| {
+            let rayleigh = (-0.008735 * lambda.powf(-4.08) * mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * mass).exp();
+            rayleigh * aerosol
+        };
+        Vec3::new(channel(0.61), channel(0.55), channel(0.465))
+    }
+
+    /// Radiance of the clear sky towards `direction` above the horizon, without the sun.
+    fn sky_radiance(&self, direction: &Vec3) -> Vec3 {
+        let cos_theta = direction.y;
+        let gamma = Vec3::dot(direction, &self.sun_direction)
+            .clamp(-1.0, 1.0)
+            .acos();
+        let value = |i: usize| self.zenith[i] * self.perez[i].f(cos_theta, gamma);
+        let (luminance, x, y) = (value(0) * SKY_SCALE, value(1), value(2));
+        if y <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = spectrum::xyz_to_rgb(&xyz);
+        Vec3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+
+    fn in_sun(&self, direction: &Vec3) -> bool {
+        Vec3::dot(direction, &self.sun_direction) >= self.cos_sun_radius
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: &Vec3) -> Vec3 {
+        if direction.y < 0.0 {
+            return self.intensity * self.ground_radiance;
+        }
+        let mut radiance = self.sky_radiance(direction);
+        if self.in_sun(direction) {
+            radiance += self.sun_radiance;
+        }
+        self.intensity * radiance
+    }
+
+    fn is_sampled(&self) -> bool {
+        true
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let mut pdf = (1.0 - self.sun_probability) / (4.0 * PI);
+        if self.in_sun(direction) {
+            pdf += self.sun_probability / (2.0 * PI * (1.0 - self.cos_sun_radius));
+        }
+        pdf
+    }
+
+    fn sample(&self, sampler: &mut Sampler) -> Vec3 {
+        if sampler.gen::<f64>() >= self.sun_probability {
+            return Vec3::random_unit_vector(sampler);
+        }
+        // uniform in the cone of the sun disk
+        let z = 1.0 - sampler.gen::<f64>() * (1.0 - self.cos_sun_radius);
+        let phi = 2.0 * PI * sampler.gen::<f64>();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Onb::build_from_w(&self.sun_direction).local(&Vec3::new(r * phi.cos(), r * phi.sin(), z))
+    }
+}
+
+impl fmt::Display for Sky {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "sky with the sun towards {}, turbidity {}",
+            self.sun_direction, self.turbidity
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn daylight(turbidity: f64) -> Sky {
+        Sky::new(
+            Vec3::new(1.0, 1.0, 0.5),
+            turbidity,
+            Vec3::new(0.3, 0.3, 0.3),
+        )
+    }
+
+    #[test]
+    fn daylight_looks_like_daylight() {
+        let sky = daylight(3.0);
+        let zenith = sky.radiance(&Vec3::new(0.0, 1.0, 0.0));
+        // a blue sky, dim next to the sun
+        assert!(zenith.z > zenith.x);
+        let sun = sky.radiance(&sky.sun_direction);
+        assert!(sun.y > 1000.0 * zenith.y);
+        // the ground is lit by both
+        let ground = sky.radiance(&Vec3::new(0.0, -1.0, 0.0));
+        assert!(ground.y > 0.0);
+        let brighter = Sky::new(sky.sun_direction, 3.0, Vec3::new(0.6, 0.6, 0.6));
+        let brighter_ground = brighter.radiance(&Vec3::new(0.0, -1.0, 0.0));
+        assert!((brighter_ground.y - 2.0 * ground.y).abs() < 1e-9 * ground.y);
+        // haze dims and reddens the sun
+        let hazy = daylight(8.0).sun_radiance;
+        assert!(hazy.y < sky.sun_radiance.y);
+        assert!(hazy.z / hazy.x < sky.sun_radiance.z / sky.sun_radiance.x);
+    }
+
+    #[test]
+    fn sampling_follows_the_density() {
+        let sky = daylight(3.0).sun_size(5.0);
+        assert!(sky.sun_probability > 0.0 && sky.sun_probability < 1.0);
+        let mut sampler = Sampler::new(4);
+        let n = 400_000;
+        // the light on a surface facing up is what lights the ground
+        let irradiance = |w: &Vec3| sky.radiance(w).y * w.y.max(0.0);
+        let sampled: f64 = (0..n)
+            .map(|_| {
+                let w = sky.sample(&mut sampler);
+                irradiance(&w) / sky.pdf(&w)
+            })
+            .sum::<f64>()
+            / n as f64;
+        let expected = sky.ground_radiance.y * PI / 0.3;
+        assert!(
+            (sampled - expected).abs() < 0.02 * expected,
+            "{} vs {}",
+            sampled,
+            expected
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Conversions between RGB colors and single-wavelength spectral samples.
+//!
+//! In spectral mode every camera sample follows one wavelength. RGB colors from textures
+//! and lights are turned into the value of a matching spectrum at that wavelength, and the
+//! radiance carried back is weighted with the CIE color matching functions to give XYZ,
+//! which is then converted to linear sRGB.
+
+use crate::sampler::Sampler;
+use crate::vec::Vec3;
+use rand::Rng;
+
+/// Shortest wavelength sampled, in nanometres.
+pub const WAVELENGTH_MIN: f64 = 380.0;
+/// Longest wavelength sampled, in nanometres.
+pub const WAVELENGTH_MAX: f64 = 780.0;
+
+/// Wavelength of the sodium D line, where a material's single index of refraction is given.
+pub const WAVELENGTH_D: f64 = 589.3;
+
+/// Picks a wavelength uniformly from the visible range.
+pub fn sample_wavelength(sampler: &mut Sampler) -> f64 {
+    sampler.gen_range(WAVELENGTH_MIN..WAVELENGTH_MAX)
+}
+
+/// Piecewise Gaussian with different widths on either side of the peak.
+fn lobe(wavelength: f64, peak: f64, width_below: f64, width_above: f64) -> f64 {
+    let width = if wavelength < peak {
+        width_below
+    } else {
+        width_above
+    };
+    let t = (wavelength - peak) / width;
+    (-0.5 * t * t).exp()
+}
+
+/// The area under `lobe` over the whole real line.
+fn lobe_area(width_below: f64, width_above: f64) -> f64 {
+    (std::f64::consts::PI / 2.0).sqrt() * (width_below + width_above)
+}
+
+/// CIE 1931 2° color matching functions, using the multi-lobe fit of Wyman, Sloan and
+/// Shirley, "Simple Analytic Approximations to the CIE XYZ Color Matching Functions".
+pub fn cie_xyz(wavelength: f64) -> Vec3 {
+    let l = wavelength;
+    Vec3::new(
+        1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(l, 501.1, 20.4, 26.2),
+        0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1),
+        1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8),
+    )
+}
+
+/// The integrals of the color matching functions. The lobes are negligible outside the
+/// sampled range, so the whole line is used.
+fn cie_integrals() -> Vec3 {
+    Vec3::new(
+        1.056 * lobe_area(37.9, 31.0) + 0.362 * lobe_area(16.0, 26.7)
+            - 0.065 * lobe_area(20.4, 26.2),
+        0.821 * lobe_area(46.9, 40.5) + 0.286 * lobe_area(16.3, 31.1),
+        1.217 * lobe_area(11.8, 36.0) + 0.681 * lobe_area(26.0, 13.8),
+    )
+}
+
+/// Relative luminance of a linear sRGB color, its Y in CIE XYZ.
+pub fn luminance(rgb: &Vec3) -> f64 {
+    0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z
+}
+
+/// Linear sRGB (D65) to CIE XYZ.
+pub fn rgb_to_xyz(rgb: &Vec3) -> Vec3 {
+    Vec3::new(
+        0.4124564 * rgb.x + 0.3575761 * rgb.y + 0.1804375 * rgb.z,
+        0.2126729 * rgb.x + 0.7151522 * rgb.y + 0.0721750 * rgb.z,
+        0.0193339 * rgb.x + 0.1191920 * rgb.y + 0.9503041 * rgb.z,
+    )
+}
+
+/// CIE XYZ to linear sRGB (D65).
+pub fn xyz_to_rgb(xyz: &Vec3) -> Vec3 {
+    Vec3::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+/// Estimate of XYZ from the radiance carried at one uniformly sampled wavelength, scaled so
+/// a constant spectrum of 1 has Y = 1.
+pub fn sample_to_xyz(radiance: f64, wavelength: f64) -> Vec3 {
+    let pdf = 1.0 / (WAVELENGTH_MAX - WAVELENGTH_MIN);
+    cie_xyz(wavelength) * (radiance / (pdf * cie_integrals().y))
+}
+
+/// Estimate of linear sRGB from the radiance carried at one wavelength.
+///
+/// The spectra made by `rgb_to_spectrum` are white balanced for a constant spectrum, so the
+/// result is white balanced the same way: RGB white upsampled and converted back stays white.
+pub fn sample_to_rgb(radiance: f64, wavelength: f64) -> Vec3 {
+    let white = xyz_to_rgb(&(cie_integrals() / cie_integrals().y));
+    let rgb = xyz_to_rgb(&sample_to_xyz(radiance, wavelength));
+    Vec3::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+}
+
+/// Number of bins of the Smits basis spectra, evenly covering 380 to 720 nm.
+const SMITS_BINS: usize = 10;
+const SMITS_MIN: f64 = 380.0;
+const SMITS_MAX: f64 = 720.0;
+
+const SMITS_WHITE: [f64; SMITS_BINS] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; SMITS_BINS] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; SMITS_BINS] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; SMITS_BINS] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; SMITS_BINS] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; SMITS_BINS] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; SMITS_BINS] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Value at `wavelength` of a smooth spectrum with the color `rgb`, using Smits' method
+/// ("An RGB-to-Spectrum Conversion for Reflectances", 1999). The spectrum is a sum of
+/// white and at most two of the primary and secondary basis spectra. It's linear in the
+/// brightness of `rgb`, so it works for emission above 1 as well as for reflectances.
+pub fn rgb_to_spectrum(rgb: &Vec3, wavelength: f64) -> f64 {
+    let bin = ((wavelength - SMITS_MIN) / (SMITS_MAX - SMITS_MIN) * SMITS_BINS as f64)
+        .max(0.0)
+        .min((SMITS_BINS - 1) as f64) as usize;
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    if r <= g && r <= b {
+        r * SMITS_WHITE[bin]
+            + if g <= b {
+                (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+            } else {
+                (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+            }
+    } else if g <= r && g <= b {
+        g * SMITS_WHITE[bin]
+            + if r <= b {
+                (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+            } else {
+                (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+            }
+    } else {
+        b * SMITS_WHITE[bin]
+            + if r <= g {
+                (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+            } else {
+                (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Converts `rgb` to a spectrum and back with a fine Riemann sum over the wavelengths.
+    fn round_trip(rgb: Vec3) -> Vec3 {
+        let steps = 4000;
+        let step = (WAVELENGTH_MAX - WAVELENGTH_MIN) / steps as f64;
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..steps {
+            let wavelength = WAVELENGTH_MIN + (i as f64 + 0.5) * step;
+            sum += sample_to_rgb(rgb_to_spectrum(&rgb, wavelength), wavelength);
+        }
+        sum / steps as f64
+    }
+
+    #[test]
+    fn white_stays_white() {
+        let white = round_trip(Vec3::new(2.0, 2.0, 2.0));
+        assert!(
+            (white - Vec3::new(2.0, 2.0, 2.0)).length() < 0.01,
+            "{}",
+            white
+        );
+        let y = rgb_to_xyz(&xyz_to_rgb(&Vec3::new(0.3, 0.6, 0.1))).y;
+        assert!((y - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn colors_survive_the_round_trip() {
+        for rgb in [
+            Vec3::new(0.8, 0.1, 0.1),
+            Vec3::new(0.1, 0.6, 0.2),
+            Vec3::new(0.2, 0.3, 0.9),
+            Vec3::new(0.9, 0.8, 0.3),
+        ] {
+            let result = round_trip(rgb);
+            assert!((result - rgb).length() < 0.1, "{} became {}", rgb, result);
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::Vec3;
+use rand::Rng;
 use std::f64::consts::PI;
 use std::sync::Arc;
 
@@ -57,8 +60,10 @@ impl Sphere {
     /// v: return value [0.0, 1.0], angle from Y=-1 to Y=+1
     /// # Examples
     /// ``` rust
+    /// # use lib::sphere::Sphere;
+    /// # use lib::vec::Vec3;
     /// let (u, v) = Sphere::get_sphere_uv(&Vec3::new(1.0, 0.0, 0.0));
-    /// assert_eq!((u, v), (0.0, 0.5));
+    /// assert_eq!((u, v), (0.5, 0.5));
     /// ```
     pub fn get_sphere_uv(p: &Vec3) -> (f64, f64) {
         let theta = (-p.y).acos();
@@ -97,15 +102,70 @@ impl Hittable for Sphere {
         (hit_record.u, hit_record.v) = Sphere::get_sphere_uv(&outward_normal);
         Some(hit_record)
     }
-    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        let (center_start, center_end) = (self.center(time_start), self.center(time_end));
         let box_start = AABB::new(
-            self.center_start - Vec3::new(self.radius, self.radius, self.radius),
-            self.center_start + Vec3::new(self.radius, self.radius, self.radius),
+            center_start - Vec3::new(self.radius, self.radius, self.radius),
+            center_start + Vec3::new(self.radius, self.radius, self.radius),
         );
         let box_end = AABB::new(
-            self.center_start - Vec3::new(self.radius, self.radius, self.radius),
-            self.center_start + Vec3::new(self.radius, self.radius, self.radius),
+            center_end - Vec3::new(self.radius, self.radius, self.radius),
+            center_end + Vec3::new(self.radius, self.radius, self.radius),
         );
         Some(AABB::surrounding_box(&box_start, &box_end))
     }
+
+    /// Samples the cone of directions the sphere covers as seen from `origin`.
+    /// Moving spheres are sampled at their starting position.
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let ray = Ray::new_at_time(*origin, *direction, self.time_start);
+        if self.hit(&ray, 0.001, f64::INFINITY).is_none() {
+            return 0.0;
+        }
+        let distance_squared = (self.center_start - *origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            // from inside, every direction hits the sphere
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let direction = self.center_start - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector(sampler);
+        }
+        let r1 = sampler.gen::<f64>();
+        let r2 = sampler.gen::<f64>();
+        let z = 1.0 + r2 * ((1.0 - self.radius * self.radius / distance_squared).sqrt() - 1.0);
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+        Onb::build_from_w(&direction).local(&Vec3::new(x, y, z))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn moving_box_covers_both_ends() {
+        let sphere = Sphere::new_moving(
+            1.0,
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            0.0,
+            1.0,
+            Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let whole = sphere.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!((whole.min.x, whole.max.x), (-1.0, 5.0));
+        // a shutter open for the second half only
+        let late = sphere.bounding_box(0.5, 1.0).unwrap();
+        assert_eq!((late.min.x, late.max.x), (1.0, 5.0));
+    }
 }
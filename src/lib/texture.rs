@@ -1,6 +1,9 @@
+use crate::error::Error;
 use crate::perlin::Perlin;
+use crate::sampler::Sampler;
 use crate::vec::Vec3;
 use stb_image::image::{Image, LoadResult};
+use std::path::Path;
 use std::sync::Arc;
 
 pub trait Texture: Send + Sync {
@@ -62,15 +65,15 @@ pub struct NoiseTexture {
     scale: f64,
 }
 impl NoiseTexture {
-    pub fn new() -> Self {
+    pub fn new(sampler: &mut Sampler) -> Self {
         NoiseTexture {
-            noise: Perlin::new(),
+            noise: Perlin::new(sampler),
             scale: 1.0,
         }
     }
-    pub fn new_scaled(scale: f64) -> Self {
+    pub fn new_scaled(scale: f64, sampler: &mut Sampler) -> Self {
         NoiseTexture {
-            noise: Perlin::new(),
+            noise: Perlin::new(sampler),
             scale,
         }
     }
@@ -104,13 +107,38 @@ impl ImageTexture {
     }
 
     pub fn new_from_file(filename: &str) -> Self {
-        let image = if let LoadResult::ImageU8(image) = stb_image::image::load(filename) {
-            image
-        } else {
-            eprintln!("Unable to load image file");
-            Image::new(1, 1, 1, vec![255, 0, 0])
-        };
+        ImageTexture::load_file(filename).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            ImageTexture::from_image(Image::new(1, 1, 1, vec![255, 0, 0]))
+        })
+    }
+
+    /// Loads an 8 bit image such as a PNG or JPEG file.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        ImageTexture::decode(stb_image::image::load(&path)).map_err(|e| {
+            Error::argument(&format!(
+                "Unable to load image file {}: {}",
+                path.as_ref().display(),
+                e
+            ))
+        })
+    }
+
+    /// Decodes an 8 bit image held in memory, such as one built into the program.
+    pub fn load_from_memory(bytes: &[u8]) -> Result<Self, Error> {
+        ImageTexture::decode(stb_image::image::load_from_memory(bytes))
+            .map_err(|e| Error::argument(&format!("Unable to decode image: {}", e)))
+    }
 
+    fn decode(image: LoadResult) -> Result<Self, String> {
+        match image {
+            LoadResult::ImageU8(image) => Ok(ImageTexture::from_image(image)),
+            LoadResult::ImageF32(_) => Err("floating point images aren't supported".to_string()),
+            LoadResult::Error(e) => Err(e),
+        }
+    }
+
+    fn from_image(image: Image<u8>) -> Self {
         ImageTexture {
             data: image.data,
             width: image.width,
@@ -121,6 +149,12 @@ impl ImageTexture {
     }
 }
 
+impl Default for ImageTexture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Texture for ImageTexture {
     fn value(&self, u: f64, v: f64, _p: &Vec3) -> Vec3 {
         if self.data.is_empty() {
@@ -135,7 +169,7 @@ impl Texture for ImageTexture {
 
         let color_scale = 1.0 / 255.0;
 
-        let pixel_start = (j * self.bytes_per_scanline + i * self.depth) as usize;
+        let pixel_start = j * self.bytes_per_scanline + i * self.depth;
         Vec3::new(
             color_scale * self.data[pixel_start] as f64,
             color_scale * self.data[pixel_start + 1] as f64,
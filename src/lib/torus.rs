@@ -0,0 +1,149 @@
+use crate::aabb::AABB;
+use crate::cylinder::around_axis;
+use crate::frame::{Frame, Motion};
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::polynomial::quartic_roots;
+use crate::ray::Ray;
+use crate::vec::Vec3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// A ring around `center` in the plane perpendicular to `axis`: the circle of
+/// `major_radius` swept by a tube of `minor_radius`.
+///
+/// u goes around the axis and v around the tube, starting on its inside.
+pub struct Torus {
+    frame: Frame,
+    major_radius: f64,
+    minor_radius: f64,
+    material: Arc<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Torus::new_moving(
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+            Motion::none(),
+            material,
+        )
+    }
+
+    pub fn new_moving(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        motion: Motion,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Torus {
+            frame: Frame::new(center, axis, motion),
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let local = self.frame.to_local(ray);
+        let length = local.direction.length();
+        let d = local.direction / length;
+        let (big, small) = (self.major_radius, self.minor_radius);
+
+        // The quartic is badly conditioned far from the torus, so the origin is first
+        // moved up to the sphere around it. Distances below are along the unit direction.
+        let bound = big + small;
+        let f = Vec3::dot(&local.origin, &d);
+        let clearance = f * f - (local.origin.length_squared() - bound * bound);
+        if clearance < 0.0 {
+            return None;
+        }
+        let start = (-f - clearance.sqrt()).max(0.0);
+        let o = local.origin + start * d;
+
+        // (|p|² + R² - r²)² = 4R² (x² + y²) along o + s d
+        let f = Vec3::dot(&o, &d);
+        let m = o.length_squared();
+        let g = m + big * big - small * small;
+        let four_r2 = 4.0 * big * big;
+        let roots = quartic_roots(
+            4.0 * f,
+            4.0 * f * f + 2.0 * g - four_r2 * (1.0 - d.z * d.z),
+            4.0 * f * g - 2.0 * four_r2 * (f - o.z * d.z),
+            g * g - four_r2 * (m - o.z * o.z),
+        );
+        let t = roots
+            .into_iter()
+            .map(|s| (start + s) / length)
+            .find(|t| *t >= t_min && *t <= t_max)?;
+
+        let p = local.at(t);
+        let ring = (p.x * p.x + p.y * p.y).sqrt();
+        let normal = p * (p.length_squared() + big * big - small * small)
+            - Vec3::new(p.x, p.y, 0.0) * (2.0 * big * big);
+        let around_tube = (p.z.atan2(ring - big) + PI) / (2.0 * PI);
+        let uv = (around_axis(&p), around_tube);
+        Some(self.frame.hit_record(ray, t, &normal, uv, &self.material))
+    }
+
+    fn bounding_box(&self, time_start: f64, time_end: f64) -> Option<AABB> {
+        let (reach, small) = (self.major_radius + self.minor_radius, self.minor_radius);
+        let local = AABB::new(
+            Vec3::new(-reach, -reach, -small),
+            Vec3::new(reach, reach, small),
+        );
+        Some(self.frame.bounding_box(&local, time_start, time_end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn rays_find_the_nearest_side_of_the_tube() {
+        let torus = Torus::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ray = |origin: Vec3, direction: Vec3| Ray::new_at_time(origin, direction, 0.0);
+
+        // through the middle of the ring, along its plane, from far away
+        let across = ray(Vec3::new(-1000.0, 1.0, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        let hits: Vec<f64> = [0.001, 998.0, 999.0, 1002.0]
+            .iter()
+            .map(|t_min| torus.hit(&across, t_min / 2.0, f64::INFINITY).unwrap().t * 2.0)
+            .collect();
+        for (hit, expected) in hits.iter().zip([997.5, 998.5, 1001.5, 1002.5]) {
+            assert!((hit - expected).abs() < 1e-6, "{:?}", hits);
+        }
+        let outside = torus.hit(&across, 0.001, f64::INFINITY).unwrap();
+        assert!((outside.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+
+        // down onto the top of the tube, and through the hole
+        let top = torus.hit(
+            &ray(Vec3::new(0.0, 5.0, 2.0), Vec3::new(0.0, -1.0, 0.0)),
+            0.001,
+            10.0,
+        );
+        assert!(top.is_some_and(|h| (h.t - 3.5).abs() < 1e-9 && h.normal.y > 0.999));
+        let hole = ray(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(&hole, 0.001, 10.0).is_none());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::aabb::AABB;
+use crate::vec::Vec3;
+use std::ops::Mul;
+
+/// Row-major 4x4 matrix acting on column vectors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix {
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Matrix { m }
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix { m }
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting; `None` for singular matrices.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inverse = Matrix::identity().m;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[column][j];
+                        inverse[row][j] -= factor * inverse[column][j];
+                    }
+                }
+            }
+        }
+        Some(Matrix { m: inverse })
+    }
+
+    /// Determinant of the upper left 3x3 part, the volume scale of the linear map.
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            Vec3::new(x, y, z)
+        } else {
+            Vec3::new(x, y, z) / w
+        }
+    }
+
+    /// Applies the linear part only, for directions.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Matrix {
+    type Output = Matrix;
+
+    fn mul(self, other: Matrix) -> Matrix {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix { m }
+    }
+}
+
+/// An invertible affine transform, kept together with its inverse.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub matrix: Matrix,
+    pub inverse: Matrix,
+}
+
+impl Transform {
+    /// `None` if `matrix` can't be inverted.
+    pub fn new(matrix: Matrix) -> Option<Self> {
+        matrix
+            .inverse()
+            .map(|inverse| Transform { matrix, inverse })
+    }
+
+    pub fn identity() -> Self {
+        Transform {
+            matrix: Matrix::identity(),
+            inverse: Matrix::identity(),
+        }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut matrix = Matrix::identity();
+        let mut inverse = Matrix::identity();
+        for (i, value) in [offset.x, offset.y, offset.z].iter().enumerate() {
+            matrix.m[i][3] = *value;
+            inverse.m[i][3] = -value;
+        }
+        Transform { matrix, inverse }
+    }
+
+    /// `None` if any factor is zero.
+    pub fn scaling(factors: Vec3) -> Option<Self> {
+        if factors.x == 0.0 || factors.y == 0.0 || factors.z == 0.0 {
+            return None;
+        }
+        let mut matrix = Matrix::identity();
+        let mut inverse = Matrix::identity();
+        for (i, value) in [factors.x, factors.y, factors.z].iter().enumerate() {
+            matrix.m[i][i] = *value;
+            inverse.m[i][i] = 1.0 / value;
+        }
+        Some(Transform { matrix, inverse })
+    }
+
+    /// Counter-clockwise rotation by `angle` degrees around `axis`, looking against the axis.
+    pub fn rotation(axis: Vec3, angle: f64) -> Self {
+        let a = Vec3::unit_vector(axis);
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut matrix = Matrix::identity();
+        matrix.m[0][..3].copy_from_slice(&[
+            a.x * a.x * (1.0 - cos) + cos,
+            a.x * a.y * (1.0 - cos) - a.z * sin,
+            a.x * a.z * (1.0 - cos) + a.y * sin,
+        ]);
+        matrix.m[1][..3].copy_from_slice(&[
+            a.y * a.x * (1.0 - cos) + a.z * sin,
+            a.y * a.y * (1.0 - cos) + cos,
+            a.y * a.z * (1.0 - cos) - a.x * sin,
+        ]);
+        matrix.m[2][..3].copy_from_slice(&[
+            a.z * a.x * (1.0 - cos) - a.y * sin,
+            a.z * a.y * (1.0 - cos) + a.x * sin,
+            a.z * a.z * (1.0 - cos) + cos,
+        ]);
+        // rotations are orthogonal
+        Transform {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    pub fn rotation_x(angle: f64) -> Self {
+        Transform::rotation(Vec3::new(1.0, 0.0, 0.0), angle)
+    }
+
+    pub fn rotation_y(angle: f64) -> Self {
+        Transform::rotation(Vec3::new(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn rotation_z(angle: f64) -> Self {
+        Transform::rotation(Vec3::new(0.0, 0.0, 1.0), angle)
+    }
+
+    /// This transform followed by `next`.
+    pub fn then(&self, next: &Transform) -> Self {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    /// Normals transform with the inverse transpose so they stay perpendicular to the surface.
+    /// The result isn't normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.inverse.transpose().transform_vector(n)
+    }
+
+    /// The smallest box around the transformed corners of `b`.
+    pub fn bounding_box(&self, b: &AABB) -> AABB {
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { b.min.x } else { b.max.x },
+                if i & 2 == 0 { b.min.y } else { b.max.y },
+                if i & 4 == 0 { b.min.z } else { b.max.z },
+            );
+            let p = self.point(&corner);
+            min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        AABB::new(min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn composed_transform_and_inverse() {
+        let t = Transform::scaling(Vec3::new(2.0, 1.0, 1.0))
+            .unwrap()
+            .then(&Transform::rotation_z(90.0))
+            .then(&Transform::translation(Vec3::new(0.0, 0.0, 5.0)));
+        let p = t.point(&Vec3::new(1.0, 0.0, 0.0));
+        assert_near(p, Vec3::new(0.0, 2.0, 5.0));
+        assert_near(t.inverse().point(&p), Vec3::new(1.0, 0.0, 0.0));
+        // translation doesn't move directions
+        assert_near(
+            t.vector(&Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 2.0, 0.0),
+        );
+
+        let general = Transform::new(t.matrix).unwrap();
+        for (a, b) in general.inverse.m.iter().zip(t.inverse.m.iter()) {
+            for (x, y) in a.iter().zip(b) {
+                assert!((x - y).abs() < 1e-9);
+            }
+        }
+        assert!(Transform::new(Matrix { m: [[0.0; 4]; 4] }).is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_scaling() {
+        let t = Transform::scaling(Vec3::new(4.0, 1.0, 1.0)).unwrap();
+        // the plane x + y = 1 and a vector lying in it
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let dot = Vec3::dot(&t.normal(&normal), &t.vector(&tangent));
+        assert!(dot.abs() < 1e-12);
+    }
+
+    #[test]
+    fn rotated_box_is_tight() {
+        let unit = AABB::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let b = Transform::rotation_y(45.0).bounding_box(&unit);
+        let half_diagonal = 2.0_f64.sqrt();
+        assert_near(b.min, Vec3::new(-half_diagonal, -1.0, -half_diagonal));
+        assert_near(b.max, Vec3::new(half_diagonal, 1.0, half_diagonal));
+    }
+}
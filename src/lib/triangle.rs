@@ -0,0 +1,358 @@
+use crate::aabb::AABB;
+use crate::bvh;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::Vec3;
+use rand::Rng;
+use std::sync::Arc;
+
+/// Triangles thinner than this along an axis get their bounding box padded.
+const PADDING: f64 = 0.0001;
+
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [(f64, f64); 3],
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, material: Arc<dyn Material>) -> Self {
+        Triangle {
+            vertices: [a, b, c],
+            normals: None,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            material,
+        }
+    }
+
+    /// Shading normals at the vertices, interpolated across the face.
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    /// Texture coordinates at the vertices, interpolated across the face.
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    fn area(&self) -> f64 {
+        let [a, b, c] = self.vertices;
+        0.5 * Vec3::cross(b - a, c - a).length()
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, b1, b2) = intersect(ray, &self.vertices, t_min, t_max)?;
+        Some(hit_record(
+            ray,
+            t,
+            (b1, b2),
+            &self.vertices,
+            self.normals.as_ref(),
+            &self.uvs,
+            self.material.clone(),
+        ))
+    }
+
+    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
+        Some(bounds(&self.vertices))
+    }
+
+    fn pdf_value(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let ray = Ray::new_at_time(*origin, *direction, 0.0);
+        match intersect(&ray, &self.vertices, 0.001, f64::INFINITY) {
+            Some((t, _, _)) => {
+                let [a, b, c] = self.vertices;
+                let normal = Vec3::unit_vector(Vec3::cross(b - a, c - a));
+                let distance_squared = t * t * direction.length_squared();
+                let cosine = Vec3::dot(direction, &normal).abs() / direction.length();
+                distance_squared / (cosine * self.area())
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        // uniform over the area, see "Shape Distributions" by Osada et al.
+        let r1 = sampler.gen::<f64>().sqrt();
+        let r2 = sampler.gen::<f64>();
+        let [a, b, c] = self.vertices;
+        let point = (1.0 - r1) * a + r1 * (1.0 - r2) * b + r1 * r2 * c;
+        point - *origin
+    }
+}
+
+/// Möller–Trumbore intersection. Returns the ray parameter and the barycentric
+/// coordinates of the hit for the second and third vertex.
+fn intersect(ray: &Ray, vertices: &[Vec3; 3], t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let [a, b, c] = *vertices;
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = Vec3::cross(ray.direction, edge2);
+    let determinant = Vec3::dot(&edge1, &p);
+    if determinant.abs() < 1e-12 {
+        // ray parallel to the triangle
+        return None;
+    }
+    let inv_determinant = 1.0 / determinant;
+    let s = ray.origin - a;
+    let b1 = Vec3::dot(&s, &p) * inv_determinant;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let q = Vec3::cross(s, edge1);
+    let b2 = Vec3::dot(&ray.direction, &q) * inv_determinant;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = Vec3::dot(&edge2, &q) * inv_determinant;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn hit_record(
+    ray: &Ray,
+    t: f64,
+    (b1, b2): (f64, f64),
+    vertices: &[Vec3; 3],
+    normals: Option<&[Vec3; 3]>,
+    uvs: &[(f64, f64); 3],
+    material: Arc<dyn Material>,
+) -> HitRecord {
+    let b0 = 1.0 - b1 - b2;
+    let [a, b, c] = *vertices;
+    let mut hit_record = HitRecord::new(material);
+    hit_record.t = t;
+    hit_record.point = ray.at(t);
+    hit_record.u = b0 * uvs[0].0 + b1 * uvs[1].0 + b2 * uvs[2].0;
+    hit_record.v = b0 * uvs[0].1 + b1 * uvs[1].1 + b2 * uvs[2].1;
+
+    let geometric = Vec3::unit_vector(Vec3::cross(b - a, c - a));
+    hit_record.set_face_normal(ray, &geometric);
+    if let Some([n0, n1, n2]) = normals {
+        // the shading normal stays on the side of the surface the ray arrived from
+        let shading = Vec3::unit_vector(b0 * *n0 + b1 * *n1 + b2 * *n2);
+        hit_record.normal = if Vec3::dot(&shading, &hit_record.normal) < 0.0 {
+            -shading
+        } else {
+            shading
+        };
+    }
+    hit_record
+}
+
+/// Box around `vertices`, padded along the axes where it would be flat.
+pub(crate) fn bounds(vertices: &[Vec3]) -> AABB {
+    let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for v in vertices {
+        min = Vec3::new(min.x.min(v.x), min.y.min(v.y), min.z.min(v.z));
+        max = Vec3::new(max.x.max(v.x), max.y.max(v.y), max.z.max(v.z));
+    }
+    let pad = |extent: f64| if extent < PADDING { PADDING } else { 0.0 };
+    let padding = Vec3::new(pad(max.x - min.x), pad(max.y - min.y), pad(max.z - min.z));
+    AABB::new(min - padding, max + padding)
+}
+
+/// Indexed triangles sharing vertex buffers and one material.
+///
+/// The mesh is a single hittable with its own bounding volume hierarchy over the
+/// triangles, built by the same flat builder `Bvh` uses.
+pub struct TriangleMesh {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<[usize; 3]>,
+    nodes: Vec<bvh::Node>,
+    material: Arc<dyn Material>,
+}
+
+impl TriangleMesh {
+    /// `normals` and `uvs` are either empty or hold one entry per position.
+    /// Each triangle lists three indices into the vertex buffers.
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        triangles: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(normals.is_empty() || normals.len() == positions.len());
+        assert!(uvs.is_empty() || uvs.len() == positions.len());
+        assert!(triangles.iter().flatten().all(|&i| i < positions.len()));
+
+        let mut mesh = TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            nodes: Vec::new(),
+            material,
+        };
+        let boxes = mesh
+            .triangles
+            .iter()
+            .map(|t| bounds(&mesh.vertices(t)))
+            .collect::<Vec<_>>();
+        let (nodes, order) = bvh::build(&boxes);
+        mesh.nodes = nodes;
+        mesh.triangles = order.iter().map(|&i| mesh.triangles[i]).collect();
+        mesh
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn vertices(&self, triangle: &[usize; 3]) -> [Vec3; 3] {
+        [
+            self.positions[triangle[0]],
+            self.positions[triangle[1]],
+            self.positions[triangle[2]],
+        ]
+    }
+
+    fn hit_triangle(&self, index: usize, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let triangle = &self.triangles[index];
+        let vertices = self.vertices(triangle);
+        let (t, b1, b2) = intersect(ray, &vertices, t_min, t_max)?;
+        let normals = if self.normals.is_empty() {
+            None
+        } else {
+            Some([
+                self.normals[triangle[0]],
+                self.normals[triangle[1]],
+                self.normals[triangle[2]],
+            ])
+        };
+        let uvs = if self.uvs.is_empty() {
+            [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]
+        } else {
+            [
+                self.uvs[triangle[0]],
+                self.uvs[triangle[1]],
+                self.uvs[triangle[2]],
+            ]
+        };
+        Some(hit_record(
+            ray,
+            t,
+            (b1, b2),
+            &vertices,
+            normals.as_ref(),
+            &uvs,
+            self.material.clone(),
+        ))
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        bvh::traverse(&self.nodes, ray, t_min, t_max, |i, closest_so_far| {
+            let hit = self.hit_triangle(i, ray, t_min, closest_so_far)?;
+            let t = hit.t;
+            closest = Some(hit);
+            Some(t)
+        });
+        closest
+    }
+
+    fn bounding_box(&self, _time_start: f64, _time_end: f64) -> Option<AABB> {
+        self.nodes.first().map(|n| *n.bounds())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_color(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn ray_down(x: f64, z: f64) -> Ray {
+        Ray::new_at_time(Vec3::new(x, 1.0, z), Vec3::new(0.0, -1.0, 0.0), 0.0)
+    }
+
+    #[test]
+    fn hit_interpolates_uv_and_normal() {
+        let triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            material(),
+        )
+        .with_normals([
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ]);
+
+        let hit = triangle.hit(&ray_down(0.5, 0.25), 0.001, 10.0).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-12);
+        assert!((hit.u - 0.5).abs() < 1e-12 && (hit.v - 0.25).abs() < 1e-12);
+        // facing the ray, leaning towards the second vertex's normal
+        assert!(hit.normal.y > 0.0 && hit.normal.x > 0.0);
+        assert!((hit.normal.length() - 1.0).abs() < 1e-12);
+
+        assert!(triangle.hit(&ray_down(0.6, 0.6), 0.001, 10.0).is_none());
+        assert!(triangle.hit(&ray_down(0.5, 0.25), 0.001, 0.5).is_none());
+    }
+
+    #[test]
+    fn mesh_matches_separate_triangles() {
+        // a bumpy 16x16 height field
+        let n = 16;
+        let mut positions = Vec::new();
+        for i in 0..=n {
+            for j in 0..=n {
+                let height = ((i * 7 + j * 3) % 5) as f64 * 0.1;
+                positions.push(Vec3::new(i as f64, height, j as f64));
+            }
+        }
+        let mut triangles = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                let corner = i * (n + 1) + j;
+                triangles.push([corner, corner + n + 1, corner + 1]);
+                triangles.push([corner + 1, corner + n + 1, corner + n + 2]);
+            }
+        }
+        let mut list = HittableList::new();
+        for t in &triangles {
+            list.add(Arc::new(Triangle::new(
+                positions[t[0]],
+                positions[t[1]],
+                positions[t[2]],
+                material(),
+            )));
+        }
+        let mesh = TriangleMesh::new(positions, Vec::new(), Vec::new(), triangles, material());
+        assert_eq!(mesh.triangle_count(), 2 * n * n);
+
+        let mut sampler = Sampler::new(3);
+        for _ in 0..500 {
+            let origin = Vec3::new(
+                sampler.gen_range(-2.0..18.0),
+                3.0,
+                sampler.gen_range(-2.0..18.0),
+            );
+            let direction = Vec3::random_in_unit_sphere(&mut sampler) - Vec3::new(0.0, 1.0, 0.0);
+            let ray = Ray::new_at_time(origin, direction, 0.0);
+            let expected = list.hit(&ray, 0.001, f64::INFINITY).map(|h| h.t);
+            let actual = mesh.hit(&ray, 0.001, f64::INFINITY).map(|h| h.t);
+            assert_eq!(expected, actual);
+        }
+    }
+}
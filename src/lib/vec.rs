@@ -14,8 +14,7 @@ impl Vec3 {
         Vec3 { x, y, z }
     }
 
-    pub fn random_range(min: f64, max: f64) -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn random_range<R: Rng + ?Sized>(rng: &mut R, min: f64, max: f64) -> Self {
         Vec3 {
             x: rng.gen_range(min..max),
             y: rng.gen_range(min..max),
@@ -23,21 +22,20 @@ impl Vec3 {
         }
     }
 
-    pub fn random() -> Self {
-        Vec3::random_range(0.0, 1.0)
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Vec3::random_range(rng, 0.0, 1.0)
     }
 
-    pub fn random_in_unit_sphere() -> Self {
+    pub fn random_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> Self {
         loop {
-            let vec = Vec3::random_range(-1.0, 1.0);
+            let vec = Vec3::random_range(rng, -1.0, 1.0);
             if vec.length_squared() < 1.0 {
                 return vec;
             }
         }
     }
 
-    pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
+    pub fn random_in_unit_disk<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         loop {
             let vec = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
             if vec.length_squared() < 1.0 {
@@ -46,8 +44,20 @@ impl Vec3 {
         }
     }
 
-    pub fn random_unit_vector() -> Self {
-        Vec3::unit_vector(Vec3::random_in_unit_sphere())
+    pub fn random_unit_vector<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Vec3::unit_vector(Vec3::random_in_unit_sphere(rng))
+    }
+
+    /// Direction in the upper hemisphere around +z, with density cos(theta)/pi.
+    pub fn random_cosine_direction<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        Vec3::new(
+            phi.cos() * r2.sqrt(),
+            phi.sin() * r2.sqrt(),
+            (1.0 - r2).sqrt(),
+        )
     }
 
     pub fn dot(a: &Vec3, b: &Vec3) -> f64 {